@@ -0,0 +1,51 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::implement_id;
+
+use super::{api_token, player, session};
+
+implement_id!(AccountId);
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "account")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: AccountId,
+    #[sea_orm(unique)]
+    pub username: String,
+    /// Argon2 hash of the account's password in PHC string format.
+    #[serde(skip)]
+    pub password_hash: String,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "player::Entity")]
+    Player,
+    #[sea_orm(has_many = "session::Entity")]
+    Session,
+    #[sea_orm(has_many = "api_token::Entity")]
+    ApiToken,
+}
+
+impl Related<player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl Related<session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Session.def()
+    }
+}
+
+impl Related<api_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiToken.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,65 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{account::AccountId, implement_id};
+
+implement_id!(ApiTokenId);
+
+/// What an API token may be used for.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Everything the account can do.
+    #[default]
+    #[sea_orm(string_value = "full")]
+    Full,
+    /// Only requests that do not change anything.
+    #[sea_orm(string_value = "read_only")]
+    ReadOnly,
+    /// Only the task endpoints.
+    #[sea_orm(string_value = "tasks")]
+    Tasks,
+    /// Only the habit endpoints.
+    #[sea_orm(string_value = "habits")]
+    Habits,
+}
+
+/// A personal access token of an account, used by scripts through `Authorization: Bearer`.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "api_token")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ApiTokenId,
+    pub account_id: AccountId,
+    pub name: String,
+    /// SHA-256 hash of the token. The token itself is only shown when it is created.
+    #[sea_orm(unique)]
+    #[serde(skip)]
+    pub token_hash: String,
+    pub scope: TokenScope,
+    pub created_at: DateTimeUtc,
+    pub last_used_at: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::account::Entity",
+        from = "Column::AccountId",
+        to = "super::account::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Account,
+}
+
+impl Related<super::account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Account.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,79 @@
+use chrono::{Datelike, Months, NaiveDate, Weekday};
+use sea_orm::{entity::prelude::*, FromJsonQueryResult};
+use serde::{Deserialize, Serialize};
+
+use crate::{implement_id, player::PlayerId};
+
+implement_id!(DailyId);
+
+/// The days on which a daily is due.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Due on each of the given weekdays.
+    Weekly { weekdays: Vec<Weekday> },
+    /// Due every `interval` days, counting from the daily's start date.
+    EveryNDays { interval: u32 },
+    /// Due on the given day of every month.
+    /// In months that are too short, it is due on the last day of the month instead.
+    Monthly { day: u32 },
+}
+
+impl Schedule {
+    /// Whether a daily with this schedule that started on `start_date` is due on `date`.
+    pub fn is_due(&self, start_date: NaiveDate, date: NaiveDate) -> bool {
+        if date < start_date {
+            return false;
+        }
+        match self {
+            Schedule::Weekly { weekdays } => weekdays.contains(&date.weekday()),
+            Schedule::EveryNDays { interval } => {
+                *interval > 0 && (date - start_date).num_days() % i64::from(*interval) == 0
+            }
+            Schedule::Monthly { day } => date.day() == (*day).min(last_day_of_month(date)),
+        }
+    }
+}
+
+fn last_day_of_month(date: NaiveDate) -> u32 {
+    let first_of_month = date.with_day(1).unwrap();
+    let first_of_next_month = first_of_month + Months::new(1);
+    first_of_next_month.pred_opt().unwrap().day()
+}
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "daily")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: DailyId,
+    pub player_id: PlayerId,
+    pub name: String,
+    pub description: String,
+    #[sea_orm(column_type = "Json")]
+    pub schedule: Schedule,
+    pub start_date: Date,
+    /// Whether the daily has been completed in the player's current day.
+    pub completed: bool,
+    /// Number of consecutive due days on which the daily was completed.
+    pub streak: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::PlayerId",
+        to = "super::player::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Player,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
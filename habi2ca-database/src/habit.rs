@@ -15,6 +15,10 @@ pub struct Model {
     pub player_id: PlayerId,
     pub name: String,
     pub description: String,
+    /// Whether the habit can be incremented to gain xp.
+    pub positive: bool,
+    /// Whether the habit can be decremented, damaging the player.
+    pub negative: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -33,4 +33,9 @@ impl LevelId {
     pub fn next_level(self) -> Self {
         LevelId(self.0 + 1)
     }
+
+    /// Returns the level below this one, or `None` if this is the first level.
+    pub fn previous_level(self) -> Option<Self> {
+        (self.0 > 1).then(|| LevelId(self.0 - 1))
+    }
 }
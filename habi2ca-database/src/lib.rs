@@ -2,11 +2,18 @@
 
 pub mod prelude;
 
+pub mod account;
+pub mod api_token;
+pub mod daily;
 pub mod habit;
 pub mod level;
 pub mod migration;
+pub mod party;
+pub mod party_member;
 pub mod player;
+pub mod session;
 pub mod task;
+pub mod xp_event;
 
 #[macro_export]
 macro_rules! implement_id {
@@ -40,5 +47,11 @@ macro_rules! implement_id {
                 Ok(Self(n as i64))
             }
         }
+
+        impl sea_orm::sea_query::Nullable for $name {
+            fn null() -> sea_orm::Value {
+                sea_orm::Value::BigInt(None)
+            }
+        }
     };
 }
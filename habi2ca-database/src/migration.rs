@@ -2,11 +2,30 @@ pub use sea_orm_migration::MigratorTrait;
 use sea_orm_migration::{async_trait::async_trait, MigrationTrait};
 
 mod m20240727_133538_initial;
+mod m20261018_120000_cascade_player_delete;
+mod m20261018_130000_habit_direction_and_health;
+mod m20261018_140000_dailies;
+mod m20261018_150000_xp_ledger;
+mod m20261018_160000_accounts;
+mod m20261018_170000_api_tokens;
+mod m20261018_180000_parties;
+#[cfg(test)]
+mod tests;
+
 pub struct Migrator;
 
 #[async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20240727_133538_initial::Migration)]
+        vec![
+            Box::new(m20240727_133538_initial::Migration),
+            Box::new(m20261018_120000_cascade_player_delete::Migration),
+            Box::new(m20261018_130000_habit_direction_and_health::Migration),
+            Box::new(m20261018_140000_dailies::Migration),
+            Box::new(m20261018_150000_xp_ledger::Migration),
+            Box::new(m20261018_160000_accounts::Migration),
+            Box::new(m20261018_170000_api_tokens::Migration),
+            Box::new(m20261018_180000_parties::Migration),
+        ]
     }
 }
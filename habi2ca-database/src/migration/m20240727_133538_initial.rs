@@ -1,5 +1,8 @@
 use sea_orm_migration::prelude::*;
 
+/// IDs are big integers and XP requirements are doubles to match the `i64` and `f64` fields of the
+/// entities, since PostgreSQL does not convert between them when reading values. SQLite databases
+/// created before this declare them as `integer` and `float`, which SQLite stores the same way.
 #[derive(DeriveMigrationName)]
 pub struct Migration;
 
@@ -8,7 +11,7 @@ fn player_table() -> TableCreateStatement {
         .table(Player::Table)
         .col(
             ColumnDef::new(Player::Id)
-                .integer()
+                .big_integer()
                 .not_null()
                 .auto_increment()
                 .primary_key(),
@@ -16,13 +19,13 @@ fn player_table() -> TableCreateStatement {
         .col(ColumnDef::new(Player::Name).string().not_null())
         .col(
             ColumnDef::new(Player::Xp)
-                .float()
+                .double()
                 .not_null()
                 .check(Expr::col(Player::Xp).gte(0.0)),
         )
         .col(
             ColumnDef::new(Player::LevelId)
-                .integer()
+                .big_integer()
                 .not_null()
                 .check(Expr::col(Player::LevelId).gte(1)),
         )
@@ -34,12 +37,12 @@ fn task_table() -> TableCreateStatement {
         .table(Task::Table)
         .col(
             ColumnDef::new(Task::Id)
-                .integer()
+                .big_integer()
                 .not_null()
                 .auto_increment()
                 .primary_key(),
         )
-        .col(ColumnDef::new(Task::PlayerId).integer().not_null())
+        .col(ColumnDef::new(Task::PlayerId).big_integer().not_null())
         .foreign_key(
             ForeignKey::create()
                 .name("fk_player_id")
@@ -57,12 +60,12 @@ fn habit_table() -> TableCreateStatement {
         .table(Habit::Table)
         .col(
             ColumnDef::new(Habit::Id)
-                .integer()
+                .big_integer()
                 .not_null()
                 .auto_increment()
                 .primary_key(),
         )
-        .col(ColumnDef::new(Habit::PlayerId).integer().not_null())
+        .col(ColumnDef::new(Habit::PlayerId).big_integer().not_null())
         .foreign_key(
             ForeignKey::create()
                 .name("fk_player_id")
@@ -80,7 +83,7 @@ fn level_table() -> TableCreateStatement {
         .if_not_exists()
         .col(
             ColumnDef::new(Level::Id)
-                .integer()
+                .big_integer()
                 .not_null()
                 .auto_increment()
                 .primary_key()
@@ -88,7 +91,7 @@ fn level_table() -> TableCreateStatement {
         )
         .col(
             ColumnDef::new(Level::XpRequirement)
-                .float()
+                .double()
                 .not_null()
                 .check(Expr::col(Level::XpRequirement).gt(0.0)),
         )
@@ -0,0 +1,188 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+/// The initial migration created the `player_id` foreign keys of `task` and `habit` without
+/// `ON DELETE CASCADE`. SQLite cannot alter constraints, so both tables are rebuilt there.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn task_table(table: impl IntoIden, on_delete: ForeignKeyAction) -> TableCreateStatement {
+    let table = table.into_iden();
+    Table::create()
+        .table(table.clone())
+        .col(
+            ColumnDef::new(Task::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(Task::PlayerId).big_integer().not_null())
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_player_id")
+                .from(table, Task::PlayerId)
+                .to(Player::Table, Player::Id)
+                .on_delete(on_delete),
+        )
+        .col(ColumnDef::new(Task::Name).string().not_null())
+        .col(ColumnDef::new(Task::Description).string().not_null())
+        .col(ColumnDef::new(Task::Completed).boolean().not_null())
+        .to_owned()
+}
+
+fn habit_table(table: impl IntoIden, on_delete: ForeignKeyAction) -> TableCreateStatement {
+    let table = table.into_iden();
+    Table::create()
+        .table(table.clone())
+        .col(
+            ColumnDef::new(Habit::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(Habit::PlayerId).big_integer().not_null())
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_player_id")
+                .from(table, Habit::PlayerId)
+                .to(Player::Table, Player::Id)
+                .on_delete(on_delete),
+        )
+        .col(ColumnDef::new(Habit::Name).string().not_null())
+        .col(ColumnDef::new(Habit::Description).string().not_null())
+        .to_owned()
+}
+
+/// Replaces `table` with a table created by `create`, keeping all rows.
+async fn rebuild_table<const N: usize>(
+    manager: &SchemaManager<'_>,
+    table: impl IntoIden,
+    columns: [DynIden; N],
+    create: impl FnOnce(Alias) -> TableCreateStatement,
+) -> Result<(), DbErr> {
+    let table = table.into_iden();
+    let new_table = Alias::new(format!("{}_rebuild", table.to_string()));
+
+    manager.create_table(create(new_table.clone())).await?;
+    let copy = Query::insert()
+        .into_table(new_table.clone())
+        .columns(columns.clone())
+        .select_from(
+            Query::select()
+                .columns(columns)
+                .from(table.clone())
+                .to_owned(),
+        )
+        .map_err(|error| DbErr::Migration(error.to_string()))?
+        .to_owned();
+    manager.exec_stmt(copy).await?;
+    manager
+        .drop_table(Table::drop().table(table.clone()).to_owned())
+        .await?;
+    manager
+        .rename_table(Table::rename().table(new_table, table).to_owned())
+        .await
+}
+
+fn task_columns() -> [DynIden; 5] {
+    [
+        Task::Id.into_iden(),
+        Task::PlayerId.into_iden(),
+        Task::Name.into_iden(),
+        Task::Description.into_iden(),
+        Task::Completed.into_iden(),
+    ]
+}
+
+fn habit_columns() -> [DynIden; 4] {
+    [
+        Habit::Id.into_iden(),
+        Habit::PlayerId.into_iden(),
+        Habit::Name.into_iden(),
+        Habit::Description.into_iden(),
+    ]
+}
+
+/// Replaces the `player_id` foreign key of `table`. Not supported by SQLite.
+async fn replace_foreign_key(
+    manager: &SchemaManager<'_>,
+    table: impl IntoIden,
+    column: impl IntoIden,
+    on_delete: ForeignKeyAction,
+) -> Result<(), DbErr> {
+    let table = table.into_iden();
+    manager
+        .alter_table(
+            Table::alter()
+                .table(table.clone())
+                .drop_foreign_key(Alias::new("fk_player_id"))
+                .add_foreign_key(
+                    TableForeignKey::new()
+                        .name("fk_player_id")
+                        .from_tbl(table)
+                        .from_col(column)
+                        .to_tbl(Player::Table)
+                        .to_col(Player::Id)
+                        .on_delete(on_delete),
+                )
+                .to_owned(),
+        )
+        .await
+}
+
+/// Sets what happens to the tasks and habits of a player when the player is deleted.
+async fn set_on_delete(
+    manager: &SchemaManager<'_>,
+    on_delete: ForeignKeyAction,
+) -> Result<(), DbErr> {
+    if manager.get_database_backend() != DbBackend::Sqlite {
+        replace_foreign_key(manager, Task::Table, Task::PlayerId, on_delete).await?;
+        return replace_foreign_key(manager, Habit::Table, Habit::PlayerId, on_delete).await;
+    }
+    rebuild_table(manager, Task::Table, task_columns(), |table| {
+        task_table(table, on_delete)
+    })
+    .await?;
+    rebuild_table(manager, Habit::Table, habit_columns(), |table| {
+        habit_table(table, on_delete)
+    })
+    .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        set_on_delete(manager, ForeignKeyAction::Cascade).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        set_on_delete(manager, ForeignKeyAction::NoAction).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Player {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Task {
+    Table,
+    Id,
+    PlayerId,
+    Name,
+    Description,
+    Completed,
+}
+
+#[derive(DeriveIden)]
+enum Habit {
+    Table,
+    Id,
+    PlayerId,
+    Name,
+    Description,
+}
@@ -0,0 +1,95 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const MAX_HEALTH: f64 = 50.0;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only supports a single change per `ALTER TABLE` statement.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Habit::Table)
+                    .add_column(
+                        ColumnDef::new(Habit::Positive)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Habit::Table)
+                    .add_column(
+                        ColumnDef::new(Habit::Negative)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Player::Table)
+                    .add_column(
+                        ColumnDef::new(Player::Health)
+                            .double()
+                            .not_null()
+                            .default(MAX_HEALTH)
+                            .check(Expr::col(Player::Health).gte(0.0)),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Player::Table)
+                    .drop_column(Player::Health)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Habit::Table)
+                    .drop_column(Habit::Negative)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Habit::Table)
+                    .drop_column(Habit::Positive)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Player {
+    Table,
+    Health,
+}
+
+#[derive(DeriveIden)]
+enum Habit {
+    Table,
+    Positive,
+    Negative,
+}
@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn daily_table() -> TableCreateStatement {
+    Table::create()
+        .table(Daily::Table)
+        .col(
+            ColumnDef::new(Daily::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(Daily::PlayerId).big_integer().not_null())
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_player_id")
+                .from(Daily::Table, Daily::PlayerId)
+                .to(Player::Table, Player::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .col(ColumnDef::new(Daily::Name).string().not_null())
+        .col(ColumnDef::new(Daily::Description).string().not_null())
+        .col(ColumnDef::new(Daily::Schedule).json().not_null())
+        .col(ColumnDef::new(Daily::StartDate).date().not_null())
+        .col(ColumnDef::new(Daily::Completed).boolean().not_null())
+        .col(
+            ColumnDef::new(Daily::Streak)
+                .big_integer()
+                .not_null()
+                .default(0)
+                .check(Expr::col(Daily::Streak).gte(0)),
+        )
+        .to_owned()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(daily_table()).await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Player::Table)
+                    .add_column(ColumnDef::new(Player::LastRollover).date().null())
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Player::Table)
+                    .drop_column(Player::LastRollover)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Daily::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Player {
+    Table,
+    Id,
+    LastRollover,
+}
+
+#[derive(DeriveIden)]
+enum Daily {
+    Table,
+    Id,
+    PlayerId,
+    Name,
+    Description,
+    Schedule,
+    StartDate,
+    Completed,
+    Streak,
+}
@@ -0,0 +1,125 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn xp_event_table() -> TableCreateStatement {
+    Table::create()
+        .table(XpEvent::Table)
+        .col(
+            ColumnDef::new(XpEvent::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(XpEvent::PlayerId).big_integer().not_null())
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_player_id")
+                .from(XpEvent::Table, XpEvent::PlayerId)
+                .to(Player::Table, Player::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .col(ColumnDef::new(XpEvent::Amount).double().not_null())
+        .col(ColumnDef::new(XpEvent::SourceKind).string().not_null())
+        .col(ColumnDef::new(XpEvent::SourceId).big_integer().null())
+        .col(
+            ColumnDef::new(XpEvent::CreatedAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .to_owned()
+}
+
+fn xp_event_index() -> IndexCreateStatement {
+    Index::create()
+        .name("idx_xp_event_player_id_created_at")
+        .table(XpEvent::Table)
+        .col(XpEvent::PlayerId)
+        .col(XpEvent::CreatedAt)
+        .to_owned()
+}
+
+/// Records the xp every existing player has earned so far as a single `initial` event,
+/// so that the sum of a player's events always matches their progress.
+async fn initial_events(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let earned_in_previous_levels = Query::select()
+        .expr(Func::coalesce([
+            Func::sum(Expr::col((Level::Table, Level::XpRequirement))).into(),
+            Expr::val(0.0).into(),
+        ]))
+        .from(Level::Table)
+        .and_where(
+            Expr::col((Level::Table, Level::Id)).lt(Expr::col((Player::Table, Player::LevelId))),
+        )
+        .to_owned();
+    let insert = Query::insert()
+        .into_table(XpEvent::Table)
+        .columns([
+            XpEvent::PlayerId,
+            XpEvent::Amount,
+            XpEvent::SourceKind,
+            XpEvent::CreatedAt,
+        ])
+        .select_from(
+            Query::select()
+                .column((Player::Table, Player::Id))
+                .expr(
+                    Expr::col((Player::Table, Player::Xp)).add(SimpleExpr::SubQuery(
+                        None,
+                        Box::new(earned_in_previous_levels.into_sub_query_statement()),
+                    )),
+                )
+                .expr(Expr::val("initial"))
+                .expr(Expr::val(chrono::Utc::now()))
+                .from(Player::Table)
+                .to_owned(),
+        )
+        .map_err(|error| DbErr::Migration(error.to_string()))?
+        .to_owned();
+    manager.exec_stmt(insert).await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(xp_event_table()).await?;
+        manager.create_index(xp_event_index()).await?;
+        initial_events(manager).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(XpEvent::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Player {
+    Table,
+    Id,
+    Xp,
+    LevelId,
+}
+
+#[derive(DeriveIden)]
+enum Level {
+    Table,
+    Id,
+    XpRequirement,
+}
+
+#[derive(DeriveIden)]
+enum XpEvent {
+    Table,
+    Id,
+    PlayerId,
+    Amount,
+    SourceKind,
+    SourceId,
+    CreatedAt,
+}
@@ -0,0 +1,127 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn account_table() -> TableCreateStatement {
+    Table::create()
+        .table(Account::Table)
+        .col(
+            ColumnDef::new(Account::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(
+            ColumnDef::new(Account::Username)
+                .string()
+                .not_null()
+                .unique_key(),
+        )
+        .col(ColumnDef::new(Account::PasswordHash).string().not_null())
+        .col(
+            ColumnDef::new(Account::CreatedAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .to_owned()
+}
+
+fn session_table() -> TableCreateStatement {
+    Table::create()
+        .table(Session::Table)
+        .col(
+            ColumnDef::new(Session::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(Session::AccountId).big_integer().not_null())
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_account_id")
+                .from(Session::Table, Session::AccountId)
+                .to(Account::Table, Account::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .col(
+            ColumnDef::new(Session::TokenHash)
+                .string()
+                .not_null()
+                .unique_key(),
+        )
+        .col(
+            ColumnDef::new(Session::CreatedAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(Session::ExpiresAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .to_owned()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(account_table()).await?;
+        manager.create_table(session_table()).await?;
+        // SQLite cannot add foreign keys through `ALTER TABLE ... ADD CONSTRAINT`, but it does
+        // support references on added columns, which sea-query cannot express. PostgreSQL supports
+        // the same statement.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"ALTER TABLE "player" ADD COLUMN "account_id" bigint NULL REFERENCES "account" ("id") ON DELETE CASCADE"#,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Player::Table)
+                    .drop_column(Player::AccountId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Session::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Account::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Player {
+    Table,
+    AccountId,
+}
+
+#[derive(DeriveIden)]
+enum Account {
+    Table,
+    Id,
+    Username,
+    PasswordHash,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Session {
+    Table,
+    Id,
+    AccountId,
+    TokenHash,
+    CreatedAt,
+    ExpiresAt,
+}
@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn api_token_table() -> TableCreateStatement {
+    Table::create()
+        .table(ApiToken::Table)
+        .col(
+            ColumnDef::new(ApiToken::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(ApiToken::AccountId).big_integer().not_null())
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_account_id")
+                .from(ApiToken::Table, ApiToken::AccountId)
+                .to(Account::Table, Account::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .col(ColumnDef::new(ApiToken::Name).string().not_null())
+        .col(
+            ColumnDef::new(ApiToken::TokenHash)
+                .string()
+                .not_null()
+                .unique_key(),
+        )
+        .col(ColumnDef::new(ApiToken::Scope).string().not_null())
+        .col(
+            ColumnDef::new(ApiToken::CreatedAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(ApiToken::LastUsedAt)
+                .timestamp_with_time_zone()
+                .null(),
+        )
+        .to_owned()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(api_token_table()).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiToken::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Account {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ApiToken {
+    Table,
+    Id,
+    AccountId,
+    Name,
+    TokenHash,
+    Scope,
+    CreatedAt,
+    LastUsedAt,
+}
@@ -0,0 +1,107 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn party_table() -> TableCreateStatement {
+    Table::create()
+        .table(Party::Table)
+        .col(
+            ColumnDef::new(Party::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(ColumnDef::new(Party::Name).string().not_null())
+        .col(
+            ColumnDef::new(Party::CreatedAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .to_owned()
+}
+
+fn party_member_table() -> TableCreateStatement {
+    Table::create()
+        .table(PartyMember::Table)
+        .col(
+            ColumnDef::new(PartyMember::Id)
+                .big_integer()
+                .not_null()
+                .auto_increment()
+                .primary_key(),
+        )
+        .col(
+            ColumnDef::new(PartyMember::PartyId)
+                .big_integer()
+                .not_null(),
+        )
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_party_id")
+                .from(PartyMember::Table, PartyMember::PartyId)
+                .to(Party::Table, Party::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        // A player can be in at most one party.
+        .col(
+            ColumnDef::new(PartyMember::PlayerId)
+                .big_integer()
+                .not_null()
+                .unique_key(),
+        )
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_player_id")
+                .from(PartyMember::Table, PartyMember::PlayerId)
+                .to(Player::Table, Player::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .col(
+            ColumnDef::new(PartyMember::JoinedAt)
+                .timestamp_with_time_zone()
+                .not_null(),
+        )
+        .to_owned()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(party_table()).await?;
+        manager.create_table(party_member_table()).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PartyMember::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Party::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Player {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Party {
+    Table,
+    Id,
+    Name,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum PartyMember {
+    Table,
+    Id,
+    PartyId,
+    PlayerId,
+    JoinedAt,
+}
@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection, Statement};
+use sea_orm_migration::MigratorTrait;
+
+use super::Migrator;
+
+/// Old database fixture, created by the first released version of the server with its API.
+/// See `fixtures/README.md`.
+const INITIAL_FIXTURE: &[u8] = include_bytes!("../../fixtures/m20240727_133538_initial.db");
+
+/// The columns, foreign keys and indexes of a table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TableShape {
+    columns: Vec<String>,
+    foreign_keys: Vec<String>,
+    indexes: Vec<String>,
+}
+
+type Schema = BTreeMap<String, TableShape>;
+/// The values of every column of every table, in the order of the rows and formatted as SQL
+/// literals.
+type Rows = BTreeMap<String, BTreeMap<String, Vec<String>>>;
+
+async fn connect() -> DatabaseConnection {
+    Database::connect("sqlite::memory:")
+        .await
+        .expect("Failed to connect to database.")
+}
+
+async fn strings(database: &DatabaseConnection, sql: String) -> Vec<String> {
+    database
+        .query_all(Statement::from_string(database.get_database_backend(), sql))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.try_get_by_index::<String>(0).unwrap())
+        .collect()
+}
+
+async fn tables(database: &DatabaseConnection) -> Vec<String> {
+    strings(
+        database,
+        "SELECT name FROM sqlite_master \
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'seaql_migrations' \
+         ORDER BY name"
+            .to_owned(),
+    )
+    .await
+}
+
+/// Columns are described by the affinity of their type rather than the declared type, since
+/// databases created before the initial migration was made portable declare other types with the
+/// same affinity.
+async fn schema(database: &DatabaseConnection) -> Schema {
+    let mut schema = Schema::new();
+    for table in tables(database).await {
+        let columns = strings(
+            database,
+            format!(
+                "SELECT name || ' ' || \
+                 CASE \
+                     WHEN upper(type) LIKE '%INT%' THEN 'INTEGER' \
+                     WHEN upper(type) LIKE '%CHAR%' OR upper(type) LIKE '%CLOB%' \
+                         OR upper(type) LIKE '%TEXT%' THEN 'TEXT' \
+                     WHEN upper(type) LIKE '%BLOB%' OR type = '' THEN 'BLOB' \
+                     WHEN upper(type) LIKE '%REAL%' OR upper(type) LIKE '%FLOA%' \
+                         OR upper(type) LIKE '%DOUB%' THEN 'REAL' \
+                     ELSE 'NUMERIC' \
+                 END || \
+                 ' notnull=' || \"notnull\" || ' default=' || quote(dflt_value) || ' pk=' || pk \
+                 FROM pragma_table_info('{table}') ORDER BY cid"
+            ),
+        )
+        .await;
+        let foreign_keys = strings(
+            database,
+            format!(
+                "SELECT \"from\" || ' -> ' || \"table\" || '.' || \"to\" || \
+                 ' on delete ' || on_delete \
+                 FROM pragma_foreign_key_list('{table}') ORDER BY 1"
+            ),
+        )
+        .await;
+        // Named by their columns, since automatic index names depend on the order of creation.
+        let indexes = strings(
+            database,
+            format!(
+                "SELECT (SELECT group_concat(name) FROM pragma_index_info(list.name)) || \
+                 ' unique=' || list.\"unique\" \
+                 FROM pragma_index_list('{table}') AS list ORDER BY 1"
+            ),
+        )
+        .await;
+        schema.insert(
+            table,
+            TableShape {
+                columns,
+                foreign_keys,
+                indexes,
+            },
+        );
+    }
+    schema
+}
+
+async fn rows(database: &DatabaseConnection) -> Rows {
+    let mut rows = Rows::new();
+    for table in tables(database).await {
+        let columns = strings(
+            database,
+            format!("SELECT name FROM pragma_table_info('{table}') ORDER BY cid"),
+        )
+        .await;
+        let mut table_rows = BTreeMap::new();
+        for column in columns {
+            let values = strings(
+                database,
+                format!("SELECT quote(\"{column}\") FROM \"{table}\" ORDER BY rowid"),
+            )
+            .await;
+            table_rows.insert(column, values);
+        }
+        rows.insert(table, table_rows);
+    }
+    rows
+}
+
+fn row_count(columns: &BTreeMap<String, Vec<String>>) -> usize {
+    columns.values().next().map_or(0, Vec::len)
+}
+
+/// Checks that the rows of `before` are still the first rows of the tables in `after`, comparing the
+/// columns that exist on both sides. Tables that no longer exist are skipped.
+fn assert_rows_kept(before: &Rows, after: &Rows, context: &str) {
+    for (table, columns) in before {
+        let Some(columns_after) = after.get(table) else {
+            continue;
+        };
+        for (column, values) in columns {
+            if let Some(values_after) = columns_after.get(column) {
+                assert!(
+                    values_after.starts_with(values),
+                    "{context}: values of '{table}.{column}' were lost or changed: {values:?} became \
+                     {values_after:?}."
+                );
+            }
+        }
+    }
+}
+
+/// Inserts a row into every table, with values based on the declared type of each column.
+/// Tables are filled after the tables they refer to, and every foreign key refers to the row with
+/// id 1, so a row must already exist in every referenced table.
+async fn seed(database: &DatabaseConnection, seed: usize) {
+    let mut remaining = tables(database).await;
+    let mut seeded: Vec<String> = Vec::new();
+    while !remaining.is_empty() {
+        let mut progress = false;
+        for table in remaining.clone() {
+            let parents = strings(
+                database,
+                format!("SELECT \"table\" FROM pragma_foreign_key_list('{table}')"),
+            )
+            .await;
+            if !parents
+                .iter()
+                .all(|parent| parent == &table || seeded.contains(parent))
+            {
+                continue;
+            }
+
+            let columns = database
+                .query_all(Statement::from_string(
+                    database.get_database_backend(),
+                    format!("SELECT name, upper(type), pk FROM pragma_table_info('{table}')"),
+                ))
+                .await
+                .unwrap();
+            let (names, values): (Vec<String>, Vec<String>) = columns
+                .into_iter()
+                .filter_map(|column| {
+                    let name: String = column.try_get_by_index(0).unwrap();
+                    let column_type: String = column.try_get_by_index(1).unwrap();
+                    let primary_key: i64 = column.try_get_by_index(2).unwrap();
+                    if primary_key > 0 {
+                        // Primary keys are assigned by the database.
+                        return None;
+                    }
+                    let value = if column_type.contains("INT") || column_type.contains("BOOL") {
+                        "1".to_owned()
+                    } else if column_type.contains("REAL")
+                        || column_type.contains("FLOA")
+                        || column_type.contains("DOUB")
+                    {
+                        "1.5".to_owned()
+                    } else {
+                        format!("'{table}.{name}.{seed}'")
+                    };
+                    Some((format!("\"{name}\""), value))
+                })
+                .unzip();
+            database
+                .execute_unprepared(&format!(
+                    "INSERT INTO \"{table}\" ({}) VALUES ({})",
+                    names.join(", "),
+                    values.join(", ")
+                ))
+                .await
+                .unwrap_or_else(|error| panic!("Failed to seed table '{table}': {error}"));
+
+            remaining.retain(|remaining| remaining != &table);
+            seeded.push(table);
+            progress = true;
+        }
+        assert!(progress, "Tables {remaining:?} refer to each other.");
+    }
+}
+
+/// For every migration, checks that applying and reverting it keep the values of rows that existed
+/// before it, that reverting it restores the previous schema, and that reapplying it gives the same
+/// schema as applying it the first time.
+#[tokio::test]
+async fn up_down_round_trips() {
+    let migrations = Migrator::migrations();
+    for (index, migration) in migrations.iter().enumerate() {
+        let name = migration.name();
+        let database = connect().await;
+        Migrator::up(&database, Some(index as u32)).await.unwrap();
+        seed(&database, 0).await;
+        let schema_before = schema(&database).await;
+        let rows_before = rows(&database).await;
+
+        Migrator::up(&database, Some(1)).await.unwrap();
+        seed(&database, 1).await;
+        let schema_after = schema(&database).await;
+        let rows_after = rows(&database).await;
+        assert_rows_kept(&rows_before, &rows_after, &format!("{name}: up"));
+
+        Migrator::down(&database, Some(1))
+            .await
+            .unwrap_or_else(|error| panic!("{name}: down failed: {error}"));
+        assert_eq!(
+            schema(&database).await,
+            schema_before,
+            "{name}: down did not restore the schema."
+        );
+        let rows_reverted = rows(&database).await;
+        assert_rows_kept(&rows_before, &rows_reverted, &format!("{name}: down"));
+
+        Migrator::up(&database, Some(1)).await.unwrap();
+        assert_eq!(
+            schema(&database).await,
+            schema_after,
+            "{name}: reapplying changed the schema."
+        );
+        let rows_reapplied = rows(&database).await;
+        assert_rows_kept(
+            &rows_reverted,
+            &rows_reapplied,
+            &format!("{name}: reapplying"),
+        );
+        for (table, columns) in &rows_reverted {
+            if let Some(columns_reapplied) = rows_reapplied.get(table) {
+                assert_eq!(
+                    row_count(columns_reapplied),
+                    row_count(columns),
+                    "{name}: rows of '{table}' were added by reapplying."
+                );
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn upgrade_initial_fixture() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("data.db");
+    std::fs::write(&path, INITIAL_FIXTURE).unwrap();
+    let database = Database::connect(format!("sqlite:{}?mode=rw", path.display()))
+        .await
+        .unwrap();
+    let rows_before = rows(&database).await;
+
+    Migrator::up(&database, None).await.unwrap();
+    assert!(Migrator::get_pending_migrations(&database)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let fresh = connect().await;
+    Migrator::up(&fresh, None).await.unwrap();
+    assert_eq!(schema(&database).await, schema(&fresh).await);
+
+    let rows_after = rows(&database).await;
+    for table in ["player", "task", "habit"] {
+        assert!(row_count(&rows_before[table]) > 0);
+        assert_eq!(
+            row_count(&rows_after[table]),
+            row_count(&rows_before[table]),
+            "Rows of '{table}' were lost."
+        );
+    }
+    assert_rows_kept(&rows_before, &rows_after, "Upgrading the initial fixture");
+}
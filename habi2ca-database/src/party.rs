@@ -0,0 +1,32 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::implement_id;
+
+use super::party_member;
+
+implement_id!(PartyId);
+
+/// A group of players, possibly of different accounts, who follow each other's progress.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "party")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: PartyId,
+    pub name: String,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "party_member::Entity")]
+    PartyMember,
+}
+
+impl Related<party_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PartyMember.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
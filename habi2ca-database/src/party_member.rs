@@ -0,0 +1,54 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{implement_id, party::PartyId, player::PlayerId};
+
+use super::{party, player};
+
+implement_id!(PartyMemberId);
+
+/// The membership of a player in a party. A player is in at most one party.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "party_member")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: PartyMemberId,
+    pub party_id: PartyId,
+    #[sea_orm(unique)]
+    pub player_id: PlayerId,
+    pub joined_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "party::Entity",
+        from = "Column::PartyId",
+        to = "party::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Party,
+    #[sea_orm(
+        belongs_to = "player::Entity",
+        from = "Column::PlayerId",
+        to = "player::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Player,
+}
+
+impl Related<party::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Party.def()
+    }
+}
+
+impl Related<player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -2,9 +2,9 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::{implement_id, level::LevelId};
+use crate::{account::AccountId, implement_id, level::LevelId};
 
-use super::{level, task};
+use super::{account, daily, habit, level, task, xp_event};
 
 implement_id!(PlayerId);
 
@@ -19,18 +19,38 @@ pub struct Model {
     #[sea_orm(default = "1")]
     #[serde(rename = "level")]
     pub level_id: LevelId,
+    #[sea_orm(column_type = "Double")]
+    pub health: f64,
+    /// The day the player's dailies were last rolled over to.
+    pub last_rollover: Option<Date>,
+    /// The account that owns the player. Players created before accounts existed have no owner.
+    pub account_id: Option<AccountId>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "task::Entity")]
     Task,
+    #[sea_orm(has_many = "habit::Entity")]
+    Habit,
+    #[sea_orm(has_many = "daily::Entity")]
+    Daily,
+    #[sea_orm(has_many = "xp_event::Entity")]
+    XpEvent,
     #[sea_orm(
         belongs_to = "level::Entity",
         from = "Column::LevelId",
         to = "level::Column::Id"
     )]
     Level,
+    #[sea_orm(
+        belongs_to = "account::Entity",
+        from = "Column::AccountId",
+        to = "account::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Account,
 }
 
 impl Related<task::Entity> for Entity {
@@ -39,10 +59,34 @@ impl Related<task::Entity> for Entity {
     }
 }
 
+impl Related<habit::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Habit.def()
+    }
+}
+
+impl Related<daily::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Daily.def()
+    }
+}
+
+impl Related<xp_event::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::XpEvent.def()
+    }
+}
+
 impl Related<level::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Level.def()
     }
 }
 
+impl Related<account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Account.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,64 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{implement_id, player::PlayerId};
+
+implement_id!(XpEventId);
+
+/// What caused a change in a player's xp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "snake_case")]
+pub enum XpSourceKind {
+    /// The xp a player had when the ledger was introduced.
+    #[sea_orm(string_value = "initial")]
+    Initial,
+    #[sea_orm(string_value = "task")]
+    Task,
+    #[sea_orm(string_value = "habit")]
+    Habit,
+    #[sea_orm(string_value = "daily")]
+    Daily,
+    /// Xp added directly through the API.
+    #[sea_orm(string_value = "manual")]
+    Manual,
+    /// Xp lost when the player ran out of health.
+    #[sea_orm(string_value = "death")]
+    Death,
+}
+
+/// A single change in a player's xp.
+/// The sum of all of a player's events is the total xp they have earned across all levels.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "xp_event")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: XpEventId,
+    pub player_id: PlayerId,
+    #[sea_orm(column_type = "Double")]
+    pub amount: f64,
+    pub source_kind: XpSourceKind,
+    /// Id of the task, habit or daily that caused the change, if any.
+    pub source_id: Option<i64>,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::player::Entity",
+        from = "Column::PlayerId",
+        to = "super::player::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Player,
+}
+
+impl Related<super::player::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Player.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
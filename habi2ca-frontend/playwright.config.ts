@@ -1,10 +1,20 @@
 import type { PlaywrightTestConfig } from '@playwright/test';
 
 const config: PlaywrightTestConfig = {
-	webServer: {
-		command: 'npm run build && npm run preview',
-		port: 4173
-	},
+	webServer: [
+		{
+			// The backend the pages are loaded from, with a database that is kept between runs.
+			command: 'cargo run -p habi2ca-server -- --database-path target/playwright/data.db',
+			cwd: '..',
+			url: 'http://localhost:8080/api/health',
+			reuseExistingServer: !process.env.CI,
+			timeout: 600_000
+		},
+		{
+			command: 'npm run build && npm run preview',
+			port: 4173
+		}
+	],
 	testDir: 'tests',
 	testMatch: /(.+\.)?(test|spec)\.[jt]s/
 };
@@ -0,0 +1,27 @@
+import type { Handle, HandleFetch } from '@sveltejs/kit';
+import { BACKEND_ORIGIN, SESSION_COOKIE } from '$lib/base';
+
+/** Pages that can be visited without logging in. */
+const PUBLIC_PAGES = ['/login', '/register'];
+
+export const handle: Handle = async ({ event, resolve }) => {
+	const path = event.url.pathname;
+	// The API answers requests without a session itself.
+	const isPublic = PUBLIC_PAGES.includes(path) || path.startsWith('/api/');
+	if (!isPublic && event.cookies.get(SESSION_COOKIE) === undefined) {
+		return new Response(null, { status: 303, headers: { location: '/login' } });
+	}
+	return resolve(event);
+};
+
+/**
+ * Forwards the session cookie to the backend. SvelteKit only does so for requests to its own
+ * origin, and the backend runs on another one.
+ */
+export const handleFetch: HandleFetch = async ({ event, request, fetch }) => {
+	const session = event.cookies.get(SESSION_COOKIE);
+	if (new URL(request.url).origin === BACKEND_ORIGIN.origin && session !== undefined) {
+		request.headers.set('cookie', `${SESSION_COOKIE}=${session}`);
+	}
+	return fetch(request);
+};
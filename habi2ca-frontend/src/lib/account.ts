@@ -0,0 +1,77 @@
+import { SESSION_COOKIE, type Fetch } from './base';
+
+export type Credentials = {
+	username: string;
+	password: string;
+};
+
+/** A session token and the number of seconds until it expires. */
+export type Session = {
+	token: string;
+	maxAge: number | undefined;
+};
+
+export async function register(
+	origin: URL,
+	credentials: Credentials,
+	fetchFn: Fetch = fetch
+): Promise<void> {
+	const registerUrl = `${origin}api/auth/register`;
+	const response = await fetchFn(registerUrl, {
+		method: 'POST',
+		body: JSON.stringify(credentials),
+		headers: { 'Content-Type': 'application/json' }
+	});
+	if (!response.ok) {
+		throw new Error(await problemDetail(response, 'Failed to register'));
+	}
+}
+
+/** Logs in and returns the session the backend set as cookie. */
+export async function login(
+	origin: URL,
+	credentials: Credentials,
+	fetchFn: Fetch = fetch
+): Promise<Session> {
+	const loginUrl = `${origin}api/auth/login`;
+	const response = await fetchFn(loginUrl, {
+		method: 'POST',
+		body: JSON.stringify(credentials),
+		headers: { 'Content-Type': 'application/json' }
+	});
+	if (!response.ok) {
+		throw new Error(await problemDetail(response, 'Failed to log in'));
+	}
+	for (const cookie of response.headers.getSetCookie()) {
+		const [pair, ...attributes] = cookie.split(';').map((part) => part.trim());
+		if (!pair.startsWith(`${SESSION_COOKIE}=`)) {
+			continue;
+		}
+		const maxAge = attributes
+			.find((attribute) => attribute.toLowerCase().startsWith('max-age='))
+			?.split('=')[1];
+		return {
+			token: pair.slice(SESSION_COOKIE.length + 1),
+			maxAge: maxAge === undefined ? undefined : parseInt(maxAge)
+		};
+	}
+	throw new Error('Failed to log in: no session was started.');
+}
+
+export async function logout(origin: URL, fetchFn: Fetch = fetch): Promise<void> {
+	const logoutUrl = `${origin}api/auth/logout`;
+	const response = await fetchFn(logoutUrl, { method: 'POST' });
+	if (!response.ok) {
+		throw new Error(`Failed to log out. ${response.status}: ${await response.text()}`);
+	}
+}
+
+/** The explanation of a failed request, taken from the problem details the backend returns. */
+async function problemDetail(response: Response, errorMessage: string): Promise<string> {
+	const text = await response.text();
+	try {
+		return `${errorMessage}: ${JSON.parse(text).detail}`;
+	} catch {
+		return `${errorMessage}. ${response.status}: ${text}`;
+	}
+}
@@ -1,10 +1,33 @@
 import { onMount } from 'svelte';
-import { error } from '@sveltejs/kit';
+import { error, redirect } from '@sveltejs/kit';
 import { type Subscriber, type Unsubscriber } from 'svelte/store';
+import { browser } from '$app/environment';
 import { env } from '$env/dynamic/public';
 
 export const BACKEND_ORIGIN: URL = new URL(env.PUBLIC_BACKEND_ORIGIN || 'http://localhost:8080');
 
+/** Name of the cookie holding the session token, as set by the backend when logging in. */
+export const SESSION_COOKIE = 'habi2ca_session';
+
+export type Fetch = typeof fetch;
+
+/**
+ * Calls the API along with the session cookie. Loaders must pass the `fetch` of their event, which
+ * forwards the cookie to the backend (see `handleFetch` in `hooks.server.ts`).
+ * Loading a page with an expired session redirects to the login page.
+ */
+export async function apiFetch(
+	url: string,
+	init: RequestInit = {},
+	fetchFn: Fetch = fetch
+): Promise<Response> {
+	const response = await fetchFn(url, { ...init, credentials: 'include' });
+	if (response.status === 401 && !browser) {
+		redirect(303, '/login');
+	}
+	return response;
+}
+
 export function expect<T>(value: T | null, message: string): T {
 	if (value === null || value === undefined) {
 		throw new Error(message);
@@ -34,8 +57,12 @@ export async function handleJsonResponse<T>(response: Response, errorMessage: st
 	}
 }
 
-export async function fetchJson<T>(url: string, errorMessage: string): Promise<T> {
-	return fetch(url).then(async (response) => {
+export async function fetchJson<T>(
+	url: string,
+	errorMessage: string,
+	fetchFn: Fetch = fetch
+): Promise<T> {
+	return apiFetch(url, {}, fetchFn).then(async (response) => {
 		return handleJsonResponse(response, errorMessage);
 	});
 }
@@ -0,0 +1,57 @@
+import type { Habit } from './habit';
+import type { Player } from './player';
+import type { Task } from './task';
+
+export type XpEvent = {
+	id: number;
+	player_id: number;
+	amount: number;
+	source_kind: string;
+	source_id: number | null;
+	created_at: string;
+};
+
+export type PlayerEvent =
+	| { type: 'task_created'; task: Task }
+	| { type: 'task_completed'; task: Task }
+	| { type: 'habit_incremented'; habit: Habit }
+	| { type: 'xp_gained'; player: Player; xp_event: XpEvent }
+	| { type: 'level_up'; player: Player; levels_gained: number }
+	| { type: 'damage_taken'; player: Player; damage: number }
+	| { type: 'died'; player: Player; xp_event: XpEvent | null }
+	| { type: 'party_joined'; party_id: number; player: Player }
+	| { type: 'party_left'; party_id: number; player: Player };
+
+const EVENT_TYPES: PlayerEvent['type'][] = [
+	'task_created',
+	'task_completed',
+	'habit_incremented',
+	'xp_gained',
+	'level_up',
+	'damage_taken',
+	'died',
+	'party_joined',
+	'party_left'
+];
+
+/**
+ * Listens to the events of a player. `onResync` is called when events may have been missed, e.g.
+ * after the server restarted, in which case the player should be fetched again.
+ * Returns a function that stops listening.
+ */
+export function subscribeToPlayerEvents(
+	origin: URL,
+	playerId: number,
+	onEvent: (event: PlayerEvent) => void,
+	onResync: () => void
+): () => void {
+	// The browser reconnects on its own, sending the id of the last event it received.
+	const source = new EventSource(`${origin}api/players/${playerId}/events`, {
+		withCredentials: true
+	});
+	for (const type of EVENT_TYPES) {
+		source.addEventListener(type, (message) => onEvent(JSON.parse(message.data)));
+	}
+	source.addEventListener('resync', () => onResync());
+	return () => source.close();
+}
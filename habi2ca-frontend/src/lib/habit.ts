@@ -1,9 +1,12 @@
+import { apiFetch, type Fetch } from './base';
 import type { Player } from './player';
 
 export type HabitData = {
 	player_id: number;
 	name: string;
 	description: string;
+	positive?: boolean;
+	negative?: boolean;
 };
 
 export type Habit = {
@@ -11,11 +14,17 @@ export type Habit = {
 	player_id: number;
 	name: string;
 	description: string;
+	positive: boolean;
+	negative: boolean;
 };
 
-export async function getHabits(origin: URL, playerId: number): Promise<Habit[]> {
+export async function getHabits(
+	origin: URL,
+	playerId: number,
+	fetchFn: Fetch = fetch
+): Promise<Habit[]> {
 	const habitsUrl = `${origin}api/habits?player=${playerId}`;
-	const response = await fetch(habitsUrl);
+	const response = await apiFetch(habitsUrl, {}, fetchFn);
 	if (response.ok) {
 		return await response.json();
 	} else {
@@ -26,7 +35,7 @@ export async function getHabits(origin: URL, playerId: number): Promise<Habit[]>
 
 export async function createHabit(origin: URL, habitData: HabitData): Promise<Habit> {
 	const createHabitUrl = `${origin}api/habits`;
-	const response = await fetch(createHabitUrl, {
+	const response = await apiFetch(createHabitUrl, {
 		method: 'POST',
 		body: JSON.stringify(habitData),
 		headers: { 'Content-Type': 'application/json' }
@@ -39,17 +48,27 @@ export async function createHabit(origin: URL, habitData: HabitData): Promise<Ha
 	}
 }
 
-export async function incrementHabit(origin: URL, habitId: number): Promise<[Habit, Player]> {
-	const completeHabitUrl = `${origin}api/habits/${habitId}/increment`;
-	const habitResponse = await fetch(completeHabitUrl, { method: 'PATCH' });
+export async function incrementHabit(origin: URL, habitId: number): Promise<Habit> {
+	const incrementHabitUrl = `${origin}api/habits/${habitId}/increment`;
+	const response = await apiFetch(incrementHabitUrl, { method: 'PATCH' });
+	if (response.ok) {
+		return await response.json();
+	} else {
+		throw new Error(`Failed to increment habit. ${response.status}: ${await response.text()}`);
+	}
+}
+
+export async function decrementHabit(origin: URL, habitId: number): Promise<[Habit, Player]> {
+	const decrementHabitUrl = `${origin}api/habits/${habitId}/decrement`;
+	const habitResponse = await apiFetch(decrementHabitUrl, { method: 'PATCH' });
 	if (!habitResponse.ok) {
 		throw new Error(
-			`Failed to increment habit. ${habitResponse.status}: ${await habitResponse.text()}`
+			`Failed to decrement habit. ${habitResponse.status}: ${await habitResponse.text()}`
 		);
 	}
 	const habit: Habit = await habitResponse.json();
 
-	const playerResponse = await fetch(`${origin}api/players/${habit.player_id}`);
+	const playerResponse = await apiFetch(`${origin}api/players/${habit.player_id}`);
 	if (playerResponse.ok) {
 		const player = await playerResponse.json();
 		return [habit, player];
@@ -1,14 +1,18 @@
+import { apiFetch, type Fetch } from './base';
+
 export type Player = {
 	id: number;
 	name: string;
 	xp: number;
 	level: number;
 	xp_requirement: number;
+	health: number;
+	max_health: number;
 };
 
 export async function createPlayer(origin: URL, playerName: string): Promise<Player> {
 	const createPlayerUrl = `${origin}api/players?name=${playerName}`;
-	const response = await fetch(createPlayerUrl, { method: 'POST' });
+	const response = await apiFetch(createPlayerUrl, { method: 'POST' });
 	if (response.ok) {
 		return await response.json();
 	} else {
@@ -16,9 +20,9 @@ export async function createPlayer(origin: URL, playerName: string): Promise<Pla
 	}
 }
 
-export async function getPlayers(origin: URL): Promise<Player[]> {
+export async function getPlayers(origin: URL, fetchFn: Fetch = fetch): Promise<Player[]> {
 	const getPlayersUrl = `${origin}api/players`;
-	const response = await fetch(getPlayersUrl, { method: 'GET' });
+	const response = await apiFetch(getPlayersUrl, { method: 'GET' }, fetchFn);
 	if (response.ok) {
 		return await response.json();
 	} else {
@@ -26,9 +30,13 @@ export async function getPlayers(origin: URL): Promise<Player[]> {
 	}
 }
 
-export async function getPlayer(origin: URL, playerId: number): Promise<Player> {
+export async function getPlayer(
+	origin: URL,
+	playerId: number,
+	fetchFn: Fetch = fetch
+): Promise<Player> {
 	const getPlayerUrl = `${origin}api/players/${playerId}`;
-	const response = await fetch(getPlayerUrl, { method: 'GET' });
+	const response = await apiFetch(getPlayerUrl, { method: 'GET' }, fetchFn);
 	if (response.ok) {
 		return await response.json();
 	} else {
@@ -38,7 +46,7 @@ export async function getPlayer(origin: URL, playerId: number): Promise<Player>
 
 export async function addXp(origin: URL, playerId: number): Promise<Player> {
 	const addXpUrl = `${origin}api/players/${playerId}/add_xp?xp=1`;
-	const response = await fetch(addXpUrl, { method: 'PATCH' });
+	const response = await apiFetch(addXpUrl, { method: 'PATCH' });
 	if (response.ok) {
 		return await response.json();
 	} else {
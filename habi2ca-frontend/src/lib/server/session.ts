@@ -0,0 +1,36 @@
+import { fail, redirect, type ActionFailure, type Cookies } from '@sveltejs/kit';
+import { login, type Credentials } from '$lib/account';
+import { BACKEND_ORIGIN, SESSION_COOKIE, type Fetch } from '$lib/base';
+
+/** Reads the credentials submitted with a login or register form. */
+export async function readCredentials(request: Request): Promise<Credentials> {
+	const form = await request.formData();
+	return {
+		username: String(form.get('username') ?? ''),
+		password: String(form.get('password') ?? '')
+	};
+}
+
+/**
+ * Logs in and keeps the session in a cookie of the frontend, which is sent along with requests to
+ * the backend from then on. Redirects to the players once logged in.
+ */
+export async function startSession(
+	credentials: Credentials,
+	cookies: Cookies,
+	fetch: Fetch
+): Promise<ActionFailure<{ username: string; error: string }>> {
+	let session;
+	try {
+		session = await login(BACKEND_ORIGIN, credentials, fetch);
+	} catch (e) {
+		return fail(400, { username: credentials.username, error: (e as Error).message });
+	}
+	cookies.set(SESSION_COOKIE, session.token, {
+		path: '/',
+		httpOnly: true,
+		sameSite: 'lax',
+		maxAge: session.maxAge
+	});
+	redirect(303, '/');
+}
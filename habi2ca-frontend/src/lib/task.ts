@@ -1,4 +1,4 @@
-import type { Player } from './player';
+import { apiFetch, type Fetch } from './base';
 
 export type TaskData = {
 	player_id: number;
@@ -15,9 +15,13 @@ export type Task = {
 	completed: boolean;
 };
 
-export async function getTasks(origin: URL, playerId: number): Promise<Task[]> {
+export async function getTasks(
+	origin: URL,
+	playerId: number,
+	fetchFn: Fetch = fetch
+): Promise<Task[]> {
 	const tasksUrl = `${origin}api/tasks?player=${playerId}`;
-	const response = await fetch(tasksUrl);
+	const response = await apiFetch(tasksUrl, {}, fetchFn);
 	if (response.ok) {
 		return await response.json();
 	} else {
@@ -27,7 +31,7 @@ export async function getTasks(origin: URL, playerId: number): Promise<Task[]> {
 
 export async function createTask(origin: URL, taskData: TaskData): Promise<Task> {
 	const createTaskUrl = `${origin}api/tasks`;
-	const response = await fetch(createTaskUrl, {
+	const response = await apiFetch(createTaskUrl, {
 		method: 'POST',
 		body: JSON.stringify({ name: taskData.name, description: taskData.description }),
 		headers: { 'Content-Type': 'application/json' }
@@ -39,23 +43,12 @@ export async function createTask(origin: URL, taskData: TaskData): Promise<Task>
 	}
 }
 
-export async function completeTask(origin: URL, taskId: number): Promise<[Task, Player]> {
+export async function completeTask(origin: URL, taskId: number): Promise<Task> {
 	const completeTaskUrl = `${origin}api/tasks/${taskId}/complete`;
-	const taskResponse = await fetch(completeTaskUrl, { method: 'PATCH' });
-	if (!taskResponse.ok) {
-		throw new Error(
-			`Failed to complete task. ${taskResponse.status}: ${await taskResponse.text()}`
-		);
-	}
-	const task: Task = await taskResponse.json();
-
-	const playerResponse = await fetch(`${origin}api/players/${task.player_id}`);
-	if (playerResponse.ok) {
-		const player = await playerResponse.json();
-		return [task, player];
+	const response = await apiFetch(completeTaskUrl, { method: 'PATCH' });
+	if (response.ok) {
+		return await response.json();
 	} else {
-		throw new Error(
-			`Failed to fetch player. ${taskResponse.status}: ${await playerResponse.text()}`
-		);
+		throw new Error(`Failed to complete task. ${response.status}: ${await response.text()}`);
 	}
 }
@@ -0,0 +1,6 @@
+import { SESSION_COOKIE } from '$lib/base';
+import type { Cookies } from '@sveltejs/kit';
+
+export function load({ cookies }: { cookies: Cookies }): { loggedIn: boolean } {
+	return { loggedIn: cookies.get(SESSION_COOKIE) !== undefined };
+}
@@ -1,16 +1,16 @@
-import { BACKEND_ORIGIN } from '$lib/base';
+import { BACKEND_ORIGIN, type Fetch } from '$lib/base';
 import { getHabits, type Habit } from '$lib/habit';
 import { getPlayers, type Player } from '$lib/player';
 import { getTasks, type Task } from '$lib/task';
 import type { PlayerInfo } from './playerInfo';
 
-export async function load(): Promise<{ players: PlayerInfo[] }> {
-	const players: Player[] = await getPlayers(BACKEND_ORIGIN);
+export async function load({ fetch }: { fetch: Fetch }): Promise<{ players: PlayerInfo[] }> {
+	const players: Player[] = await getPlayers(BACKEND_ORIGIN, fetch);
 
 	const playerPromises = players.map(async (player) => {
-		const tasks: Task[] = await getTasks(BACKEND_ORIGIN, player.id);
+		const tasks: Task[] = await getTasks(BACKEND_ORIGIN, player.id, fetch);
 
-		const habits: Habit[] = await getHabits(BACKEND_ORIGIN, player.id);
+		const habits: Habit[] = await getHabits(BACKEND_ORIGIN, player.id, fetch);
 
 		return {
 			player: player,
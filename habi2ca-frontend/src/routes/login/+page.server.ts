@@ -0,0 +1,8 @@
+import type { Actions } from '@sveltejs/kit';
+import { readCredentials, startSession } from '$lib/server/session';
+
+export const actions: Actions = {
+	default: async ({ request, cookies, fetch }) => {
+		return startSession(await readCredentials(request), cookies, fetch);
+	}
+};
@@ -0,0 +1,10 @@
+import { redirect, type RequestHandler } from '@sveltejs/kit';
+import { logout } from '$lib/account';
+import { BACKEND_ORIGIN, SESSION_COOKIE } from '$lib/base';
+
+/** Ends the session in the backend and forgets it. */
+export const POST: RequestHandler = async ({ cookies, fetch }) => {
+	await logout(BACKEND_ORIGIN, fetch);
+	cookies.delete(SESSION_COOKIE, { path: '/' });
+	redirect(303, '/login');
+};
@@ -1,13 +1,15 @@
-import { BACKEND_ORIGIN } from '$lib/base';
+import { BACKEND_ORIGIN, type Fetch } from '$lib/base';
 import { getHabits, type Habit } from '$lib/habit';
 import { getPlayer, type Player } from '$lib/player';
 import { getTasks, type Task } from '$lib/task';
 import { error } from '@sveltejs/kit';
 
 export async function load({
-	params
+	params,
+	fetch
 }: {
 	params: { playerId: string };
+	fetch: Fetch;
 }): Promise<{ player: Player; tasks: Task[]; habits: Habit[] }> {
 	const playerIdStr = params.playerId;
 
@@ -17,8 +19,8 @@ export async function load({
 		error(400, 'Invalid player ID');
 	}
 
-	const playerPromise = getPlayer(BACKEND_ORIGIN, playerId);
-	const tasksPromise = getTasks(BACKEND_ORIGIN, playerId);
-	const habitPromise = getHabits(BACKEND_ORIGIN, playerId);
+	const playerPromise = getPlayer(BACKEND_ORIGIN, playerId, fetch);
+	const tasksPromise = getTasks(BACKEND_ORIGIN, playerId, fetch);
+	const habitPromise = getHabits(BACKEND_ORIGIN, playerId, fetch);
 	return { player: await playerPromise, tasks: await tasksPromise, habits: await habitPromise };
 }
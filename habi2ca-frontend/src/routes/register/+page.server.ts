@@ -0,0 +1,16 @@
+import { fail, type Actions } from '@sveltejs/kit';
+import { register } from '$lib/account';
+import { BACKEND_ORIGIN } from '$lib/base';
+import { readCredentials, startSession } from '$lib/server/session';
+
+export const actions: Actions = {
+	default: async ({ request, cookies, fetch }) => {
+		const credentials = await readCredentials(request);
+		try {
+			await register(BACKEND_ORIGIN, credentials, fetch);
+		} catch (e) {
+			return fail(400, { username: credentials.username, error: (e as Error).message });
+		}
+		return startSession(credentials, cookies, fetch);
+	}
+};
@@ -1,5 +1,30 @@
-import { test } from '@playwright/test';
+import { expect, test } from '@playwright/test';
 
-test('empty', async ({ page: _page }) => {
-	console.log('empty test to avoid error');
+test('redirects to the login page without a session', async ({ page }) => {
+	await page.goto('/');
+	await expect(page).toHaveURL('/login');
+});
+
+test('loads the players of the account once logged in', async ({ page }) => {
+	// The database is kept between runs, so every run registers a new account.
+	const username = `tester-${Date.now()}`;
+	const password = 'correct horse battery staple';
+
+	await page.goto('/register');
+	await page.getByLabel('Username').fill(username);
+	await page.getByLabel('Password').fill(password);
+	await page.getByRole('button', { name: 'Register' }).click();
+	await expect(page).toHaveURL('/');
+
+	// Loading the page again renders it on the server, with the session cookie of the browser.
+	await page.reload();
+	await expect(page.getByRole('button', { name: 'Create Player' })).toBeVisible();
+	await expect(page.getByRole('button', { name: 'Log out' })).toBeVisible();
+
+	await page.getByRole('button', { name: 'Log out' }).click();
+	await expect(page).toHaveURL('/login');
+	await page.getByLabel('Username').fill(username);
+	await page.getByLabel('Password').fill(password);
+	await page.getByRole('button', { name: 'Log in' }).click();
+	await expect(page).toHaveURL('/');
 });
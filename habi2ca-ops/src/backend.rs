@@ -34,8 +34,11 @@ impl RunBackend {
         }
         command.args([
             "--",
+            "--database-path",
             self.database_path.as_str(),
+            "--hostname",
             self.hostname.as_str(),
+            "--port",
             self.port.to_string().as_str(),
             "--log-dir",
             self.log_dir.to_str().unwrap(),
@@ -4,7 +4,7 @@ use std::{
 };
 
 use anyhow::{Context, Result};
-use clap::{command, Args, Subcommand};
+use clap::{Args, Subcommand};
 
 #[derive(Args, Debug, Clone)]
 pub struct Docker {
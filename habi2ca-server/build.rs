@@ -0,0 +1,40 @@
+use std::{env, path::Path, process::Command};
+
+/// Runs git in the repository and returns its trimmed output, if it succeeds.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        .filter(|output| !output.is_empty())
+}
+
+/// Embeds the commit the server is built from as `HABI2CA_GIT_COMMIT`, for `/api/version`.
+///
+/// Builds without the git repository, like the Docker image, get the commit from the
+/// `HABI2CA_GIT_COMMIT` environment variable instead.
+fn main() {
+    println!("cargo:rerun-if-env-changed=HABI2CA_GIT_COMMIT");
+    let commit = env::var("HABI2CA_GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.is_empty())
+        .or_else(|| {
+            // Rebuild when a commit is made or checked out. Files that do not exist are skipped,
+            // since cargo would consider them changed on every build.
+            let reference = git(&["symbolic-ref", "-q", "HEAD"]);
+            let files = ["HEAD", "packed-refs"]
+                .into_iter()
+                .chain(reference.as_deref());
+            for file in files {
+                if let Some(path) = git(&["rev-parse", "--git-path", file]) {
+                    if Path::new(&path).exists() {
+                        println!("cargo:rerun-if-changed={path}");
+                    }
+                }
+            }
+            git(&["rev-parse", "HEAD"])
+        })
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=HABI2CA_GIT_COMMIT={commit}");
+}
@@ -0,0 +1,516 @@
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use clap::Args;
+use habi2ca_database::migration::{Migrator, MigratorTrait};
+use sea_orm::{
+    sqlx::{
+        self,
+        sqlite::{SqliteConnectOptions, SqliteConnection},
+        Connection,
+    },
+    ConnectionTrait, Database, DatabaseConnection, DbErr, Statement,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{database_utils, export};
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("No backup named '{0}' exists.")]
+    NotFound(String),
+    #[error("Backup '{name}' is not a valid database: {reason}")]
+    Corrupt { name: String, reason: String },
+    #[error(
+        "Backup '{name}' was made by a newer version of the server with unknown migrations: {}",
+        migrations.join(", ")
+    )]
+    UnknownMigrations {
+        name: String,
+        migrations: Vec<String>,
+    },
+    #[error("Failed to create backup: {0:#}")]
+    Create(anyhow::Error),
+    #[error("Failed to access backup files.")]
+    Io(#[from] io::Error),
+    #[error("Database error while restoring backup.")]
+    Restore(#[from] sqlx::Error),
+    #[error("Database error while handling backups.")]
+    Database(#[from] DbErr),
+}
+
+const DEFAULT_KEEP_LAST: usize = 10;
+const DEFAULT_KEEP_DAILY: usize = 7;
+const DEFAULT_KEEP_WEEKLY: usize = 4;
+
+/// Which backups to keep when old backups are pruned.
+/// A backup is kept if any of the rules selects it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Args)]
+pub struct Retention {
+    /// Number of most recent backups to keep.
+    #[clap(long = "backup-keep-last", global = true, default_value_t = DEFAULT_KEEP_LAST)]
+    pub keep_last: usize,
+    /// Number of most recent days for which the newest backup of the day is kept.
+    #[clap(long = "backup-keep-daily", global = true, default_value_t = DEFAULT_KEEP_DAILY)]
+    pub keep_daily: usize,
+    /// Number of most recent weeks for which the newest backup of the week is kept.
+    #[clap(long = "backup-keep-weekly", global = true, default_value_t = DEFAULT_KEEP_WEEKLY)]
+    pub keep_weekly: usize,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            keep_last: DEFAULT_KEEP_LAST,
+            keep_daily: DEFAULT_KEEP_DAILY,
+            keep_weekly: DEFAULT_KEEP_WEEKLY,
+        }
+    }
+}
+
+impl Retention {
+    /// The backups that are not selected by any rule.
+    /// `backups` must be sorted from newest to oldest.
+    fn expired<'a>(&self, backups: &'a [Backup]) -> Vec<&'a Backup> {
+        let mut days = HashSet::new();
+        let mut weeks = HashSet::new();
+        backups
+            .iter()
+            .enumerate()
+            .filter(|(index, backup)| {
+                let date = backup.created_at.date_naive();
+                // Evaluate every rule so each backup counts towards the days and weeks it covers.
+                let keep_last = *index < self.keep_last;
+                let keep_daily = days.len() < self.keep_daily && days.insert(date);
+                let keep_weekly = weeks.len() < self.keep_weekly && weeks.insert(date.iso_week());
+                !(keep_last || keep_daily || keep_weekly)
+            })
+            .map(|(_, backup)| backup)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backup {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Size of the backup in bytes.
+    pub size: u64,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Backup {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Result of checking that a backup can be restored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    #[serde(flatten)]
+    pub backup: Backup,
+    /// Last migration applied to the backup.
+    pub schema_version: Option<String>,
+    /// Migrations that will be applied to the backup when it is restored.
+    pub pending_migrations: Vec<String>,
+}
+
+/// The backups of a database file, which are stored next to it.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    database_path: PathBuf,
+    retention: Retention,
+}
+
+impl Backups {
+    pub fn new(database_path: impl Into<PathBuf>, retention: Retention) -> Self {
+        Self {
+            database_path: database_path.into(),
+            retention,
+        }
+    }
+
+    pub fn database_path(&self) -> &Path {
+        &self.database_path
+    }
+
+    fn directory(&self) -> &Path {
+        match self.database_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        }
+    }
+
+    /// Parses the backup file names produced by [`database_utils::backup`].
+    fn parse(&self, path: PathBuf) -> Option<Backup> {
+        let database_name = self.database_path.file_name()?.to_str()?;
+        let name = path.file_name()?.to_str()?.to_owned();
+        let timestamp = name.strip_suffix(database_name)?;
+        if !timestamp.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        let created_at = match timestamp.len() {
+            14 => NaiveDateTime::parse_from_str(timestamp, "%Y%m%d%H%M%S"),
+            17 => NaiveDateTime::parse_from_str(timestamp, "%Y%m%d%H%M%S%3f"),
+            _ => return None,
+        }
+        .ok()?
+        .and_utc();
+        let size = path.metadata().ok()?.len();
+        Some(Backup {
+            name,
+            created_at,
+            size,
+            path,
+        })
+    }
+
+    /// All backups of the database, newest first.
+    pub fn list(&self) -> Result<Vec<Backup>, BackupError> {
+        let mut backups = Vec::new();
+        for entry in fs::read_dir(self.directory())? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(backup) = self.parse(entry.path()) {
+                backups.push(backup);
+            }
+        }
+        backups.sort_by_key(|backup| Reverse(backup.created_at));
+        Ok(backups)
+    }
+
+    /// Finds a backup by name. Only names of existing backups are accepted, so the name cannot
+    /// be used to access other files.
+    pub fn get(&self, name: &str) -> Result<Backup, BackupError> {
+        self.list()?
+            .into_iter()
+            .find(|backup| backup.name == name)
+            .ok_or_else(|| BackupError::NotFound(name.to_owned()))
+    }
+
+    /// Backs up the database through `database`, which must be connected to it, and prunes old
+    /// backups.
+    pub async fn create(&self, database: &DatabaseConnection) -> Result<Backup, BackupError> {
+        let path = database_utils::backup(database, &self.database_path)
+            .await
+            .map_err(BackupError::Create)?;
+        let backup = self.parse(path.clone()).ok_or_else(|| {
+            BackupError::Create(anyhow::anyhow!("Unexpected backup path {path:?}."))
+        })?;
+        if let Err(error) = self.prune() {
+            // The backup itself succeeded, so a failed cleanup should not fail it.
+            warn!("Failed to prune old backups: {error}");
+        }
+        Ok(backup)
+    }
+
+    /// Deletes the backups not kept by the retention policy and returns them.
+    pub fn prune(&self) -> Result<Vec<Backup>, BackupError> {
+        let backups = self.list()?;
+        let expired: Vec<Backup> = self
+            .retention
+            .expired(&backups)
+            .into_iter()
+            .cloned()
+            .collect();
+        for backup in &expired {
+            fs::remove_file(&backup.path)?;
+            info!("Deleted expired backup '{}'.", backup.name);
+        }
+        Ok(expired)
+    }
+
+    /// Checks that the backup is an intact database with a schema this server can migrate.
+    pub async fn verify(&self, name: &str) -> Result<Verification, BackupError> {
+        let backup = self.get(name)?;
+        let corrupt = |reason: String| BackupError::Corrupt {
+            name: backup.name.clone(),
+            reason,
+        };
+
+        let url = format!("sqlite:{}?mode=ro", backup.path.display());
+        let database = Database::connect(url.as_str())
+            .await
+            .map_err(|error| corrupt(error.to_string()))?;
+        let backend = database.get_database_backend();
+
+        let integrity = database
+            .query_all(Statement::from_string(backend, "PRAGMA integrity_check"))
+            .await
+            .map_err(|error| corrupt(error.to_string()))?
+            .into_iter()
+            .map(|row| row.try_get_by_index::<String>(0))
+            .collect::<Result<Vec<_>, _>>()?;
+        if integrity != ["ok"] {
+            return Err(corrupt(integrity.join("; ")));
+        }
+
+        let applied = database
+            .query_all(Statement::from_string(
+                backend,
+                "SELECT version FROM seaql_migrations ORDER BY version",
+            ))
+            .await
+            .map_err(|_| corrupt("no migration history found".to_owned()))?
+            .into_iter()
+            .map(|row| row.try_get_by_index::<String>(0))
+            .collect::<Result<Vec<_>, _>>()?;
+        database.close().await?;
+
+        let known: Vec<String> = Migrator::migrations()
+            .iter()
+            .map(|migration| migration.name().to_owned())
+            .collect();
+        let unknown: Vec<String> = applied
+            .iter()
+            .filter(|version| !known.contains(version))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(BackupError::UnknownMigrations {
+                name: backup.name,
+                migrations: unknown,
+            });
+        }
+        let pending_migrations = known
+            .into_iter()
+            .filter(|version| !applied.contains(version))
+            .collect();
+
+        Ok(Verification {
+            backup,
+            schema_version: applied.last().cloned(),
+            pending_migrations,
+        })
+    }
+
+    /// Replaces the contents of the database with those of the backup.
+    ///
+    /// The backup is verified and migrated to the current schema in a scratch copy, and the current
+    /// database is backed up before anything is replaced. The data is then swapped in a single
+    /// transaction, so a running server sees either the old or the restored data.
+    ///
+    /// Returns the backup of the database from before the restore.
+    pub async fn restore(&self, name: &str) -> Result<Backup, BackupError> {
+        let verification = self.verify(name).await?;
+
+        let scratch_path = database_utils::scratch_path(&self.database_path, "restore");
+        fs::copy(&verification.backup.path, &scratch_path)?;
+        let result = self.restore_from(&scratch_path).await;
+        if let Err(error) = fs::remove_file(&scratch_path) {
+            warn!("Failed to remove scratch database {scratch_path:?}: {error}");
+        }
+        let previous = result?;
+        info!(
+            "Restored backup '{}'. The previous database was backed up as '{}'.",
+            verification.backup.name, previous.name
+        );
+        Ok(previous)
+    }
+
+    async fn restore_from(&self, scratch_path: &Path) -> Result<Backup, BackupError> {
+        let scratch = database_utils::sqlite_connection(scratch_path)
+            .await
+            .map_err(BackupError::Create)?;
+        Migrator::up(&scratch, None).await?;
+        scratch.close().await?;
+
+        let database = database_utils::sqlite_connection(&self.database_path)
+            .await
+            .map_err(BackupError::Create)?;
+        let previous = self.create(&database).await?;
+        database.close().await?;
+
+        let options =
+            SqliteConnectOptions::from_str(&database_utils::sqlite_url(&self.database_path))?
+                .foreign_keys(true);
+        let mut connection = SqliteConnection::connect_with(&options).await?;
+        sqlx::query("ATTACH DATABASE ?1 AS restore")
+            .bind(scratch_path.to_string_lossy())
+            .execute(&mut connection)
+            .await?;
+        let mut transaction = connection.begin().await?;
+        // Foreign keys are only checked once all tables have been refilled.
+        sqlx::query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut *transaction)
+            .await?;
+        let tables = export::data_tables(&mut transaction).await?;
+        // Every table is emptied before any is refilled, since deletes cascade to other tables.
+        for table in &tables {
+            sqlx::query(&format!("DELETE FROM main.\"{table}\""))
+                .execute(&mut *transaction)
+                .await?;
+        }
+        for table in &tables {
+            let columns = export::table_columns(&mut transaction, table)
+                .await?
+                .iter()
+                .map(|column| format!("\"{column}\""))
+                .collect::<Vec<_>>()
+                .join(", ");
+            sqlx::query(&format!(
+                "INSERT INTO main.\"{table}\" ({columns}) SELECT {columns} FROM restore.\"{table}\""
+            ))
+            .execute(&mut *transaction)
+            .await?;
+        }
+        // Keep ids from being reused for rows deleted before the backup was taken.
+        sqlx::query("DELETE FROM main.sqlite_sequence")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("INSERT INTO main.sqlite_sequence SELECT * FROM restore.sqlite_sequence")
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        connection.close().await?;
+        Ok(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, slice};
+
+    use chrono::{DateTime, TimeDelta, Utc};
+    use habi2ca_database::account;
+    use sea_orm::{ConnectionTrait, DatabaseConnection, EntityTrait};
+
+    use super::{Backup, BackupError, Backups, Retention};
+    use crate::{database_utils, logic::player::Player, test_utils};
+
+    fn backups_at(now: DateTime<Utc>, ages: &[TimeDelta]) -> Vec<Backup> {
+        ages.iter()
+            .map(|age| Backup {
+                name: format!("{age}"),
+                created_at: now - *age,
+                size: 0,
+                path: Default::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn retention() {
+        // A Sunday, so the hourly backups are all in the same week.
+        let now = "2026-10-18T12:00:00Z".parse().unwrap();
+        let hours: Vec<TimeDelta> = (0..6).map(TimeDelta::hours).collect();
+        let days: Vec<TimeDelta> = (1..30).map(TimeDelta::days).collect();
+        let backups = backups_at(now, &[hours.as_slice(), days.as_slice()].concat());
+
+        let retention = Retention {
+            keep_last: 3,
+            keep_daily: 4,
+            keep_weekly: 2,
+        };
+        let kept: Vec<TimeDelta> = backups
+            .iter()
+            .filter(|backup| !retention.expired(&backups).contains(backup))
+            .map(|backup| now - backup.created_at)
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                // The last 3 backups.
+                TimeDelta::hours(0),
+                TimeDelta::hours(1),
+                TimeDelta::hours(2),
+                // The newest backup of the 3 days before today.
+                TimeDelta::days(1),
+                TimeDelta::days(2),
+                TimeDelta::days(3),
+                // The newest backup of the previous week, which ended on Sunday.
+                TimeDelta::days(7),
+            ]
+        );
+    }
+
+    async fn setup_database() -> (tempfile::TempDir, Backups, DatabaseConnection) {
+        let directory = tempfile::tempdir().unwrap();
+        let backups = Backups::new(directory.path().join("data.db"), Retention::default());
+        let database = database_utils::open_or_initialize_database(&backups, false, false)
+            .await
+            .unwrap();
+        (directory, backups, database)
+    }
+
+    #[tokio::test]
+    async fn create_and_restore() {
+        let (_directory, backups, database) = setup_database().await;
+        test_utils::create_test_account(&database).await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+
+        let backup = backups.create(&database).await.unwrap();
+        assert_eq!(backups.list().unwrap(), slice::from_ref(&backup));
+        let verification = backups.verify(&backup.name).await.unwrap();
+        assert!(verification.pending_migrations.is_empty());
+
+        let (player, _) = player.delete(&database).await.unwrap();
+        let previous = backups.restore(&backup.name).await.unwrap();
+        assert_eq!(backups.list().unwrap().len(), 2);
+        let players = Player::account_players(&database, test_utils::TEST_ACCOUNT)
+            .await
+            .unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0].id(), player.id());
+
+        // Restoring the backup taken before the restore undoes it.
+        backups.restore(&previous.name).await.unwrap();
+        let players = Player::account_players(&database, test_utils::TEST_ACCOUNT)
+            .await
+            .unwrap();
+        assert!(players.is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_backups() {
+        let (_directory, backups, database) = setup_database().await;
+
+        assert!(matches!(
+            backups.verify("../data.db").await,
+            Err(BackupError::NotFound(_))
+        ));
+
+        let backup = backups.create(&database).await.unwrap();
+        fs::write(backup.path(), "not a database").unwrap();
+        assert!(matches!(
+            backups.restore(&backup.name).await,
+            Err(BackupError::Corrupt { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn backup_includes_unflushed_writes() {
+        let (_directory, backups, database) = setup_database().await;
+        // In WAL mode, committed writes stay in the log until it is checkpointed, so a copy of the
+        // database file alone would miss them.
+        database
+            .execute_unprepared("PRAGMA journal_mode = WAL")
+            .await
+            .unwrap();
+        test_utils::create_test_account(&database).await;
+
+        let backup = backups.create(&database).await.unwrap();
+        let backup_database = database_utils::sqlite_connection(backup.path())
+            .await
+            .unwrap();
+        let account = account::Entity::find_by_id(test_utils::TEST_ACCOUNT)
+            .one(&backup_database)
+            .await
+            .unwrap();
+        assert!(account.is_some());
+    }
+}
@@ -1,11 +1,18 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use habi2ca_database::migration::{Migrator, MigratorTrait};
-use sea_orm::{sqlx::types::chrono, Database, DatabaseConnection};
+use sea_orm::{
+    sqlx::types::chrono, ConnectionTrait, Database, DatabaseConnection, DbErr, Statement,
+};
 use std::{
     fs,
     path::{Path, PathBuf},
 };
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::{
+    backup::{Backup, Backups},
+    export::Export,
+};
 
 pub fn sqlite_url(database_path: impl AsRef<Path>) -> String {
     format!("sqlite:{}?mode=rw", database_path.as_ref().display())
@@ -22,8 +29,33 @@ pub async fn sqlite_connection(database_path: impl AsRef<Path>) -> Result<Databa
         })
 }
 
-pub fn backup(database_path: &Path) -> Result<PathBuf> {
-    let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
+/// Path of a temporary file next to the database.
+pub fn scratch_path(database_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = database_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Writes a copy of the database to `path`, which must not exist.
+async fn vacuum_into(database: &DatabaseConnection, path: &Path) -> Result<(), DbErr> {
+    database
+        .execute(Statement::from_sql_and_values(
+            database.get_database_backend(),
+            "VACUUM INTO ?",
+            [path.to_string_lossy().into_owned().into()],
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Backs up the database to a file next to it, named by prefixing the database's name with the time.
+/// Use [`Backups::create`] to also prune old backups.
+///
+/// The backup is written by the database itself with `VACUUM INTO`, so it is a consistent snapshot
+/// even while other connections are writing to the database.
+pub async fn backup(database: &DatabaseConnection, database_path: &Path) -> Result<PathBuf> {
+    let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S%3f").to_string();
     let file_name = database_path.file_stem().unwrap().to_string_lossy();
     let new_path = database_path.with_file_name(format!("{}{}", timestamp, file_name));
     let new_path = if let Some(extension) = database_path.extension() {
@@ -31,33 +63,131 @@ pub fn backup(database_path: &Path) -> Result<PathBuf> {
     } else {
         new_path
     };
-    fs::copy(database_path, &new_path).with_context(|| {
-        format!("Failed to move database at '{database_path:?}' to new path '{new_path:?}'")
+    vacuum_into(database, &new_path).await.with_context(|| {
+        format!("Failed to back up database at '{database_path:?}' to '{new_path:?}'")
     })?;
     info!("Database backup created at '{new_path:?}'");
     Ok(new_path)
 }
 
-pub async fn reinitialize_database(database_path: &Path) -> Result<PathBuf> {
-    let backup_path = backup(database_path)?;
-    let database = sqlite_connection(database_path).await?;
-    Migrator::fresh(&database)
+/// Deletes all data in the database by recreating its schema, after backing it up if `backups` is
+/// given. Nothing else may use the database meanwhile.
+pub async fn reinitialize_database(
+    database: &DatabaseConnection,
+    backups: Option<&Backups>,
+) -> Result<Option<Backup>> {
+    let backup = match backups {
+        Some(backups) => Some(backups.create(database).await?),
+        None => None,
+    };
+    Migrator::fresh(database)
         .await
         .context("Failed to run fresh migrations")?;
-    Ok(backup_path)
+    Ok(backup)
 }
 
+/// Applies the pending migrations to a scratch copy of the database, leaving the database itself
+/// untouched.
+async fn dry_run_migrations(database: &DatabaseConnection, database_path: &Path) -> Result<()> {
+    let scratch_path = scratch_path(database_path, "dry-run");
+    if scratch_path.exists() {
+        fs::remove_file(&scratch_path)?;
+    }
+    vacuum_into(database, &scratch_path)
+        .await
+        .context("Failed to copy database for migration dry run")?;
+    let scratch = sqlite_connection(&scratch_path).await?;
+    let result = Migrator::up(&scratch, None).await;
+    scratch.close().await?;
+    fs::remove_file(&scratch_path)?;
+    result.context("Migrations failed on a scratch copy of the database")
+}
+
+/// Rebuilds the database from the data in `backup`, which must be a backup of the database from
+/// before any of the pending migrations were attempted.
+///
+/// The data is exported to a JSON file next to the backup and imported into a database with a fresh
+/// schema. The rebuilt database only replaces the database if no data was lost. Otherwise the
+/// database is restored from the backup and an error with a report of the lost data is returned.
+/// The database must not be open while it is rebuilt.
+async fn rebuild_database(database_path: &Path, backup: &Backup) -> Result<()> {
+    let export = Export::read(backup.path())
+        .await
+        .context("Failed to export data from backup")?;
+    let export_path = backup.path().with_extension("json");
+    export.save(&export_path)?;
+    info!("Exported data to {export_path:?}.");
+
+    let rebuilt_path = scratch_path(database_path, "rebuild");
+    if rebuilt_path.exists() {
+        fs::remove_file(&rebuilt_path)?;
+    }
+    let rebuilt = Database::connect(format!("sqlite:{}?mode=rwc", rebuilt_path.display())).await?;
+    Migrator::up(&rebuilt, None)
+        .await
+        .context("Failed to create fresh schema")?;
+    rebuilt.close().await?;
+    let report = export
+        .import(&rebuilt_path)
+        .await
+        .context("Failed to import data")?;
+
+    if !report.is_lossless() {
+        fs::remove_file(&rebuilt_path)?;
+        // Undo any migrations that were partially applied.
+        fs::copy(backup.path(), database_path)?;
+        bail!(
+            "Rebuilding the database would lose data, so the database was restored from backup \
+            '{}' and left unchanged. Its data was exported to {export_path:?}.\n{report}",
+            backup.name
+        );
+    }
+    fs::rename(&rebuilt_path, database_path)?;
+    info!("Rebuilt database from backup '{}':\n{report}", backup.name);
+    Ok(())
+}
+
+/// Connects to the database at `url` and applies pending migrations.
+///
+/// Unlike [`open_or_initialize_database`], failing migrations cannot be recovered from, since
+/// backups are only supported for SQLite database files.
+pub async fn open_database_url(url: &str) -> Result<DatabaseConnection> {
+    // The URL is left out of errors, since it may contain a password.
+    let database = Database::connect(url)
+        .await
+        .context("Failed to connect to database.")?;
+    if !Migrator::get_pending_migrations(&database)
+        .await
+        .context("Failed to get pending migrations")?
+        .is_empty()
+    {
+        info!("Pending migrations found. Running migrations...");
+        Migrator::up(&database, None)
+            .await
+            .context("Failed to run pending migrations")?;
+        info!("Migrations complete.");
+    }
+    Ok(database)
+}
+
+/// Opens the database, creating it if it does not exist, and applies pending migrations.
+///
+/// If the migrations fail and `force_migrations` is set, the database is rebuilt from its data
+/// instead. With `migration_dry_run`, the migrations are first applied to a scratch copy so a
+/// failing migration never touches the database.
 pub async fn open_or_initialize_database(
-    database_path: impl AsRef<Path>,
+    backups: &Backups,
     force_migrations: bool,
+    migration_dry_run: bool,
 ) -> Result<DatabaseConnection> {
-    let database_path = database_path.as_ref();
-    let database_url = if database_path.exists() {
+    let database_path = backups.database_path();
+    let existed = database_path.exists();
+    let database_url = if existed {
         format!("sqlite:{}?mode=rw", database_path.display())
     } else {
         format!("sqlite:{}?mode=rwc", database_path.display())
     };
-    let database = Database::connect(database_url.as_str())
+    let mut database = Database::connect(database_url.as_str())
         .await
         .with_context(|| {
             format!(
@@ -72,18 +202,125 @@ pub async fn open_or_initialize_database(
         .is_empty()
     {
         info!("Pending migrations found. Creating backup and running migrations...");
-        if force_migrations {
-            if Migrator::up(&database, None).await.is_err() {
-                reinitialize_database(database_path).await.map(|_| ())
-            } else {
-                Ok(())
-            }
+        let backup = if existed {
+            Some(backups.create(&database).await?)
+        } else {
+            None
+        };
+        let result = if migration_dry_run {
+            dry_run_migrations(&database, database_path).await
         } else {
-            Migrator::up(&database, None)
+            Ok(())
+        };
+        let result = match result {
+            Ok(()) => Migrator::up(&database, None)
                 .await
-                .context("Failed to run pending migrations")
-        }?;
+                .context("Failed to run pending migrations"),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            match backup {
+                Some(backup) if force_migrations => {
+                    warn!("{error:#}. Rebuilding database from its data...");
+                    database.close().await?;
+                    rebuild_database(database_path, &backup).await?;
+                    database = sqlite_connection(database_path).await?;
+                }
+                _ => return Err(error),
+            }
+        }
         info!("Migrations complete.");
     }
     Ok(database)
 }
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ConnectionTrait, DatabaseConnection};
+
+    use super::open_or_initialize_database;
+    use crate::{
+        backup::{Backups, Retention},
+        logic::player::Player,
+        test_utils,
+    };
+
+    /// Creates a database with a player whose last migration has not been applied yet.
+    /// Applying it fails, since its tables already exist.
+    async fn setup_database() -> (tempfile::TempDir, Backups) {
+        let directory = tempfile::tempdir().unwrap();
+        let backups = Backups::new(directory.path().join("data.db"), Retention::default());
+        let database = open_or_initialize_database(&backups, false, false)
+            .await
+            .unwrap();
+        test_utils::create_test_account(&database).await;
+        Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        database
+            .execute_unprepared(
+                "DELETE FROM seaql_migrations WHERE version = 'm20261018_180000_parties'",
+            )
+            .await
+            .unwrap();
+        database.close().await.unwrap();
+        (directory, backups)
+    }
+
+    async fn player_count(database: &DatabaseConnection) -> usize {
+        Player::account_players(database, test_utils::TEST_ACCOUNT)
+            .await
+            .unwrap()
+            .len()
+    }
+
+    #[tokio::test]
+    async fn failing_migrations() {
+        let (_directory, backups) = setup_database().await;
+
+        assert!(open_or_initialize_database(&backups, false, true)
+            .await
+            .is_err());
+        assert!(open_or_initialize_database(&backups, false, false)
+            .await
+            .is_err());
+
+        let database = open_or_initialize_database(&backups, true, false)
+            .await
+            .unwrap();
+        assert_eq!(player_count(&database).await, 1);
+        // The data is kept in a portable format next to the backup it was exported from.
+        let backup = &backups.list().unwrap()[0];
+        assert!(backup.path().with_extension("json").exists());
+    }
+
+    #[tokio::test]
+    async fn rebuild_refuses_data_loss() {
+        let (_directory, backups) = setup_database().await;
+        let database = super::sqlite_connection(backups.database_path())
+            .await
+            .unwrap();
+        database
+            .execute_unprepared(
+                "CREATE TABLE legacy (id INTEGER PRIMARY KEY); INSERT INTO legacy VALUES (1);",
+            )
+            .await
+            .unwrap();
+        database.close().await.unwrap();
+
+        let error = open_or_initialize_database(&backups, true, false)
+            .await
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("Table 'legacy' no longer exists."));
+
+        // The database is left as it was.
+        let database = super::sqlite_connection(backups.database_path())
+            .await
+            .unwrap();
+        assert_eq!(player_count(&database).await, 1);
+        database
+            .execute_unprepared("SELECT * FROM legacy")
+            .await
+            .unwrap();
+    }
+}
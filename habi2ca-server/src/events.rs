@@ -0,0 +1,289 @@
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::web::Bytes;
+use futures_util::{stream, Stream};
+use habi2ca_database::{party::PartyId, player::PlayerId};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::{self, MissedTickBehavior},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    logic::{habit::Habit, player::Player, task::Task, xp_event::XpEvent},
+    metrics::metrics,
+};
+
+/// Number of recent events kept for clients resuming a stream with `Last-Event-ID`.
+const RECENT_EVENTS: usize = 1024;
+/// Number of events a stream may fall behind before it has to resync.
+const CHANNEL_CAPACITY: usize = 256;
+/// Time between comments sent to keep idle streams from being closed by proxies.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Something that happened to a player, streamed to clients at `/api/players/{id}/events`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlayerEvent {
+    TaskCreated {
+        task: Task,
+    },
+    TaskCompleted {
+        task: Task,
+    },
+    HabitIncremented {
+        habit: Habit,
+    },
+    /// The player's xp changed. The amount of the xp event is negative when xp is taken back, e.g.
+    /// by uncompleting a task.
+    XpGained {
+        player: Player,
+        xp_event: XpEvent,
+    },
+    LevelUp {
+        player: Player,
+        levels_gained: u64,
+    },
+    /// The player lost health, e.g. by decrementing a habit or missing a daily.
+    DamageTaken {
+        player: Player,
+        damage: f64,
+    },
+    /// The player's health ran out. They lost a level along with their progress towards the next
+    /// one, recorded by the xp event if there was any, and are back at full health.
+    Died {
+        player: Player,
+        xp_event: Option<XpEvent>,
+    },
+    /// The player joined a party, either by founding it or by joining an existing one.
+    PartyJoined {
+        party_id: PartyId,
+        player: Player,
+    },
+    /// The player left a party. Parties are disbanded once their last member leaves.
+    PartyLeft {
+        party_id: PartyId,
+        player: Player,
+    },
+}
+
+impl PlayerEvent {
+    /// Name of the event in the stream, which is also its `type`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerEvent::TaskCreated { .. } => "task_created",
+            PlayerEvent::TaskCompleted { .. } => "task_completed",
+            PlayerEvent::HabitIncremented { .. } => "habit_incremented",
+            PlayerEvent::XpGained { .. } => "xp_gained",
+            PlayerEvent::LevelUp { .. } => "level_up",
+            PlayerEvent::DamageTaken { .. } => "damage_taken",
+            PlayerEvent::Died { .. } => "died",
+            PlayerEvent::PartyJoined { .. } => "party_joined",
+            PlayerEvent::PartyLeft { .. } => "party_left",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Event {
+    /// Increases with every published event. Ids start at the time the server started, so ids
+    /// from before a restart are lower than every id after it.
+    pub id: u64,
+    pub player_id: PlayerId,
+    pub event: PlayerEvent,
+}
+
+impl Event {
+    fn to_sse(&self) -> Bytes {
+        let data = serde_json::to_string(&self.event).expect("Events should serialize to JSON.");
+        Bytes::from(format!(
+            "id: {}\nevent: {}\ndata: {data}\n\n",
+            self.id,
+            self.event.name()
+        ))
+    }
+}
+
+/// Tells the client that it may have missed events and should fetch the state of the player
+/// again. Carries the id to resume from, if known.
+fn resync(id: Option<u64>) -> Bytes {
+    let id = id.map(|id| format!("id: {id}\n")).unwrap_or_default();
+    Bytes::from(format!("{id}event: resync\ndata: {{}}\n\n"))
+}
+
+struct Recent {
+    next_id: u64,
+    events: VecDeque<Arc<Event>>,
+}
+
+/// Events the client should receive before the live ones.
+pub struct Subscription {
+    /// Whether events the client asked for are no longer known.
+    resync: bool,
+    /// Id of the latest event published before subscribing.
+    latest_id: u64,
+    missed: Vec<Arc<Event>>,
+    receiver: broadcast::Receiver<Arc<Event>>,
+}
+
+/// In-process bus player events are published to once their changes are committed. Each app has
+/// its own bus in its [`State`](crate::state::State).
+pub struct EventBus {
+    recent: Mutex<Recent>,
+    sender: broadcast::Sender<Arc<Event>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            recent: Mutex::new(Recent {
+                next_id: started_at.as_micros() as u64,
+                events: VecDeque::with_capacity(RECENT_EVENTS),
+            }),
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+
+    pub fn publish(&self, player_id: PlayerId, event: PlayerEvent) {
+        let mut recent = self.recent.lock().unwrap();
+        let event = Arc::new(Event {
+            id: recent.next_id,
+            player_id,
+            event,
+        });
+        recent.next_id += 1;
+        if recent.events.len() == RECENT_EVENTS {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(event.clone());
+        // Sent while locked, so subscribers see every event exactly once. Fails if no one listens.
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribes to the events published from now on, and to the recent events after
+    /// `last_event_id` if given.
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> Subscription {
+        let recent = self.recent.lock().unwrap();
+        let latest_id = recent.next_id - 1;
+        let (resync, missed) = match last_event_id {
+            None => (false, Vec::new()),
+            Some(last_event_id) => {
+                let oldest_id = recent
+                    .events
+                    .front()
+                    .map_or(recent.next_id, |event| event.id);
+                let missed = recent
+                    .events
+                    .iter()
+                    .filter(|event| event.id > last_event_id)
+                    .cloned()
+                    .collect();
+                let resync = last_event_id + 1 < oldest_id || last_event_id > latest_id;
+                (resync, missed)
+            }
+        };
+        Subscription {
+            resync,
+            latest_id,
+            missed,
+            receiver: self.sender.subscribe(),
+        }
+    }
+
+    /// Receives the events of every player published from now on.
+    pub fn listen(&self) -> broadcast::Receiver<Arc<Event>> {
+        self.sender.subscribe()
+    }
+}
+
+/// Events of changes made by the game logic, held back until the changes are committed so clients
+/// never see changes that were rolled back.
+#[derive(Debug, Default)]
+#[must_use = "The events are lost unless the outbox is published."]
+pub struct Outbox {
+    events: Vec<(PlayerId, PlayerEvent)>,
+}
+
+impl Outbox {
+    pub fn push(&mut self, player_id: PlayerId, event: PlayerEvent) {
+        self.events.push((player_id, event));
+    }
+
+    /// Publishes the events and records them in the metrics. Must only be called once the
+    /// transaction making the changes has been committed.
+    pub fn publish(self, bus: &EventBus) {
+        for (player_id, event) in self.events {
+            metrics().record_event(&event);
+            bus.publish(player_id, event);
+        }
+    }
+}
+
+/// Server-Sent Events stream of the events of a player. The stream ends once `shutdown` is
+/// cancelled.
+pub fn player_stream(
+    player_id: PlayerId,
+    subscription: Subscription,
+    shutdown: CancellationToken,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    let Subscription {
+        resync: needs_resync,
+        latest_id,
+        missed,
+        receiver,
+    } = subscription;
+    let backlog: VecDeque<Bytes> = if needs_resync {
+        VecDeque::from([resync(Some(latest_id))])
+    } else {
+        missed
+            .iter()
+            .filter(|event| event.player_id == player_id)
+            .map(|event| event.to_sse())
+            .collect()
+    };
+    let mut keep_alive = time::interval_at(
+        time::Instant::now() + KEEP_ALIVE_INTERVAL,
+        KEEP_ALIVE_INTERVAL,
+    );
+    keep_alive.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    stream::unfold(
+        (backlog, receiver, keep_alive, shutdown),
+        move |(mut backlog, mut receiver, mut keep_alive, shutdown)| async move {
+            if shutdown.is_cancelled() {
+                return None;
+            }
+            let bytes = match backlog.pop_front() {
+                Some(bytes) => bytes,
+                None => loop {
+                    tokio::select! {
+                        _ = shutdown.cancelled() => return None,
+                        received = receiver.recv() => match received {
+                            Ok(event) if event.player_id == player_id => break event.to_sse(),
+                            Ok(_) => {}
+                            Err(RecvError::Lagged(_)) => break resync(None),
+                            Err(RecvError::Closed) => return None,
+                        },
+                        _ = keep_alive.tick() => break Bytes::from_static(b": keep-alive\n\n"),
+                    }
+                },
+            };
+            Some((Ok(bytes), (backlog, receiver, keep_alive, shutdown)))
+        },
+    )
+}
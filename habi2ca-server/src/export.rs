@@ -0,0 +1,258 @@
+use std::{collections::BTreeMap, fmt, fs, io, path::Path, str::FromStr};
+
+use sea_orm::sqlx::{
+    self,
+    query::Query,
+    sqlite::{SqliteArguments, SqliteConnectOptions, SqliteConnection},
+    Connection, Row, Sqlite, TypeInfo, ValueRef,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("Database error while exporting or importing data.")]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to read or write export file.")]
+    Io(#[from] io::Error),
+    #[error("Failed to serialize or deserialize export.")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A single SQLite value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// The data of every table in a database, independent of the schema version.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Export {
+    pub tables: BTreeMap<String, Table>,
+}
+
+/// Names of the tables holding data, i.e. excluding SQLite's and the migrator's own tables.
+pub async fn data_tables(connection: &mut SqliteConnection) -> Result<Vec<String>, sqlx::Error> {
+    Ok(sqlx::query(
+        "SELECT name FROM main.sqlite_master \
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'seaql_migrations' \
+         ORDER BY name",
+    )
+    .fetch_all(connection)
+    .await?
+    .iter()
+    .map(|row| row.get(0))
+    .collect())
+}
+
+pub async fn table_columns(
+    connection: &mut SqliteConnection,
+    table: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    Ok(
+        sqlx::query("SELECT name FROM pragma_table_info(?1, 'main')")
+            .bind(table)
+            .fetch_all(connection)
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect(),
+    )
+}
+
+async fn connect(
+    database_path: &Path,
+    foreign_keys: bool,
+) -> Result<SqliteConnection, sqlx::Error> {
+    let options = SqliteConnectOptions::from_str(&format!("sqlite:{}", database_path.display()))?
+        .create_if_missing(false)
+        .foreign_keys(foreign_keys);
+    SqliteConnection::connect_with(&options).await
+}
+
+fn bind<'q>(
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    value: &'q Value,
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    match value {
+        Value::Null => query.bind(None::<i64>),
+        Value::Integer(value) => query.bind(value),
+        Value::Real(value) => query.bind(value),
+        Value::Text(value) => query.bind(value),
+        Value::Blob(value) => query.bind(value),
+    }
+}
+
+impl Export {
+    /// Reads all data from the database.
+    pub async fn read(database_path: &Path) -> Result<Self, ExportError> {
+        let mut connection = connect(database_path, true).await?;
+        let mut tables = BTreeMap::new();
+        for name in data_tables(&mut connection).await? {
+            let columns = table_columns(&mut connection, &name).await?;
+            let rows = sqlx::query(&format!("SELECT * FROM \"{name}\""))
+                .fetch_all(&mut connection)
+                .await?
+                .iter()
+                .map(|row| {
+                    (0..row.len())
+                        .map(|index| {
+                            let raw = row.try_get_raw(index)?;
+                            if raw.is_null() {
+                                return Ok(Value::Null);
+                            }
+                            Ok(match raw.type_info().name() {
+                                "INTEGER" => Value::Integer(row.try_get_unchecked(index)?),
+                                "REAL" => Value::Real(row.try_get_unchecked(index)?),
+                                "BLOB" => Value::Blob(row.try_get_unchecked(index)?),
+                                _ => Value::Text(row.try_get_unchecked(index)?),
+                            })
+                        })
+                        .collect::<Result<Vec<_>, sqlx::Error>>()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            tables.insert(name, Table { columns, rows });
+        }
+        connection.close().await?;
+        Ok(Self { tables })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ExportError> {
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Inserts the data into a freshly migrated database, which may have a newer schema than the one
+    /// the data was exported from. Columns that no longer exist are dropped.
+    ///
+    /// Rows that cannot be inserted are skipped and recorded in the report instead of failing the
+    /// import, so that every problem is reported at once.
+    pub async fn import(&self, database_path: &Path) -> Result<ImportReport, ExportError> {
+        // Tables are filled in arbitrary order, so foreign keys are checked once all are filled.
+        let mut connection = connect(database_path, false).await?;
+        let target_tables = data_tables(&mut connection).await?;
+        let mut report = ImportReport::default();
+        let mut transaction = connection.begin().await?;
+
+        for (name, table) in &self.tables {
+            let mut table_report = TableImport {
+                name: name.clone(),
+                exported: table.rows.len(),
+                imported: 0,
+            };
+            if !target_tables.contains(name) {
+                if !table.rows.is_empty() {
+                    report
+                        .problems
+                        .push(format!("Table '{name}' no longer exists."));
+                }
+                report.tables.push(table_report);
+                continue;
+            }
+
+            // Replace any rows the migrations seeded the table with.
+            sqlx::query(&format!("DELETE FROM \"{name}\""))
+                .execute(&mut *transaction)
+                .await?;
+            let target_columns = table_columns(&mut transaction, name).await?;
+            let (kept, dropped): (Vec<usize>, Vec<usize>) = (0..table.columns.len())
+                .partition(|&index| target_columns.contains(&table.columns[index]));
+            for index in dropped {
+                let has_data = table.rows.iter().any(|row| row[index] != Value::Null);
+                if has_data {
+                    report.problems.push(format!(
+                        "Column '{}' of table '{name}' no longer exists.",
+                        table.columns[index]
+                    ));
+                }
+            }
+
+            let columns = kept
+                .iter()
+                .map(|&index| format!("\"{}\"", table.columns[index]))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let placeholders = vec!["?"; kept.len()].join(", ");
+            let statement = format!("INSERT INTO \"{name}\" ({columns}) VALUES ({placeholders})");
+            for row in &table.rows {
+                let query = kept.iter().fold(sqlx::query(&statement), |query, &index| {
+                    bind(query, &row[index])
+                });
+                match query.execute(&mut *transaction).await {
+                    Ok(_) => table_report.imported += 1,
+                    Err(error) => report
+                        .problems
+                        .push(format!("Failed to import row of table '{name}': {error}")),
+                }
+            }
+            report.tables.push(table_report);
+        }
+
+        let violations = sqlx::query("PRAGMA foreign_key_check")
+            .fetch_all(&mut *transaction)
+            .await?;
+        for violation in violations {
+            let table: String = violation.get(0);
+            let parent: String = violation.get(2);
+            report.problems.push(format!(
+                "A row of table '{table}' refers to a missing row of table '{parent}'."
+            ));
+        }
+
+        transaction.commit().await?;
+        connection.close().await?;
+        Ok(report)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableImport {
+    pub name: String,
+    pub exported: usize,
+    pub imported: usize,
+}
+
+/// Outcome of [`Export::import`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub tables: Vec<TableImport>,
+    pub problems: Vec<String>,
+}
+
+impl ImportReport {
+    /// Whether every exported row and value made it into the new database.
+    pub fn is_lossless(&self) -> bool {
+        self.problems.is_empty()
+            && self
+                .tables
+                .iter()
+                .all(|table| table.imported == table.exported)
+    }
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for table in &self.tables {
+            writeln!(
+                f,
+                "{}: imported {} of {} rows",
+                table.name, table.imported, table.exported
+            )?;
+        }
+        for problem in &self.problems {
+            writeln!(f, "{problem}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use actix_files::NamedFile;
+use actix_web::{
+    http::{
+        header::{self, AcceptEncoding, ContentEncoding, Encoding, Header},
+        Method,
+    },
+    web, HttpRequest, HttpResponse,
+};
+
+use crate::state::State;
+
+/// Build output whose file names contain a hash of their content, so they never change.
+const IMMUTABLE_DIR: &str = "_app/immutable";
+/// Page served for every path that is not a file, so the frontend's router can handle it.
+const FALLBACK_PAGE: &str = "index.html";
+
+/// Compressed variants that may be stored next to a file, e.g. `index.html.br`, in order of
+/// preference.
+const PRECOMPRESSED: &[(ContentEncoding, &str)] = &[
+    (ContentEncoding::Brotli, "br"),
+    (ContentEncoding::Gzip, "gz"),
+];
+
+/// Finds the file in `static_dir` to serve for a request path. Paths may leave out `.html` or
+/// `/index.html`, like the pages of a static frontend build.
+///
+/// Returns `None` for paths that could leave `static_dir` or that refer to hidden files.
+fn resolve(static_dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut file = static_dir.to_path_buf();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        if segment.starts_with('.') || segment.contains('\\') {
+            return None;
+        }
+        file.push(segment);
+    }
+    let mut html = file.clone().into_os_string();
+    html.push(".html");
+    [file.clone(), PathBuf::from(html), file.join("index.html")]
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+}
+
+/// Whether a path that is not a file should get the fallback page. Missing assets, like scripts
+/// from an older build, get a 404 instead of a page that cannot be parsed as the asset.
+fn is_page(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    !name.contains('.') && !path.trim_start_matches('/').starts_with("_app/")
+}
+
+/// Opens `file`, or a precompressed variant of it if the client accepts one.
+fn open(request: &HttpRequest, file: &Path) -> io::Result<NamedFile> {
+    let variants: Vec<(ContentEncoding, PathBuf)> = PRECOMPRESSED
+        .iter()
+        .map(|&(encoding, extension)| {
+            let mut variant = file.to_path_buf().into_os_string();
+            variant.push(".");
+            variant.push(extension);
+            (encoding, PathBuf::from(variant))
+        })
+        .filter(|(_, variant)| variant.is_file())
+        .collect();
+
+    let supported: Vec<Encoding> = variants
+        .iter()
+        .map(|&(encoding, _)| Encoding::Known(encoding))
+        .chain([Encoding::identity()])
+        .collect();
+    let accepted = AcceptEncoding::parse(request)
+        .ok()
+        .and_then(|accept| accept.negotiate(supported.iter()));
+
+    let named_file = match variants
+        .into_iter()
+        .find(|(encoding, _)| accepted == Some(Encoding::Known(*encoding)))
+    {
+        // The content type is taken from the name of the uncompressed file.
+        Some((encoding, variant)) => {
+            NamedFile::from_file(File::open(variant)?, file)?.set_content_encoding(encoding)
+        }
+        None => NamedFile::open(file)?,
+    };
+    Ok(named_file.disable_content_disposition())
+}
+
+/// Serves the frontend build in the static directory for every request outside the API.
+///
+/// Hashed assets are cached forever, while everything else must be revalidated, so a new build is
+/// picked up on the next page load.
+pub async fn serve(request: HttpRequest, state: web::Data<State>) -> HttpResponse {
+    let path = request.path();
+    let is_api = path
+        .strip_prefix("/api")
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+    let Some(static_dir) = state.static_dir() else {
+        return HttpResponse::NotFound().finish();
+    };
+    if is_api || !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let file = match resolve(static_dir, path) {
+        Some(file) => file,
+        None if is_page(path) => static_dir.join(FALLBACK_PAGE),
+        None => return HttpResponse::NotFound().finish(),
+    };
+    let named_file = match open(&request, &file) {
+        Ok(named_file) => named_file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return HttpResponse::NotFound().finish()
+        }
+        Err(error) => {
+            ::tracing::error!("Failed to open static file {}: {error}", file.display());
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let cache_control = if file.starts_with(static_dir.join(IMMUTABLE_DIR)) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+    let mut response = named_file.into_response(&request);
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static(cache_control),
+    );
+    headers.insert(
+        header::VARY,
+        header::HeaderValue::from_static("Accept-Encoding"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use actix_web::{
+        dev::ServiceResponse,
+        http::{header, StatusCode},
+        test::{self as actix_test, TestRequest},
+        web,
+    };
+
+    use crate::{
+        start::{create_app, create_app_with_state},
+        state::State,
+        test_utils,
+    };
+
+    fn header_value<B>(response: &ServiceResponse<B>, name: header::HeaderName) -> &str {
+        response.headers().get(name).unwrap().to_str().unwrap()
+    }
+
+    fn write(dir: &Path, path: &str, contents: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn serves_frontend() {
+        let static_dir = tempfile::tempdir().unwrap();
+        let dir = static_dir.path();
+        write(dir, "index.html", "index");
+        write(dir, "about.html", "about");
+        write(dir, "_app/immutable/app.123.js", "app");
+        write(dir, "_app/immutable/app.123.js.br", "app brotli");
+        write(dir, "_app/immutable/app.123.js.gz", "app gzip");
+        write(dir, ".env", "secret");
+
+        let database = test_utils::setup_database().await;
+        let state = State::new(database, None).with_static_dir(Some(dir.to_path_buf()));
+        let app = actix_test::init_service(create_app_with_state(web::Data::new(state))).await;
+
+        let get = |uri: &str| TestRequest::get().uri(uri);
+        let body = |response| async { actix_test::read_body(response).await };
+
+        let response = actix_test::call_service(&app, get("/").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header_value(&response, header::CACHE_CONTROL), "no-cache");
+        assert_eq!(body(response).await, "index");
+
+        let response = actix_test::call_service(&app, get("/about").to_request()).await;
+        assert_eq!(body(response).await, "about");
+
+        // Client side routes get the fallback page.
+        let response = actix_test::call_service(&app, get("/players/1/tasks").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, "index");
+
+        let response =
+            actix_test::call_service(&app, get("/_app/immutable/app.123.js").to_request()).await;
+        assert_eq!(
+            header_value(&response, header::CACHE_CONTROL),
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(header_value(&response, header::VARY), "Accept-Encoding");
+        assert!(header_value(&response, header::CONTENT_TYPE).contains("javascript"));
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(body(response).await, "app");
+
+        let response = actix_test::call_service(
+            &app,
+            get("/_app/immutable/app.123.js")
+                .insert_header((header::ACCEPT_ENCODING, "gzip, br"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(header_value(&response, header::CONTENT_ENCODING), "br");
+        assert!(header_value(&response, header::CONTENT_TYPE).contains("javascript"));
+        assert_eq!(body(response).await, "app brotli");
+
+        let response = actix_test::call_service(
+            &app,
+            get("/_app/immutable/app.123.js")
+                .insert_header((header::ACCEPT_ENCODING, "gzip"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(header_value(&response, header::CONTENT_ENCODING), "gzip");
+        assert_eq!(body(response).await, "app gzip");
+
+        for uri in [
+            "/_app/immutable/missing.js",
+            "/favicon.png",
+            "/.env",
+            "/../index.html",
+        ] {
+            let response = actix_test::call_service(&app, get(uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+        // Unknown API routes are never answered with the frontend.
+        let response = actix_test::call_service(&app, get("/api/missing").to_request()).await;
+        assert!(!response.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn disabled_without_static_dir() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+        let response =
+            actix_test::call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
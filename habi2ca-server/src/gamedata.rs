@@ -0,0 +1,192 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use sea_orm::DatabaseConnection;
+use thiserror::Error;
+use tracing::info;
+
+use crate::logic::level::Level;
+
+const LEVELS_FILE: &str = "levels.json";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum GamedataError {
+    #[error("At least one level must be defined.")]
+    NoLevels,
+    #[error("XP requirement {xp_requirement} of level {level} must be finite and positive.")]
+    InvalidXpRequirement { level: usize, xp_requirement: f64 },
+    #[error("XP requirement of level {level} is lower than that of the level before it.")]
+    DecreasingXpRequirement { level: usize },
+}
+
+/// Static game data that is synced into the database at startup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gamedata {
+    /// XP requirements of the levels, starting at level 1.
+    pub levels: Vec<f64>,
+}
+
+impl Gamedata {
+    /// Game data compiled into the binary from the repository's `gamedata` directory.
+    pub fn embedded() -> Result<Self> {
+        Self::parse(include_str!("../../gamedata/levels.json"))
+            .context("Embedded game data is invalid.")
+    }
+
+    /// Loads game data from the files in `dir`.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let levels_path = dir.join(LEVELS_FILE);
+        let levels = fs::read_to_string(&levels_path)
+            .with_context(|| format!("Failed to read '{}'.", levels_path.display()))?;
+        Self::parse(&levels).with_context(|| format!("Invalid game data in '{}'.", dir.display()))
+    }
+
+    fn parse(levels: &str) -> Result<Self> {
+        let gamedata = Self {
+            levels: serde_json::from_str(levels).context("Failed to parse levels.")?,
+        };
+        gamedata.validate()?;
+        Ok(gamedata)
+    }
+
+    pub fn validate(&self) -> Result<(), GamedataError> {
+        if self.levels.is_empty() {
+            return Err(GamedataError::NoLevels);
+        }
+        for (index, &xp_requirement) in self.levels.iter().enumerate() {
+            let level = index + 1;
+            if !xp_requirement.is_finite() || xp_requirement <= 0.0 {
+                return Err(GamedataError::InvalidXpRequirement {
+                    level,
+                    xp_requirement,
+                });
+            }
+            if index > 0 && xp_requirement < self.levels[index - 1] {
+                return Err(GamedataError::DecreasingXpRequirement { level });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reconciles the database with the game data and logs what changed.
+    pub async fn sync(self, database: &DatabaseConnection) -> Result<()> {
+        let diff = Level::sync_curve(database, self.levels)
+            .await
+            .context("Failed to sync levels.")?;
+        if diff.is_empty() {
+            info!("Game data is up to date.");
+            return Ok(());
+        }
+        for level_id in &diff.added {
+            info!("Added level {level_id}.");
+        }
+        for (level_id, old, new) in &diff.changed {
+            info!("Changed XP requirement of level {level_id} from {old} to {new}.");
+        }
+        info!(
+            "Recalculated the level of {} players.",
+            diff.players_recalculated
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use habi2ca_database::{level::LevelId, player};
+    use sea_orm::{sea_query::Expr, ConnectionTrait, EntityTrait};
+
+    use super::{Gamedata, GamedataError};
+    use crate::{
+        logic::{level::Level, player::Player, xp_event::XpSource},
+        test_utils,
+    };
+
+    #[test]
+    fn validate() {
+        assert!(Gamedata::embedded().is_ok());
+
+        let gamedata = |levels: &[f64]| Gamedata {
+            levels: levels.to_vec(),
+        };
+        assert_eq!(gamedata(&[]).validate(), Err(GamedataError::NoLevels));
+        assert_eq!(
+            gamedata(&[10.0, 0.0]).validate(),
+            Err(GamedataError::InvalidXpRequirement {
+                level: 2,
+                xp_requirement: 0.0
+            })
+        );
+        assert_eq!(
+            gamedata(&[10.0, 20.0, 15.0]).validate(),
+            Err(GamedataError::DecreasingXpRequirement { level: 3 })
+        );
+        assert_eq!(gamedata(&[10.0, 10.0]).validate(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn sync() {
+        let database = test_utils::setup_database().await;
+
+        // The initial migration seeds the embedded game data.
+        let diff = Level::sync_curve(&database, Gamedata::embedded().unwrap().levels)
+            .await
+            .unwrap();
+        assert!(diff.is_empty());
+
+        let mut player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let first_levels: f64 = Gamedata::embedded().unwrap().levels[..2].iter().sum();
+        let _ = player
+            .add_xp(&database, first_levels + 10.0, XpSource::Manual)
+            .await
+            .unwrap();
+        assert_eq!(player.level(), LevelId(3));
+
+        let diff = Level::sync_curve(&database, vec![10.0, 20.0])
+            .await
+            .unwrap();
+        assert_eq!(diff.players_recalculated, 1);
+        assert_eq!(diff.added, vec![]);
+        assert_eq!(diff.changed.len(), 6);
+
+        // 150 xp is 10 + 20 + 30 + 40 + 50 on the new curve.
+        let player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(player.level(), LevelId(6));
+        assert_eq!(player.xp(), 0.0);
+        assert_eq!(player.xp_requirement(), 60.0);
+
+        let diff = Level::sync_curve(&database, vec![10.0, 20.0])
+            .await
+            .unwrap();
+        assert!(diff.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sync_player_with_negative_xp() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        // Negative xp is rejected by the schema, but may still be found in databases that were
+        // edited by hand or written without checking constraints.
+        database
+            .execute_unprepared("PRAGMA ignore_check_constraints = ON")
+            .await
+            .unwrap();
+        player::Entity::update_many()
+            .col_expr(player::Column::Xp, Expr::value(-5.0))
+            .exec(&database)
+            .await
+            .unwrap();
+
+        let diff = Level::sync_curve(&database, vec![10.0, 20.0])
+            .await
+            .unwrap();
+        assert_eq!(diff.players_recalculated, 1);
+        let player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(player.level(), LevelId(1));
+        assert_eq!(player.xp(), 0.0);
+    }
+}
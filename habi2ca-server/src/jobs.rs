@@ -0,0 +1,98 @@
+use std::{sync::Arc, time::Duration};
+
+use sea_orm::DatabaseConnection;
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+use tracing::{error, info};
+
+use crate::{backup::Backups, events::EventBus, logic::daily::Daily, state::MaintenanceLock};
+
+/// Tells jobs to stop once a value is sent or the sender is dropped. Runs in progress are finished
+/// first.
+pub type Shutdown = watch::Receiver<()>;
+
+/// Waits for the next tick of `interval`. Returns `false` if the job should stop instead.
+async fn next_run(interval: &mut time::Interval, shutdown: &mut Shutdown) -> bool {
+    tokio::select! {
+        _ = interval.tick() => true,
+        _ = shutdown.changed() => false,
+    }
+}
+
+/// Periodically rolls over the dailies of every player whose day has ended. Days end at midnight
+/// UTC.
+pub fn spawn_daily_rollover(
+    database: DatabaseConnection,
+    events: Arc<EventBus>,
+    maintenance: MaintenanceLock,
+    period: Duration,
+    mut shutdown: Shutdown,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        while next_run(&mut interval, &mut shutdown).await {
+            let _guard = maintenance.read().await;
+            let today = chrono::Utc::now().date_naive();
+            match Daily::rollover_all(&database, &events, today).await {
+                Ok(0) => {}
+                Ok(count) => info!("Rolled over dailies of {count} players to {today}."),
+                Err(error) => error!("Failed to roll over dailies: {error}"),
+            }
+        }
+    })
+}
+
+/// Periodically backs up the database and prunes old backups.
+/// The first backup is made one period after the job is started.
+pub fn spawn_scheduled_backups(
+    database: DatabaseConnection,
+    backups: Backups,
+    maintenance: MaintenanceLock,
+    period: Duration,
+    mut shutdown: Shutdown,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        while next_run(&mut interval, &mut shutdown).await {
+            let _guard = maintenance.read().await;
+            match backups.create(&database).await {
+                Ok(backup) => info!("Created scheduled backup '{}'.", backup.name),
+                Err(error) => error!("Failed to create scheduled backup: {error}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::{sync::watch, time};
+
+    use super::spawn_daily_rollover;
+    use crate::{events::EventBus, state::MaintenanceLock, test_utils};
+
+    #[tokio::test]
+    async fn stops_on_shutdown() {
+        let database = test_utils::setup_database().await;
+        let (stop, shutdown) = watch::channel(());
+        let job = spawn_daily_rollover(
+            database,
+            Arc::new(EventBus::new()),
+            MaintenanceLock::default(),
+            Duration::from_secs(60 * 60),
+            shutdown,
+        );
+
+        drop(stop);
+        time::timeout(Duration::from_secs(10), job)
+            .await
+            .expect("The job did not stop.")
+            .unwrap();
+    }
+}
@@ -1,4 +1,31 @@
+pub mod account;
+pub mod api_token;
+pub mod daily;
 pub mod habit;
 pub mod level;
+pub mod party;
 pub mod player;
 pub mod task;
+pub mod xp_event;
+
+use sea_orm::{DbErr, TransactionError};
+
+/// Collapses the error of a `TransactionTrait::transaction` call into the error type used inside
+/// the transaction, so callers don't have to deal with the nested `TransactionError`.
+fn flatten_transaction_error<E>(error: TransactionError<E>) -> E
+where
+    E: std::error::Error + From<DbErr>,
+{
+    match error {
+        TransactionError::Connection(error) => error.into(),
+        TransactionError::Transaction(error) => error,
+    }
+}
+
+/// Returns whether the error was caused by a violated foreign key constraint.
+fn is_foreign_key_violation(error: &DbErr) -> bool {
+    matches!(
+        error.sql_err(),
+        Some(sea_orm::SqlErr::ForeignKeyConstraintViolation(_))
+    )
+}
@@ -0,0 +1,281 @@
+use std::sync::LazyLock;
+
+use argon2::{
+    password_hash::{self, rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use chrono::{TimeDelta, Utc};
+use habi2ca_database::{
+    account::{self, AccountId, Model},
+    player, session,
+};
+use rand_core::RngCore;
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    PaginatorTrait, QueryFilter, Set, SqlErr, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{debug_span, info, Instrument};
+
+use super::flatten_transaction_error;
+
+#[derive(Debug, Error)]
+pub enum AccountError {
+    #[error("No account with id {0} exists.")]
+    NotFound(AccountId),
+    #[error("The username '{0}' is already taken.")]
+    UsernameTaken(String),
+    #[error("Invalid username: {0}")]
+    InvalidUsername(&'static str),
+    #[error("Invalid password: {0}")]
+    InvalidPassword(&'static str),
+    #[error("Invalid username or password.")]
+    InvalidCredentials,
+    #[error("Failed to hash password.")]
+    PasswordHash(#[source] password_hash::Error),
+    #[error("Database error while accessing accounts.")]
+    Database(#[from] DbErr),
+}
+
+/// Credentials used to create an account and to log in.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Hash of a random password, verified when logging in as an unknown user so that it takes as long
+/// as a wrong password and does not reveal which usernames exist.
+static DUMMY_PASSWORD_HASH: LazyLock<String> = LazyLock::new(|| {
+    Argon2::default()
+        .hash_password(
+            generate_token().as_bytes(),
+            &SaltString::generate(&mut OsRng),
+        )
+        .expect("Hashing a password with a generated salt cannot fail.")
+        .to_string()
+});
+
+const MAX_USERNAME_LENGTH: usize = 64;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+fn validate_credentials(credentials: &Credentials) -> Result<(), AccountError> {
+    let username = credentials.username.as_str();
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        return Err(AccountError::InvalidUsername(
+            "usernames must be between 1 and 64 characters long",
+        ));
+    }
+    if username.chars().any(char::is_whitespace) {
+        return Err(AccountError::InvalidUsername(
+            "usernames may not contain whitespace",
+        ));
+    }
+    if credentials.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AccountError::InvalidPassword(
+            "passwords must be at least 8 characters long",
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Account {
+    #[serde(flatten)]
+    pub(super) model: Model,
+}
+
+impl Account {
+    /// Creates an account with the given credentials.
+    ///
+    /// The first account also gets the players created before accounts existed, which have no
+    /// owner and are inaccessible until then.
+    pub async fn create(
+        db: &DatabaseConnection,
+        credentials: Credentials,
+    ) -> Result<Self, AccountError> {
+        validate_credentials(&credentials)?;
+        let Credentials { username, password } = credentials;
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(AccountError::PasswordHash)?
+            .to_string();
+        let model = db
+            .transaction::<_, Model, AccountError>(|txn| {
+                Box::pin(async move {
+                    let model = account::Entity::insert(account::ActiveModel {
+                        username: Set(username.clone()),
+                        password_hash: Set(password_hash),
+                        created_at: Set(Utc::now()),
+                        ..Default::default()
+                    })
+                    .exec_with_returning(txn)
+                    .await
+                    .map_err(|error| match error.sql_err() {
+                        Some(SqlErr::UniqueConstraintViolation(_)) => {
+                            AccountError::UsernameTaken(username)
+                        }
+                        _ => error.into(),
+                    })?;
+
+                    if account::Entity::find().count(txn).await? == 1 {
+                        let claimed = player::Entity::update_many()
+                            .col_expr(player::Column::AccountId, Expr::value(model.id))
+                            .filter(player::Column::AccountId.is_null())
+                            .exec(txn)
+                            .await?
+                            .rows_affected;
+                        if claimed > 0 {
+                            info!(
+                                "Assigned {claimed} players without an account to the first \
+                                 account, '{}'.",
+                                model.username
+                            );
+                        }
+                    }
+                    Ok(model)
+                })
+            })
+            .instrument(debug_span!("transaction"))
+            .await
+            .map_err(flatten_transaction_error)?;
+        Ok(Self { model })
+    }
+
+    pub async fn from_id(db: &impl ConnectionTrait, id: AccountId) -> Result<Self, AccountError> {
+        let model = account::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(AccountError::NotFound(id))?;
+        Ok(Self { model })
+    }
+
+    /// Finds the account with the given credentials.
+    pub async fn authenticate(
+        db: &impl ConnectionTrait,
+        credentials: &Credentials,
+    ) -> Result<Self, AccountError> {
+        let model = account::Entity::find()
+            .filter(account::Column::Username.eq(credentials.username.as_str()))
+            .one(db)
+            .await?;
+        let Some(model) = model else {
+            let dummy_hash = PasswordHash::new(&DUMMY_PASSWORD_HASH)
+                .expect("The dummy password hash should be valid.");
+            // Fails, since the dummy password is random.
+            let _ = Argon2::default().verify_password(credentials.password.as_bytes(), &dummy_hash);
+            return Err(AccountError::InvalidCredentials);
+        };
+        let password_hash =
+            PasswordHash::new(&model.password_hash).map_err(AccountError::PasswordHash)?;
+        Argon2::default()
+            .verify_password(credentials.password.as_bytes(), &password_hash)
+            .map_err(|error| match error {
+                password_hash::Error::Password => AccountError::InvalidCredentials,
+                error => AccountError::PasswordHash(error),
+            })?;
+        Ok(Self { model })
+    }
+
+    pub fn id(&self) -> AccountId {
+        self.model.id
+    }
+}
+
+/// How long a session stays valid after logging in.
+pub const SESSION_DURATION: TimeDelta = TimeDelta::days(30);
+
+/// Generates a random secret token.
+pub(super) fn generate_token() -> String {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes a token for storage. Tokens are random, so a fast hash is sufficient.
+pub(super) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// A login session. Sessions are identified by a random token only known to the client.
+pub struct Session;
+
+impl Session {
+    /// Starts a new session for the account and returns its token.
+    pub async fn create(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+    ) -> Result<String, AccountError> {
+        let token = generate_token();
+        Self::insert(db, account_id, &token).await?;
+        Ok(token)
+    }
+
+    /// Starts a session for the account with a known token.
+    #[cfg(test)]
+    pub async fn create_with_token(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+        token: &str,
+    ) -> Result<(), AccountError> {
+        Self::insert(db, account_id, token).await
+    }
+
+    async fn insert(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+        token: &str,
+    ) -> Result<(), AccountError> {
+        let now = Utc::now();
+        session::Entity::insert(session::ActiveModel {
+            account_id: Set(account_id),
+            token_hash: Set(hash_token(token)),
+            created_at: Set(now),
+            expires_at: Set(now + SESSION_DURATION),
+            ..Default::default()
+        })
+        .exec_without_returning(db)
+        .await?;
+        Ok(())
+    }
+
+    /// The account the session with the given token belongs to, if the session exists and has
+    /// not expired.
+    pub async fn authenticate(
+        db: &impl ConnectionTrait,
+        token: &str,
+    ) -> Result<Option<AccountId>, AccountError> {
+        let Some(model) = session::Entity::find()
+            .filter(session::Column::TokenHash.eq(hash_token(token)))
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        if model.expires_at <= Utc::now() {
+            session::Entity::delete_by_id(model.id).exec(db).await?;
+            return Ok(None);
+        }
+        Ok(Some(model.account_id))
+    }
+
+    /// Ends the session with the given token. Does nothing if no such session exists.
+    pub async fn delete(db: &impl ConnectionTrait, token: &str) -> Result<(), AccountError> {
+        session::Entity::delete_many()
+            .filter(session::Column::TokenHash.eq(hash_token(token)))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use chrono::Utc;
+use habi2ca_database::{
+    account::AccountId,
+    api_token::{self, ApiTokenId, Model, TokenScope},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::account::{generate_token, hash_token};
+
+#[derive(Debug, Error)]
+pub enum ApiTokenError {
+    #[error("No API token with id {0} exists.")]
+    NotFound(ApiTokenId),
+    #[error("Invalid API token name: {0}")]
+    InvalidName(&'static str),
+    #[error("Database error while accessing API tokens.")]
+    Database(#[from] DbErr),
+}
+
+/// Prefix of all API tokens, making them recognizable in scripts and secret scanners.
+const TOKEN_PREFIX: &str = "h2c_";
+
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ApiTokenData {
+    /// Describes what the token is used for.
+    pub name: String,
+    #[serde(default)]
+    pub scope: TokenScope,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiToken {
+    #[serde(flatten)]
+    pub(super) model: Model,
+}
+
+/// A newly created API token together with its secret, which cannot be retrieved later.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
+}
+
+impl ApiToken {
+    pub async fn create(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+        data: ApiTokenData,
+    ) -> Result<CreatedApiToken, ApiTokenError> {
+        let ApiTokenData { name, scope } = data;
+        if name.trim().is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(ApiTokenError::InvalidName(
+                "names must be between 1 and 100 characters long",
+            ));
+        }
+        let token = format!("{TOKEN_PREFIX}{}", generate_token());
+        let model = api_token::Entity::insert(api_token::ActiveModel {
+            account_id: Set(account_id),
+            name: Set(name),
+            token_hash: Set(hash_token(&token)),
+            scope: Set(scope),
+            created_at: Set(Utc::now()),
+            last_used_at: Set(None),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+        Ok(CreatedApiToken {
+            api_token: Self { model },
+            token,
+        })
+    }
+
+    /// Gets a token of the account. Tokens of other accounts are reported as not found.
+    pub async fn from_id(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+        id: ApiTokenId,
+    ) -> Result<Self, ApiTokenError> {
+        let model = api_token::Entity::find_by_id(id)
+            .filter(api_token::Column::AccountId.eq(account_id))
+            .one(db)
+            .await?
+            .ok_or(ApiTokenError::NotFound(id))?;
+        Ok(Self { model })
+    }
+
+    pub async fn account_tokens(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+    ) -> Result<Vec<Self>, ApiTokenError> {
+        let models = api_token::Entity::find()
+            .filter(api_token::Column::AccountId.eq(account_id))
+            .order_by_asc(api_token::Column::Id)
+            .all(db)
+            .await?;
+        Ok(models.into_iter().map(|model| Self { model }).collect())
+    }
+
+    /// Finds the account and scope of a token and records that the token was used.
+    pub async fn authenticate(
+        db: &impl ConnectionTrait,
+        token: &str,
+    ) -> Result<Option<(AccountId, TokenScope)>, ApiTokenError> {
+        let Some(model) = api_token::Entity::find()
+            .filter(api_token::Column::TokenHash.eq(hash_token(token)))
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let model = api_token::ActiveModel {
+            last_used_at: Set(Some(Utc::now())),
+            ..model.into_active_model()
+        }
+        .update(db)
+        .await?;
+        Ok(Some((model.account_id, model.scope)))
+    }
+
+    /// Revokes the token and returns it.
+    pub async fn revoke(self, db: &impl ConnectionTrait) -> Result<Self, ApiTokenError> {
+        api_token::Entity::delete_by_id(self.model.id)
+            .exec(db)
+            .await?;
+        Ok(self)
+    }
+
+    #[cfg(test)]
+    pub fn id(&self) -> ApiTokenId {
+        self.model.id
+    }
+
+    #[cfg(test)]
+    pub fn last_used_at(&self) -> Option<chrono::DateTime<Utc>> {
+        self.model.last_used_at
+    }
+}
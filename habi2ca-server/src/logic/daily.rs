@@ -0,0 +1,388 @@
+use chrono::NaiveDate;
+use habi2ca_database::{
+    account::AccountId,
+    daily::{self, ActiveModel, DailyId, Model, Schedule},
+    player::{self, PlayerId},
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
+    DbErr, EntityTrait, IntoActiveModel, JoinType, QueryFilter, QuerySelect, RelationTrait,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug_span, warn, Instrument};
+
+use crate::events::{EventBus, Outbox};
+
+use super::{
+    flatten_transaction_error, is_foreign_key_violation,
+    player::{Player, PlayerError},
+    xp_event::XpSource,
+};
+
+#[derive(Debug, Error)]
+pub enum DailyError {
+    #[error("No daily with id {0} exists.")]
+    NotFound(DailyId),
+    #[error("Cannot create daily for player {0} since no such player exists.")]
+    UnknownPlayer(PlayerId),
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(&'static str),
+    #[error("Daily {daily_id} cannot be completed since it is not due on {day}.")]
+    NotDue { daily_id: DailyId, day: NaiveDate },
+    #[error("Failed to update player {player_id}.")]
+    Player {
+        player_id: PlayerId,
+        #[source]
+        source: PlayerError,
+    },
+    #[error("Database error while accessing dailies.")]
+    Database(#[from] DbErr),
+}
+
+fn validate_schedule(schedule: &Schedule) -> Result<(), DailyError> {
+    match schedule {
+        Schedule::Weekly { weekdays } if weekdays.is_empty() => Err(DailyError::InvalidSchedule(
+            "weekly schedules must have at least one weekday",
+        )),
+        Schedule::EveryNDays { interval: 0 } => Err(DailyError::InvalidSchedule(
+            "interval must be at least 1 day",
+        )),
+        Schedule::Monthly { day } if !(1..=31).contains(day) => Err(DailyError::InvalidSchedule(
+            "day of month must be between 1 and 31",
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DailyData {
+    pub player_id: PlayerId,
+    pub name: String,
+    pub description: String,
+    pub schedule: Schedule,
+    /// First day the daily can be due. Defaults to the current day in UTC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<NaiveDate>,
+}
+
+impl DailyData {
+    pub fn into_active_model(self) -> ActiveModel {
+        ActiveModel {
+            player_id: sea_orm::ActiveValue::Set(self.player_id),
+            name: sea_orm::ActiveValue::Set(self.name),
+            description: sea_orm::ActiveValue::Set(self.description),
+            schedule: sea_orm::ActiveValue::Set(self.schedule),
+            start_date: sea_orm::ActiveValue::Set(
+                self.start_date
+                    .unwrap_or_else(|| chrono::Utc::now().date_naive()),
+            ),
+            completed: sea_orm::ActiveValue::Set(false),
+            streak: sea_orm::ActiveValue::Set(0),
+            ..Default::default()
+        }
+    }
+}
+
+/// Changes to the editable fields of a daily. Fields that are `None` are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DailyUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+}
+
+/// XP granted for completing a daily.
+const DAILY_XP: f64 = 1.0;
+/// Damage dealt to the player for each due daily left uncompleted at rollover.
+const DAILY_DAMAGE: f64 = 2.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Daily {
+    #[serde(flatten)]
+    pub(super) model: Model,
+}
+
+impl Daily {
+    pub async fn create(
+        db: &impl ConnectionTrait,
+        daily_data: DailyData,
+    ) -> Result<Self, DailyError> {
+        validate_schedule(&daily_data.schedule)?;
+        let player_id = daily_data.player_id;
+        let model = daily::Entity::insert(daily_data.into_active_model())
+            .exec_with_returning(db)
+            .await
+            .map_err(|error| {
+                if is_foreign_key_violation(&error) {
+                    DailyError::UnknownPlayer(player_id)
+                } else {
+                    error.into()
+                }
+            })?;
+        Ok(Self { model })
+    }
+
+    pub async fn from_id(db: &impl ConnectionTrait, id: DailyId) -> Result<Self, DailyError> {
+        let model = daily::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(DailyError::NotFound(id))?;
+        Ok(Self { model })
+    }
+
+    /// All dailies of the players owned by the account.
+    pub async fn account_dailies(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+    ) -> Result<Vec<Daily>, DailyError> {
+        let models = daily::Entity::find()
+            .join(JoinType::InnerJoin, daily::Relation::Player.def())
+            .filter(player::Column::AccountId.eq(account_id))
+            .all(db)
+            .await?;
+        Ok(models.into_iter().map(|model| Daily { model }).collect())
+    }
+
+    pub async fn player_dailies(
+        db: &impl ConnectionTrait,
+        player_id: PlayerId,
+    ) -> Result<Vec<Daily>, DailyError> {
+        let models = daily::Entity::find()
+            .filter(daily::Column::PlayerId.eq(player_id))
+            .all(db)
+            .await?;
+        Ok(models.into_iter().map(|model| Daily { model }).collect())
+    }
+
+    #[cfg(test)]
+    pub fn id(&self) -> DailyId {
+        self.model.id
+    }
+
+    #[cfg(test)]
+    pub fn completed(&self) -> bool {
+        self.model.completed
+    }
+
+    #[cfg(test)]
+    pub fn streak(&self) -> i64 {
+        self.model.streak
+    }
+
+    pub fn player_id(&self) -> PlayerId {
+        self.model.player_id
+    }
+
+    pub fn is_due(&self, date: NaiveDate) -> bool {
+        self.model.schedule.is_due(self.model.start_date, date)
+    }
+
+    pub async fn update(
+        &mut self,
+        db: &impl ConnectionTrait,
+        update: DailyUpdate,
+    ) -> Result<(), DailyError> {
+        let DailyUpdate {
+            name,
+            description,
+            schedule,
+        } = update;
+        let mut active_model = self.model.clone().into_active_model();
+        if let Some(name) = name {
+            active_model.name = sea_orm::ActiveValue::Set(name);
+        }
+        if let Some(description) = description {
+            active_model.description = sea_orm::ActiveValue::Set(description);
+        }
+        if let Some(schedule) = schedule {
+            validate_schedule(&schedule)?;
+            active_model.schedule = sea_orm::ActiveValue::Set(schedule);
+        }
+        self.model = active_model.update(db).await?;
+        Ok(())
+    }
+
+    /// Deletes the daily from the database and returns it.
+    pub async fn delete(self, db: &impl ConnectionTrait) -> Result<Self, DailyError> {
+        daily::Entity::delete_by_id(self.model.id).exec(db).await?;
+        Ok(self)
+    }
+
+    /// Sets the completion state of the daily and grants or revokes the player's xp accordingly.
+    /// Revoking never takes more xp than the player has left.
+    /// Dailies can only be completed on days they are due, judged by the player's current day.
+    /// The daily is only changed if it is not in that state yet, checked in the same statement as
+    /// the update, so that concurrent requests cannot grant the xp twice.
+    async fn set_completed(
+        &mut self,
+        db: &DatabaseConnection,
+        completed: bool,
+    ) -> Result<Outbox, DailyError> {
+        let daily_id = self.model.id;
+        let player_id = self.model.player_id;
+        let outbox;
+        (self.model, outbox) = db
+            .transaction::<_, (Model, Outbox), DailyError>(|txn| {
+                Box::pin(async move {
+                    let map_player_error = |source| DailyError::Player { player_id, source };
+                    let mut player = Player::from_id(txn, player_id)
+                        .await
+                        .map_err(map_player_error)?;
+                    let daily = Self::from_id(txn, daily_id).await?;
+                    if completed {
+                        // Players from before dailies existed have no current day yet.
+                        let day = player
+                            .last_rollover()
+                            .unwrap_or_else(|| chrono::Utc::now().date_naive());
+                        if !daily.is_due(day) {
+                            return Err(DailyError::NotDue { daily_id, day });
+                        }
+                    }
+
+                    let result = daily::Entity::update_many()
+                        .col_expr(daily::Column::Completed, Expr::value(completed))
+                        .filter(daily::Column::Id.eq(daily_id))
+                        .filter(daily::Column::Completed.eq(!completed))
+                        .exec(txn)
+                        .await?;
+                    let mut model = daily.model;
+                    if result.rows_affected != 1 {
+                        return Ok((model, Outbox::default()));
+                    }
+                    model.completed = completed;
+
+                    let source = XpSource::Daily(daily_id);
+                    let outbox = if completed {
+                        player.add_xp(txn, DAILY_XP, source).await
+                    } else {
+                        player.revoke_xp(txn, DAILY_XP, source).await
+                    }
+                    .map_err(map_player_error)?;
+                    Ok((model, outbox))
+                })
+            })
+            .instrument(debug_span!("transaction"))
+            .await
+            .map_err(flatten_transaction_error)?;
+        Ok(outbox)
+    }
+
+    pub async fn complete(&mut self, db: &DatabaseConnection) -> Result<Outbox, DailyError> {
+        self.set_completed(db, true).await
+    }
+
+    /// Reverts a completion, removing the xp it granted. Does nothing if the daily is not completed.
+    pub async fn uncomplete(&mut self, db: &DatabaseConnection) -> Result<Outbox, DailyError> {
+        self.set_completed(db, false).await
+    }
+
+    /// Ends the player's current day if `today` is later. Days are calendar days in UTC for every
+    /// player.
+    ///
+    /// Every daily that was due on the ending day either extends its streak if it was completed,
+    /// or loses its streak and damages the player if it was not. All dailies are then reset.
+    /// Only the player's current day is evaluated, so days skipped while no rollover ran
+    /// (e.g. while the server was down) are not held against the player.
+    ///
+    /// Returns the events of the rollover, to be published once it is committed, or `None` if no
+    /// rollover happened.
+    pub async fn rollover_player(
+        db: &DatabaseConnection,
+        player_id: PlayerId,
+        today: NaiveDate,
+    ) -> Result<Option<Outbox>, DailyError> {
+        db.transaction::<_, Option<Outbox>, DailyError>(|txn| {
+            Box::pin(async move {
+                let map_player_error = |source| DailyError::Player { player_id, source };
+                let mut player = Player::from_id(txn, player_id)
+                    .await
+                    .map_err(map_player_error)?;
+                let ending_day = match player.last_rollover() {
+                    Some(day) if day >= today => return Ok(None),
+                    Some(day) => Some(day),
+                    // Players from before dailies existed have no current day yet.
+                    None => None,
+                };
+
+                let mut missed = 0;
+                for daily in Self::player_dailies(txn, player_id).await? {
+                    let mut model = daily.model.clone();
+                    if ending_day.is_some_and(|day| daily.is_due(day)) {
+                        if model.completed {
+                            model.streak += 1;
+                        } else {
+                            model.streak = 0;
+                            missed += 1;
+                        }
+                    }
+                    model.completed = false;
+                    ActiveModel {
+                        completed: sea_orm::ActiveValue::Set(model.completed),
+                        streak: sea_orm::ActiveValue::Set(model.streak),
+                        ..model.into_active_model()
+                    }
+                    .update(txn)
+                    .await?;
+                }
+
+                let outbox = if missed > 0 {
+                    player
+                        .take_damage(txn, f64::from(missed) * DAILY_DAMAGE)
+                        .await
+                        .map_err(map_player_error)?
+                } else {
+                    Outbox::default()
+                };
+                player
+                    .set_last_rollover(txn, today)
+                    .await
+                    .map_err(map_player_error)?;
+                Ok(Some(outbox))
+            })
+        })
+        .instrument(debug_span!("transaction"))
+        .await
+        .map_err(flatten_transaction_error)
+    }
+
+    /// Rolls over every player whose current day is before `today`, publishing the events of each
+    /// rollover to `events`.
+    /// Failures are logged and do not stop other players from being rolled over.
+    ///
+    /// Returns the number of players that were rolled over.
+    pub async fn rollover_all(
+        db: &DatabaseConnection,
+        events: &EventBus,
+        today: NaiveDate,
+    ) -> Result<usize, DailyError> {
+        let player_ids: Vec<PlayerId> = player::Entity::find()
+            .select_only()
+            .column(player::Column::Id)
+            .filter(
+                Condition::any()
+                    .add(player::Column::LastRollover.is_null())
+                    .add(player::Column::LastRollover.lt(today)),
+            )
+            .into_tuple()
+            .all(db)
+            .await?;
+
+        let mut rolled_over = 0;
+        for player_id in player_ids {
+            match Self::rollover_player(db, player_id, today).await {
+                Ok(Some(outbox)) => {
+                    outbox.publish(events);
+                    rolled_over += 1;
+                }
+                Ok(None) => {}
+                Err(error) => warn!("Failed to roll over dailies of player {player_id}: {error}"),
+            }
+        }
+        Ok(rolled_over)
+    }
+}
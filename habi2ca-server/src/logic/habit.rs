@@ -1,22 +1,70 @@
-use std::fmt::Display;
-
-use anyhow::{Context, Result};
 use habi2ca_database::{
+    account::AccountId,
     habit::{self, ActiveModel, HabitId, Model},
-    player::PlayerId,
+    player::{self, PlayerId},
 };
 use sea_orm::{
-    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait,
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    IntoActiveModel, JoinType, QueryFilter, QuerySelect, RelationTrait, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug_span, Instrument};
+
+use crate::events::{Outbox, PlayerEvent};
+
+use super::{
+    flatten_transaction_error, is_foreign_key_violation,
+    player::{Player, PlayerError},
+    xp_event::XpSource,
+};
 
-use super::player::Player;
+#[derive(Debug, Error)]
+pub enum HabitError {
+    #[error("No habit with id {0} exists.")]
+    NotFound(HabitId),
+    #[error("Habit {0} is not positive and cannot be incremented.")]
+    NotPositive(HabitId),
+    #[error("Habit {0} is not negative and cannot be decremented.")]
+    NotNegative(HabitId),
+    #[error("Invalid habit direction: {0}")]
+    InvalidDirection(&'static str),
+    #[error("Cannot create habit for player {0} since no such player exists.")]
+    UnknownPlayer(PlayerId),
+    #[error("Failed to update owner of habit {habit_id}.")]
+    Player {
+        habit_id: HabitId,
+        #[source]
+        source: PlayerError,
+    },
+    #[error("Database error while accessing habits.")]
+    Database(#[from] DbErr),
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Rejects habits that can be neither incremented nor decremented.
+fn validate_direction(positive: bool, negative: bool) -> Result<(), HabitError> {
+    if positive || negative {
+        Ok(())
+    } else {
+        Err(HabitError::InvalidDirection(
+            "habits must be positive, negative or both",
+        ))
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct HabitData {
     pub player_id: PlayerId,
     pub name: String,
     pub description: String,
+    #[serde(default = "default_true")]
+    pub positive: bool,
+    #[serde(default)]
+    pub negative: bool,
 }
 
 impl HabitData {
@@ -25,88 +73,190 @@ impl HabitData {
             player_id: sea_orm::ActiveValue::Set(self.player_id),
             name: sea_orm::ActiveValue::Set(self.name),
             description: sea_orm::ActiveValue::Set(self.description),
+            positive: sea_orm::ActiveValue::Set(self.positive),
+            negative: sea_orm::ActiveValue::Set(self.negative),
             ..Default::default()
         }
     }
 }
 
-#[derive(Debug)]
-struct DbError(anyhow::Error);
-
-impl Display for DbError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
+/// Changes to the editable fields of a habit. Fields that are `None` are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HabitUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub positive: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative: Option<bool>,
 }
 
-impl std::error::Error for DbError {}
+/// XP granted for incrementing a positive habit.
+const HABIT_XP: f64 = 1.0;
+/// Damage dealt to the player for decrementing a negative habit.
+const HABIT_DAMAGE: f64 = 1.0;
 
-impl From<anyhow::Error> for DbError {
-    fn from(error: anyhow::Error) -> Self {
-        Self(error)
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Habit {
     #[serde(flatten)]
     pub(super) model: Model,
 }
 
 impl Habit {
-    pub async fn create(db: &impl ConnectionTrait, habit_data: HabitData) -> Result<Self> {
+    pub async fn create(
+        db: &impl ConnectionTrait,
+        habit_data: HabitData,
+    ) -> Result<Self, HabitError> {
+        validate_direction(habit_data.positive, habit_data.negative)?;
+        let player_id = habit_data.player_id;
         let model = habit::Entity::insert(habit_data.into_active_model())
             .exec_with_returning(db)
-            .await?;
+            .await
+            .map_err(|error| {
+                if is_foreign_key_violation(&error) {
+                    HabitError::UnknownPlayer(player_id)
+                } else {
+                    error.into()
+                }
+            })?;
         Ok(Self { model })
     }
 
-    pub async fn from_id(db: &impl ConnectionTrait, id: HabitId) -> Result<Self> {
+    pub async fn from_id(db: &impl ConnectionTrait, id: HabitId) -> Result<Self, HabitError> {
         let model = habit::Entity::find_by_id(id)
             .one(db)
-            .await
-            .with_context(|| format!("Failed to get habit with id {id} from database."))?
-            .with_context(|| format!("No habit with id {id} exists."))?;
+            .await?
+            .ok_or(HabitError::NotFound(id))?;
         Ok(Self { model })
     }
 
-    pub async fn all_habits(db: &DatabaseConnection) -> Result<Vec<Habit>> {
-        let models = habit::Entity::find().all(db).await?;
+    /// All habits of the players owned by the account.
+    pub async fn account_habits(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+    ) -> Result<Vec<Habit>, HabitError> {
+        let models = habit::Entity::find()
+            .join(JoinType::InnerJoin, habit::Relation::Player.def())
+            .filter(player::Column::AccountId.eq(account_id))
+            .all(db)
+            .await?;
         Ok(models.into_iter().map(|model| Habit { model }).collect())
     }
 
-    pub async fn player_habits(db: &DatabaseConnection, player_id: PlayerId) -> Result<Vec<Habit>> {
+    pub async fn player_habits(
+        db: &DatabaseConnection,
+        player_id: PlayerId,
+    ) -> Result<Vec<Habit>, HabitError> {
         let models = habit::Entity::find()
             .filter(habit::Column::PlayerId.eq(player_id))
             .all(db)
-            .await
-            .with_context(|| format!("Failed to get habits for player '{player_id}'"))?;
+            .await?;
 
         Ok(models.into_iter().map(|model| Habit { model }).collect())
     }
 
-    pub async fn increment(&mut self, db: &DatabaseConnection) -> Result<()> {
+    pub async fn update(
+        &mut self,
+        db: &impl ConnectionTrait,
+        update: HabitUpdate,
+    ) -> Result<(), HabitError> {
+        let HabitUpdate {
+            name,
+            description,
+            positive,
+            negative,
+        } = update;
+        validate_direction(
+            positive.unwrap_or(self.model.positive),
+            negative.unwrap_or(self.model.negative),
+        )?;
+        let mut active_model = self.model.clone().into_active_model();
+        if let Some(name) = name {
+            active_model.name = sea_orm::ActiveValue::Set(name);
+        }
+        if let Some(description) = description {
+            active_model.description = sea_orm::ActiveValue::Set(description);
+        }
+        if let Some(positive) = positive {
+            active_model.positive = sea_orm::ActiveValue::Set(positive);
+        }
+        if let Some(negative) = negative {
+            active_model.negative = sea_orm::ActiveValue::Set(negative);
+        }
+        self.model = active_model.update(db).await?;
+        Ok(())
+    }
+
+    /// Deletes the habit from the database and returns it.
+    pub async fn delete(self, db: &impl ConnectionTrait) -> Result<Self, HabitError> {
+        habit::Entity::delete_by_id(self.model.id).exec(db).await?;
+        Ok(self)
+    }
+
+    pub async fn increment(&mut self, db: &DatabaseConnection) -> Result<Outbox, HabitError> {
         let habit_id = self.model.id;
+        if !self.model.positive {
+            return Err(HabitError::NotPositive(habit_id));
+        }
         let player_id = self.model.player_id;
         let new_model = self.model.clone();
-        self.model = db
-            .transaction::<_, Model, DbError>(|txn| {
+        let mut outbox;
+        (self.model, outbox) = db
+            .transaction::<_, (Model, Outbox), HabitError>(|txn| {
                 Box::pin(async move {
+                    let map_player_error = |source| HabitError::Player { habit_id, source };
                     let mut player = Player::from_id(txn, player_id)
                         .await
-                        .with_context(|| format!("Failed to get owner of habit {habit_id}."))?;
-
-                    player.add_xp(txn, 1.0).await.with_context(|| {
-                        format!(
-                        "Failed to add xp to player {player_id} while completing habit {habit_id}."
-                    )
-                    })?;
-                    Ok(new_model)
+                        .map_err(map_player_error)?;
+
+                    let outbox = player
+                        .add_xp(txn, HABIT_XP, XpSource::Habit(habit_id))
+                        .await
+                        .map_err(map_player_error)?;
+                    Ok((new_model, outbox))
                 })
             })
+            .instrument(debug_span!("transaction"))
             .await
-            .context("Failed to complete transaction")?;
-        Ok(())
+            .map_err(flatten_transaction_error)?;
+        outbox.push(
+            player_id,
+            PlayerEvent::HabitIncremented {
+                habit: self.clone(),
+            },
+        );
+        Ok(outbox)
+    }
+
+    pub async fn decrement(&mut self, db: &DatabaseConnection) -> Result<Outbox, HabitError> {
+        let habit_id = self.model.id;
+        if !self.model.negative {
+            return Err(HabitError::NotNegative(habit_id));
+        }
+        let player_id = self.model.player_id;
+        let new_model = self.model.clone();
+        let outbox;
+        (self.model, outbox) = db
+            .transaction::<_, (Model, Outbox), HabitError>(|txn| {
+                Box::pin(async move {
+                    let map_player_error = |source| HabitError::Player { habit_id, source };
+                    let mut player = Player::from_id(txn, player_id)
+                        .await
+                        .map_err(map_player_error)?;
+
+                    let outbox = player
+                        .take_damage(txn, HABIT_DAMAGE)
+                        .await
+                        .map_err(map_player_error)?;
+                    Ok((new_model, outbox))
+                })
+            })
+            .instrument(debug_span!("transaction"))
+            .await
+            .map_err(flatten_transaction_error)?;
+        Ok(outbox)
     }
 
     #[cfg(test)]
@@ -114,7 +264,6 @@ impl Habit {
         self.model.id
     }
 
-    #[cfg(test)]
     pub fn player(&self) -> PlayerId {
         self.model.player_id
     }
@@ -128,4 +277,14 @@ impl Habit {
     pub fn description(&self) -> &str {
         &self.model.description
     }
+
+    #[cfg(test)]
+    pub fn positive(&self) -> bool {
+        self.model.positive
+    }
+
+    #[cfg(test)]
+    pub fn negative(&self) -> bool {
+        self.model.negative
+    }
 }
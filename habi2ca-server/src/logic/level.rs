@@ -1,8 +1,130 @@
-use anyhow::{Context, Result};
-
-use habi2ca_database::level::{self, LevelId, Model};
-use sea_orm::{ConnectionTrait, DatabaseConnection, EntityTrait};
+use habi2ca_database::{
+    level::{self, LevelId, Model},
+    player,
+};
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, IntoActiveModel, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug_span, Instrument};
+
+use super::flatten_transaction_error;
+
+#[derive(Debug, Error)]
+pub enum LevelError {
+    #[error("No level with id {0} exists.")]
+    NotFound(LevelId),
+    #[error("Database error while accessing levels.")]
+    Database(#[from] DbErr),
+}
+
+/// Highest level players can reach. Levels past it are never generated, and xp earned at this
+/// level is kept instead of leveling up.
+pub const MAX_LEVEL: LevelId = LevelId(1000);
+
+/// Continues the level curve by one level given the xp requirements of the (up to) three highest
+/// levels, in ascending order.
+///
+/// The curve is extended by keeping the second difference of the requirements constant, which
+/// reproduces a quadratic curve exactly. Requirements never decrease.
+fn next_xp_requirement(highest: &[f64]) -> f64 {
+    match *highest {
+        [.., a, b, c] => c + ((c - b) + (c - b) - (b - a)).max(0.0),
+        [b, c] => c + (c - b).max(0.0),
+        [c] => c,
+        [] => unreachable!("The level curve cannot be extended without any levels."),
+    }
+}
+
+/// Xp requirements of consecutive levels starting at level 1, extended up to [`MAX_LEVEL`] past
+/// the known levels.
+pub(super) struct LevelCurve {
+    xp_requirements: Vec<f64>,
+}
+
+impl LevelCurve {
+    fn new(xp_requirements: Vec<f64>) -> Self {
+        Self { xp_requirements }
+    }
+
+    /// The curve of the levels in the database.
+    pub(super) async fn load(database: &impl ConnectionTrait) -> Result<Self, LevelError> {
+        let xp_requirements: Vec<f64> = level::Entity::find()
+            .select_only()
+            .column(level::Column::XpRequirement)
+            .order_by_asc(level::Column::Id)
+            .into_tuple()
+            .all(database)
+            .await?;
+        if xp_requirements.is_empty() {
+            return Err(LevelError::NotFound(LevelId(1)));
+        }
+        Ok(Self::new(xp_requirements))
+    }
+
+    fn xp_requirement(&mut self, level_id: LevelId) -> f64 {
+        let index = (level_id.0 - 1) as usize;
+        while self.xp_requirements.len() <= index {
+            let next = next_xp_requirement(&self.xp_requirements);
+            self.xp_requirements.push(next);
+        }
+        self.xp_requirements[index]
+    }
+
+    /// Total xp a player has earned to reach `level_id` with `xp` xp.
+    pub(super) fn total_xp(&mut self, level_id: LevelId, xp: f64) -> f64 {
+        (1..level_id.0)
+            .map(|id| self.xp_requirement(LevelId(id)))
+            .sum::<f64>()
+            + xp
+    }
+
+    /// The level and xp of a player that has earned `total_xp` xp. Negative totals, which older
+    /// versions allowed, count as 0 xp.
+    fn level_for_total_xp(&mut self, total_xp: f64) -> (LevelId, f64) {
+        self.add_xp(LevelId(1), 0.0, total_xp.max(0.0))
+            .unwrap_or((LevelId(1), 0.0))
+    }
+
+    /// The level and xp of a player at `level_id` with `xp` xp after gaining `xp_delta` xp, or
+    /// `None` if that would bring them below 0 total xp.
+    pub(super) fn add_xp(
+        &mut self,
+        mut level_id: LevelId,
+        xp: f64,
+        xp_delta: f64,
+    ) -> Option<(LevelId, f64)> {
+        let mut xp = xp + xp_delta;
+        while level_id.0 < MAX_LEVEL.0 && xp >= self.xp_requirement(level_id) {
+            xp -= self.xp_requirement(level_id);
+            level_id = level_id.next_level();
+        }
+        while xp < 0.0 {
+            level_id = level_id.previous_level()?;
+            xp += self.xp_requirement(level_id);
+        }
+        Some((level_id, xp))
+    }
+}
+
+/// Changes made to the database by [`Level::sync_curve`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LevelCurveDiff {
+    /// Levels that did not exist before.
+    pub added: Vec<LevelId>,
+    /// Levels whose xp requirement changed, with their old and new requirement.
+    pub changed: Vec<(LevelId, f64, f64)>,
+    /// Number of players whose level or xp changed.
+    pub players_recalculated: usize,
+}
+
+impl LevelCurveDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.players_recalculated == 0
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Level {
@@ -11,21 +133,154 @@ pub struct Level {
 }
 
 impl Level {
-    pub async fn from_id(database: &impl ConnectionTrait, id: LevelId) -> Result<Self> {
+    /// Gets the level with the given id.
+    ///
+    /// Levels past the highest level in the database are generated by extending the level curve
+    /// and stored, so players can keep leveling up indefinitely.
+    pub async fn from_id(database: &impl ConnectionTrait, id: LevelId) -> Result<Self, LevelError> {
+        if let Some(model) = level::Entity::find_by_id(id).one(database).await? {
+            return Ok(Self { model });
+        }
+        Self::extend_curve(database, id).await
+    }
+
+    /// Generates and stores all levels up to and including `id`.
+    async fn extend_curve(
+        database: &impl ConnectionTrait,
+        id: LevelId,
+    ) -> Result<Self, LevelError> {
+        let mut highest: Vec<Model> = level::Entity::find()
+            .order_by_desc(level::Column::Id)
+            .limit(3)
+            .all(database)
+            .await?;
+        highest.reverse();
+        let Some(top) = highest.last() else {
+            return Err(LevelError::NotFound(id));
+        };
+        if id.0 <= top.id.0 || id.0 > MAX_LEVEL.0 {
+            // Ids below the highest level are only missing if they are invalid.
+            return Err(LevelError::NotFound(id));
+        }
+
+        let mut xp_requirements: Vec<f64> =
+            highest.iter().map(|model| model.xp_requirement).collect();
+        let mut new_models = Vec::new();
+        let mut level_id = top.id;
+        while level_id.0 < id.0 {
+            level_id = level_id.next_level();
+            let xp_requirement = next_xp_requirement(&xp_requirements);
+            xp_requirements.push(xp_requirement);
+            new_models.push(Model {
+                id: level_id,
+                xp_requirement,
+            });
+        }
+
+        level::Entity::insert_many(new_models.iter().map(|model| level::ActiveModel {
+            id: Set(model.id),
+            xp_requirement: Set(model.xp_requirement),
+        }))
+        .on_conflict(
+            OnConflict::column(level::Column::Id)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(database)
+        .await?;
+
         Ok(Self {
-            model: level::Entity::find_by_id(id)
-                .one(database)
-                .await
-                .with_context(|| format!("Failed to get level with id {id} from database."))?
-                .with_context(|| format!("No level with id {id} exists."))?,
+            model: new_models.pop().expect("At least one level was generated."),
         })
     }
 
-    pub async fn all_levels(database: &DatabaseConnection) -> Result<Vec<Level>> {
-        let models = level::Entity::find()
-            .all(database)
+    /// Makes the level table match the curve starting with `xp_requirements`.
+    ///
+    /// Levels generated past the given requirements are regenerated from the new curve.
+    /// Players keep their total xp, so their level and xp are recalculated for the new curve.
+    pub async fn sync_curve(
+        database: &DatabaseConnection,
+        xp_requirements: Vec<f64>,
+    ) -> Result<LevelCurveDiff, LevelError> {
+        database
+            .transaction::<_, LevelCurveDiff, LevelError>(|txn| {
+                Box::pin(async move {
+                    let old_levels = level::Entity::find()
+                        .order_by_asc(level::Column::Id)
+                        .all(txn)
+                        .await?;
+                    let mut old_curve = LevelCurve::new(
+                        old_levels
+                            .iter()
+                            .map(|model| model.xp_requirement)
+                            .collect(),
+                    );
+                    let given_levels = xp_requirements.len();
+                    let mut new_curve = LevelCurve::new(xp_requirements);
+
+                    let mut player_updates = Vec::new();
+                    for player in player::Entity::find().all(txn).await? {
+                        let total_xp = old_curve.total_xp(player.level_id, player.xp);
+                        let (level_id, xp) = new_curve.level_for_total_xp(total_xp);
+                        if level_id != player.level_id || xp != player.xp {
+                            player_updates.push((player, level_id, xp));
+                        }
+                    }
+
+                    let level_count = player_updates
+                        .iter()
+                        .map(|(_, level_id, _)| level_id.0 as usize)
+                        .chain([given_levels, old_levels.len()])
+                        .max()
+                        .unwrap_or_default();
+                    let mut diff = LevelCurveDiff::default();
+                    for id in 1..=level_count as i64 {
+                        let level_id = LevelId(id);
+                        let xp_requirement = new_curve.xp_requirement(level_id);
+                        match old_levels.get(id as usize - 1) {
+                            Some(old) if old.xp_requirement == xp_requirement => {}
+                            Some(old) => {
+                                diff.changed
+                                    .push((level_id, old.xp_requirement, xp_requirement));
+                                level::ActiveModel {
+                                    xp_requirement: Set(xp_requirement),
+                                    ..old.clone().into_active_model()
+                                }
+                                .update(txn)
+                                .await?;
+                            }
+                            None => {
+                                diff.added.push(level_id);
+                                level::Entity::insert(level::ActiveModel {
+                                    id: Set(level_id),
+                                    xp_requirement: Set(xp_requirement),
+                                })
+                                .exec_without_returning(txn)
+                                .await?;
+                            }
+                        }
+                    }
+
+                    diff.players_recalculated = player_updates.len();
+                    for (player, level_id, xp) in player_updates {
+                        player::ActiveModel {
+                            level_id: Set(level_id),
+                            xp: Set(xp),
+                            ..player.into_active_model()
+                        }
+                        .update(txn)
+                        .await?;
+                    }
+                    Ok(diff)
+                })
+            })
+            .instrument(debug_span!("transaction"))
             .await
-            .context("Failed to get all levels from database.")?;
+            .map_err(flatten_transaction_error)
+    }
+
+    pub async fn all_levels(database: &DatabaseConnection) -> Result<Vec<Level>, LevelError> {
+        let models = level::Entity::find().all(database).await?;
         Ok(models.into_iter().map(|model| Level { model }).collect())
     }
 
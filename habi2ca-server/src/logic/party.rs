@@ -0,0 +1,317 @@
+use chrono::{DateTime, Utc};
+use habi2ca_database::{
+    account::AccountId,
+    level::LevelId,
+    party::{self, Model, PartyId},
+    party_member,
+    player::{self, PlayerId},
+};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, JoinType, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait, Set, SqlErr, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug_span, Instrument};
+
+use crate::events::{Outbox, PlayerEvent};
+
+use super::{
+    flatten_transaction_error,
+    player::{Player, PlayerError},
+};
+
+#[derive(Debug, Error)]
+pub enum PartyError {
+    #[error("No party with id {0} exists.")]
+    NotFound(PartyId),
+    #[error("Invalid party name: {0}")]
+    InvalidName(&'static str),
+    #[error("Cannot add player {0} to a party since no such player exists.")]
+    UnknownPlayer(PlayerId),
+    #[error("Player {0} is already in a party.")]
+    AlreadyInParty(PlayerId),
+    #[error("Player {player_id} is not a member of party {party_id}.")]
+    NotAMember {
+        party_id: PartyId,
+        player_id: PlayerId,
+    },
+    #[error("Failed to get member of party {party_id}.")]
+    Player {
+        party_id: PartyId,
+        #[source]
+        source: PlayerError,
+    },
+    #[error("Database error while accessing parties.")]
+    Database(#[from] DbErr),
+}
+
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PartyData {
+    pub name: String,
+    /// The player founding the party, who becomes its first member.
+    pub player_id: PlayerId,
+}
+
+/// A player in a party. Leaves out who owns the player, since members are shown to every account
+/// with a player in the party.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartyMember {
+    pub player_id: PlayerId,
+    pub name: String,
+    pub level: LevelId,
+    pub health: f64,
+    pub joined_at: DateTime<Utc>,
+    #[serde(skip)]
+    account_id: Option<AccountId>,
+}
+
+impl PartyMember {
+    /// The account that owns the player, if any.
+    pub fn account_id(&self) -> Option<AccountId> {
+        self.account_id
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Party {
+    #[serde(flatten)]
+    model: Model,
+    /// In the order they joined.
+    members: Vec<PartyMember>,
+}
+
+/// Adds the player to the party, failing if they are already in one.
+async fn insert_member(
+    db: &impl ConnectionTrait,
+    party_id: PartyId,
+    player_id: PlayerId,
+) -> Result<Outbox, PartyError> {
+    let player = Player::from_id(db, player_id)
+        .await
+        .map_err(|error| match error {
+            PlayerError::NotFound(player_id) => PartyError::UnknownPlayer(player_id),
+            source => PartyError::Player { party_id, source },
+        })?;
+    party_member::Entity::insert(party_member::ActiveModel {
+        party_id: Set(party_id),
+        player_id: Set(player_id),
+        joined_at: Set(Utc::now()),
+        ..Default::default()
+    })
+    .exec(db)
+    .await
+    .map_err(|error| match error.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(_)) => PartyError::AlreadyInParty(player_id),
+        _ => error.into(),
+    })?;
+    let mut outbox = Outbox::default();
+    outbox.push(player_id, PlayerEvent::PartyJoined { party_id, player });
+    Ok(outbox)
+}
+
+/// Removes the player from the party and disbands it if they were its last member. Returns `None`
+/// if the player is not in the party.
+async fn remove_member(
+    db: &impl ConnectionTrait,
+    party_id: PartyId,
+    player: Player,
+) -> Result<Option<Outbox>, DbErr> {
+    let player_id = player.id();
+    let result = party_member::Entity::delete_many()
+        .filter(party_member::Column::PartyId.eq(party_id))
+        .filter(party_member::Column::PlayerId.eq(player_id))
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Ok(None);
+    }
+    let remaining = party_member::Entity::find()
+        .filter(party_member::Column::PartyId.eq(party_id))
+        .count(db)
+        .await?;
+    if remaining == 0 {
+        party::Entity::delete_by_id(party_id).exec(db).await?;
+    }
+
+    let mut outbox = Outbox::default();
+    outbox.push(player_id, PlayerEvent::PartyLeft { party_id, player });
+    Ok(Some(outbox))
+}
+
+/// Removes the player from their party, if they are in one.
+pub(super) async fn leave_party(
+    db: &impl ConnectionTrait,
+    player: Player,
+) -> Result<Outbox, DbErr> {
+    let membership = party_member::Entity::find()
+        .filter(party_member::Column::PlayerId.eq(player.id()))
+        .one(db)
+        .await?;
+    match membership {
+        Some(membership) => Ok(remove_member(db, membership.party_id, player)
+            .await?
+            .unwrap_or_default()),
+        None => Ok(Outbox::default()),
+    }
+}
+
+async fn load_members(
+    db: &impl ConnectionTrait,
+    party_id: PartyId,
+) -> Result<Vec<PartyMember>, PartyError> {
+    let members = party_member::Entity::find()
+        .filter(party_member::Column::PartyId.eq(party_id))
+        .find_also_related(player::Entity)
+        .order_by_asc(party_member::Column::Id)
+        .all(db)
+        .await?;
+    Ok(members
+        .into_iter()
+        .filter_map(|(member, player)| {
+            let player = player?;
+            Some(PartyMember {
+                player_id: player.id,
+                name: player.name,
+                level: player.level_id,
+                health: player.health,
+                joined_at: member.joined_at,
+                account_id: player.account_id,
+            })
+        })
+        .collect())
+}
+
+impl Party {
+    /// Creates a party with the founding player as its only member.
+    pub async fn create(
+        db: &DatabaseConnection,
+        data: PartyData,
+    ) -> Result<(Self, Outbox), PartyError> {
+        let PartyData { name, player_id } = data;
+        if name.trim().is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(PartyError::InvalidName(
+                "names must be between 1 and 100 characters long",
+            ));
+        }
+        db.transaction::<_, (Self, Outbox), PartyError>(|txn| {
+            Box::pin(async move {
+                let model = party::Entity::insert(party::ActiveModel {
+                    name: Set(name),
+                    created_at: Set(Utc::now()),
+                    ..Default::default()
+                })
+                .exec_with_returning(txn)
+                .await?;
+                let outbox = insert_member(txn, model.id, player_id).await?;
+                let members = load_members(txn, model.id).await?;
+                Ok((Self { model, members }, outbox))
+            })
+        })
+        .instrument(debug_span!("transaction"))
+        .await
+        .map_err(flatten_transaction_error)
+    }
+
+    pub async fn from_id(db: &impl ConnectionTrait, id: PartyId) -> Result<Self, PartyError> {
+        let model = party::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(PartyError::NotFound(id))?;
+        let members = load_members(db, id).await?;
+        Ok(Self { model, members })
+    }
+
+    /// All parties with a player owned by the account.
+    pub async fn account_parties(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+    ) -> Result<Vec<Self>, PartyError> {
+        let models = party::Entity::find()
+            .join(JoinType::InnerJoin, party::Relation::PartyMember.def())
+            .join(JoinType::InnerJoin, party_member::Relation::Player.def())
+            .filter(player::Column::AccountId.eq(account_id))
+            .order_by_asc(party::Column::Id)
+            .distinct()
+            .all(db)
+            .await?;
+        let mut parties = Vec::with_capacity(models.len());
+        for model in models {
+            let members = load_members(db, model.id).await?;
+            parties.push(Self { model, members });
+        }
+        Ok(parties)
+    }
+
+    #[cfg(test)]
+    pub fn id(&self) -> PartyId {
+        self.model.id
+    }
+
+    pub fn members(&self) -> &[PartyMember] {
+        &self.members
+    }
+
+    /// Whether the account owns a player in the party, which gives it access to the party.
+    pub fn has_member_of(&self, account_id: AccountId) -> bool {
+        self.members
+            .iter()
+            .any(|member| member.account_id == Some(account_id))
+    }
+
+    pub async fn join(
+        &mut self,
+        db: &DatabaseConnection,
+        player_id: PlayerId,
+    ) -> Result<Outbox, PartyError> {
+        let party_id = self.model.id;
+        let outbox;
+        (self.members, outbox) = db
+            .transaction::<_, (Vec<PartyMember>, Outbox), PartyError>(|txn| {
+                Box::pin(async move {
+                    // Fails if the party was disbanded in the meantime.
+                    party::Entity::find_by_id(party_id)
+                        .one(txn)
+                        .await?
+                        .ok_or(PartyError::NotFound(party_id))?;
+                    let outbox = insert_member(txn, party_id, player_id).await?;
+                    Ok((load_members(txn, party_id).await?, outbox))
+                })
+            })
+            .instrument(debug_span!("transaction"))
+            .await
+            .map_err(flatten_transaction_error)?;
+        Ok(outbox)
+    }
+
+    /// Removes the player from the party. The party is disbanded once its last member leaves.
+    pub async fn leave(
+        &mut self,
+        db: &DatabaseConnection,
+        player_id: PlayerId,
+    ) -> Result<Outbox, PartyError> {
+        let party_id = self.model.id;
+        let outbox;
+        (self.members, outbox) = db
+            .transaction::<_, (Vec<PartyMember>, Outbox), PartyError>(|txn| {
+                Box::pin(async move {
+                    let player = Player::from_id(txn, player_id)
+                        .await
+                        .map_err(|source| PartyError::Player { party_id, source })?;
+                    let outbox = remove_member(txn, party_id, player).await?.ok_or(
+                        PartyError::NotAMember {
+                            party_id,
+                            player_id,
+                        },
+                    )?;
+                    Ok((load_members(txn, party_id).await?, outbox))
+                })
+            })
+            .instrument(debug_span!("transaction"))
+            .await
+            .map_err(flatten_transaction_error)?;
+        Ok(outbox)
+    }
+}
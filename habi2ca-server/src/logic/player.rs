@@ -1,98 +1,183 @@
-use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
 use habi2ca_database::{
-    level,
+    account::AccountId,
+    level::{self, LevelId},
     player::{self, ActiveModel, PlayerId},
 };
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ConnectionTrait, DatabaseConnection, EntityTrait,
-    IntoActiveModel, Set,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug_span, instrument, Instrument};
 
-use super::level::Level;
+use crate::events::{Outbox, PlayerEvent};
 
-#[cfg(test)]
-use habi2ca_database::level::LevelId;
+use super::{
+    flatten_transaction_error,
+    level::{Level, LevelCurve, LevelError},
+    party,
+    xp_event::{XpEvent, XpSource},
+};
+
+#[derive(Debug, Error)]
+pub enum PlayerError {
+    #[error("No player with id {0} exists.")]
+    NotFound(PlayerId),
+    #[error("Player {player_id}s level ({level_id}) not found in database.")]
+    LevelNotFound {
+        player_id: PlayerId,
+        level_id: LevelId,
+    },
+    #[error(
+        "Invalid xp amount {0}. XP amounts must be at most {MAX_XP_DELTA} in either direction and may not bring a player below 0 total xp."
+    )]
+    InvalidXp(f64),
+    #[error("Invalid damage amount {0}. Damage must be finite and non-negative.")]
+    InvalidDamage(f64),
+    #[error("Failed to get level.")]
+    Level(#[from] LevelError),
+    #[error("Database error while accessing players.")]
+    Database(#[from] DbErr),
+}
+
+/// Changes to the editable fields of a player. Fields that are `None` are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlayerUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// A player's standing on the leaderboard. Leaves out who owns the player, since the leaderboard is
+/// shown to every account.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub player_id: PlayerId,
+    pub name: String,
+    pub level: LevelId,
+    pub xp: f64,
+}
+
+/// Largest amount of xp a player can gain or lose at once.
+pub const MAX_XP_DELTA: f64 = 1e9;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Health of a newly created player and the most health a player can have.
+pub const MAX_HEALTH: f64 = 50.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Player {
     #[serde(flatten)]
     pub(super) model: player::Model,
     pub(super) xp_requirement: f64,
+    pub(super) max_health: f64,
 }
 
 impl Player {
-    fn default_model(name: impl AsRef<str>) -> ActiveModel {
+    fn default_model(account_id: AccountId, name: impl AsRef<str>) -> ActiveModel {
         ActiveModel {
+            account_id: Set(Some(account_id)),
             name: Set(name.as_ref().to_owned()),
             xp: Set(0.0),
             level_id: Set(1.into()),
+            health: Set(MAX_HEALTH),
+            last_rollover: Set(Some(chrono::Utc::now().date_naive())),
             ..Default::default()
         }
     }
 
-    pub async fn create(db: &impl ConnectionTrait, name: impl AsRef<str>) -> Result<Self> {
-        let player = Self::default_model(name);
+    pub async fn create(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+        name: impl AsRef<str>,
+    ) -> Result<Self, PlayerError> {
+        let player = Self::default_model(account_id, name);
         let model = player::Entity::insert(player)
             .exec_with_returning(db)
-            .await
-            .context("Failed to insert player into database.")?;
-        let level = Level::from_id(db, model.level_id)
-            .await
-            .context("Failed to get level from database.")?;
+            .await?;
+        let level = Level::from_id(db, model.level_id).await?;
         Ok(Self {
             model,
             xp_requirement: level.xp_requirement(),
+            max_health: MAX_HEALTH,
         })
     }
 
-    pub async fn from_id(db: &impl ConnectionTrait, id: PlayerId) -> Result<Self> {
+    pub async fn from_id(db: &impl ConnectionTrait, id: PlayerId) -> Result<Self, PlayerError> {
         let (player_model, level_model_option) = player::Entity::find_by_id(id)
             .find_also_related(level::Entity)
             .one(db)
-            .await
-            .with_context(|| format!("Failure while getting player with id {id} from database."))?
-            .with_context(|| format!("Player with id {id} not found in database."))?;
-        let level_model = level_model_option.with_context(|| {
-            format!(
-                "Player {id}s level ({}) not found in database.",
-                player_model.level_id
-            )
+            .await?
+            .ok_or(PlayerError::NotFound(id))?;
+        let level_model = level_model_option.ok_or(PlayerError::LevelNotFound {
+            player_id: id,
+            level_id: player_model.level_id,
         })?;
         Ok(Self {
             model: player_model,
             xp_requirement: level_model.xp_requirement,
+            max_health: MAX_HEALTH,
         })
     }
 
-    pub async fn all(db: &DatabaseConnection) -> Result<Vec<Self>> {
+    /// All players owned by the account.
+    pub async fn account_players(
+        db: &DatabaseConnection,
+        account_id: AccountId,
+    ) -> Result<Vec<Self>, PlayerError> {
         let models = player::Entity::find()
+            .filter(player::Column::AccountId.eq(account_id))
             .find_also_related(level::Entity)
             .all(db)
-            .await
-            .context("Failed to get players from database.")?;
+            .await?;
         models
             .into_iter()
             .map(|(player_model, level_model)| {
-                let level_model = level_model.with_context(|| {
-                    format!(
-                        "Player {}s level ({}) not found in database.",
-                        player_model.id, player_model.level_id
-                    )
+                let level_model = level_model.ok_or(PlayerError::LevelNotFound {
+                    player_id: player_model.id,
+                    level_id: player_model.level_id,
                 })?;
                 Ok(Self {
                     model: player_model,
                     xp_requirement: level_model.xp_requirement,
+                    max_health: MAX_HEALTH,
                 })
             })
-            .collect::<Result<Vec<Self>>>()
+            .collect()
+    }
+
+    /// The players with the highest levels, and within a level the most xp, across all accounts.
+    pub async fn leaderboard(
+        db: &impl ConnectionTrait,
+        limit: u64,
+    ) -> Result<Vec<LeaderboardEntry>, PlayerError> {
+        let models = player::Entity::find()
+            .order_by_desc(player::Column::LevelId)
+            .order_by_desc(player::Column::Xp)
+            .order_by_asc(player::Column::Id)
+            .limit(limit)
+            .all(db)
+            .await?;
+        Ok(models
+            .into_iter()
+            .map(|model| LeaderboardEntry {
+                player_id: model.id,
+                name: model.name,
+                level: model.level_id,
+                xp: model.xp,
+            })
+            .collect())
     }
 
-    #[cfg(test)]
     pub fn id(&self) -> PlayerId {
         self.model.id
     }
 
+    /// The account that owns the player, if any.
+    pub fn account_id(&self) -> Option<AccountId> {
+        self.model.account_id
+    }
+
     #[cfg(test)]
     pub fn name(&self) -> &str {
         &self.model.name
@@ -108,33 +193,206 @@ impl Player {
         self.model.level_id
     }
 
+    #[cfg(test)]
+    pub fn health(&self) -> f64 {
+        self.model.health
+    }
+
+    pub async fn update(
+        &mut self,
+        db: &impl ConnectionTrait,
+        update: PlayerUpdate,
+    ) -> Result<(), PlayerError> {
+        let PlayerUpdate { name } = update;
+        let mut active_model = self.model.clone().into_active_model();
+        if let Some(name) = name {
+            active_model.name = Set(name);
+        }
+        self.model = active_model.update(db).await?;
+        Ok(())
+    }
+
+    /// Deletes the player along with all their tasks and habits and returns the deleted player.
+    /// The player leaves their party first, so the other members are told about it.
+    pub async fn delete(self, db: &DatabaseConnection) -> Result<(Self, Outbox), PlayerError> {
+        db.transaction::<_, (Self, Outbox), PlayerError>(|txn| {
+            Box::pin(async move {
+                let outbox = party::leave_party(txn, self.clone()).await?;
+                player::Entity::delete_by_id(self.model.id)
+                    .exec(txn)
+                    .await?;
+                Ok((self, outbox))
+            })
+        })
+        .instrument(debug_span!("transaction"))
+        .await
+        .map_err(flatten_transaction_error)
+    }
+
+    /// The player's current day, i.e. the day their dailies were last rolled over to.
+    pub fn last_rollover(&self) -> Option<NaiveDate> {
+        self.model.last_rollover
+    }
+
+    pub async fn set_last_rollover(
+        &mut self,
+        db: &impl ConnectionTrait,
+        day: NaiveDate,
+    ) -> Result<(), PlayerError> {
+        let active_model = ActiveModel {
+            last_rollover: ActiveValue::Set(Some(day)),
+            ..self.model.clone().into_active_model()
+        };
+        self.model = active_model.update(db).await?;
+        Ok(())
+    }
+
     pub fn xp_requirement(&self) -> f64 {
         self.xp_requirement
     }
 
-    pub async fn add_xp(&mut self, db: &impl ConnectionTrait, xp_delta: f64) -> Result<()> {
-        self.model.xp += xp_delta;
-        loop {
-            let xp_needed = self.xp_requirement();
-            if self.model.xp >= xp_needed {
-                self.model.xp -= xp_needed;
-                self.model.level_id = self.model.level_id.next_level();
-            } else {
-                break;
+    /// Adds `xp_delta` to the player's xp, leveling up or down as needed, and records the change
+    /// in the xp ledger.
+    /// Negative deltas may level the player down, but never below 0 xp on the first level.
+    /// Deltas are limited to [`MAX_XP_DELTA`], and players stop leveling up at
+    /// [`MAX_LEVEL`](super::level::MAX_LEVEL).
+    /// The player must have been read in the same transaction as `db`, or concurrent changes to
+    /// their xp are lost.
+    /// Returns the events of the change, to be published once it is committed.
+    #[instrument(level = "debug", skip(self, db), fields(player_id = %self.model.id))]
+    pub async fn add_xp(
+        &mut self,
+        db: &impl ConnectionTrait,
+        xp_delta: f64,
+        source: XpSource,
+    ) -> Result<Outbox, PlayerError> {
+        if !xp_delta.is_finite() || xp_delta.abs() > MAX_XP_DELTA {
+            return Err(PlayerError::InvalidXp(xp_delta));
+        }
+        let (level_id, xp) = LevelCurve::load(db)
+            .await?
+            .add_xp(self.model.level_id, self.model.xp, xp_delta)
+            .ok_or(PlayerError::InvalidXp(xp_delta))?;
+        // Stores the levels the player reached that were not generated yet.
+        let xp_requirement = Level::from_id(db, level_id).await?.xp_requirement();
+
+        let active_model = ActiveModel {
+            xp: ActiveValue::Set(xp),
+            level_id: ActiveValue::Set(level_id),
+            ..self.model.clone().into_active_model()
+        };
+        let levels_gained = level_id.0 - self.model.level_id.0;
+        self.model = active_model.update(db).await?;
+        self.xp_requirement = xp_requirement;
+        let mut outbox = Outbox::default();
+        if xp_delta != 0.0 {
+            let xp_event = XpEvent::record(db, self.model.id, xp_delta, source).await?;
+            outbox.push(
+                self.model.id,
+                PlayerEvent::XpGained {
+                    player: self.clone(),
+                    xp_event,
+                },
+            );
+        }
+        if levels_gained > 0 {
+            outbox.push(
+                self.model.id,
+                PlayerEvent::LevelUp {
+                    player: self.clone(),
+                    levels_gained: levels_gained as u64,
+                },
+            );
+        }
+        Ok(outbox)
+    }
+
+    /// Takes back up to `xp` xp the player was granted, but never more than they have earned in
+    /// total, so that the xp they already lost in other ways is not taken twice.
+    /// Returns the events of the change, to be published once it is committed.
+    pub async fn revoke_xp(
+        &mut self,
+        db: &impl ConnectionTrait,
+        xp: f64,
+        source: XpSource,
+    ) -> Result<Outbox, PlayerError> {
+        let total_xp = LevelCurve::load(db)
+            .await?
+            .total_xp(self.model.level_id, self.model.xp);
+        self.add_xp(db, -xp.min(total_xp), source).await
+    }
+
+    /// Xp events of the player created in `[from, to)`, oldest first.
+    pub async fn xp_history(
+        &self,
+        db: &impl ConnectionTrait,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<XpEvent>, PlayerError> {
+        Ok(XpEvent::player_events(db, self.model.id, from, to).await?)
+    }
+
+    /// Removes `damage` health from the player.
+    /// If their health runs out, the player dies: they lose a level along with all progress
+    /// towards the next one, and are restored to full health.
+    /// Returns the events of the change, to be published once it is committed.
+    pub async fn take_damage(
+        &mut self,
+        db: &impl ConnectionTrait,
+        damage: f64,
+    ) -> Result<Outbox, PlayerError> {
+        if !damage.is_finite() || damage < 0.0 {
+            return Err(PlayerError::InvalidDamage(damage));
+        }
+        let mut health = self.model.health - damage;
+        let mut xp = self.model.xp;
+        let mut level_id = self.model.level_id;
+        let mut xp_requirement = self.xp_requirement();
+        let mut xp_lost = 0.0;
+        let died = health <= 0.0;
+        if died {
+            health = MAX_HEALTH;
+            xp_lost = xp;
+            xp = 0.0;
+            if let Some(previous_level) = level_id.previous_level() {
+                level_id = previous_level;
+                xp_requirement = Level::from_id(db, level_id).await?.xp_requirement();
+                xp_lost += xp_requirement;
             }
         }
 
         let active_model = ActiveModel {
-            xp: ActiveValue::Set(self.model.xp),
-            level_id: ActiveValue::Set(self.model.level_id),
+            health: ActiveValue::Set(health),
+            xp: ActiveValue::Set(xp),
+            level_id: ActiveValue::Set(level_id),
             ..self.model.clone().into_active_model()
         };
-        active_model.update(db).await.with_context(|| {
-            format!(
-                "Failure while updating player '{}' in database.",
-                self.model.id
-            )
-        })?;
-        Ok(())
+        self.model = active_model.update(db).await?;
+        self.xp_requirement = xp_requirement;
+        let mut outbox = Outbox::default();
+        if damage > 0.0 {
+            outbox.push(
+                self.model.id,
+                PlayerEvent::DamageTaken {
+                    player: self.clone(),
+                    damage,
+                },
+            );
+        }
+        if died {
+            let xp_event = if xp_lost > 0.0 {
+                Some(XpEvent::record(db, self.model.id, -xp_lost, XpSource::Death).await?)
+            } else {
+                None
+            };
+            outbox.push(
+                self.model.id,
+                PlayerEvent::Died {
+                    player: self.clone(),
+                    xp_event,
+                },
+            );
+        }
+        Ok(outbox)
     }
 }
@@ -1,16 +1,41 @@
-use std::fmt::Display;
-
-use crate::logic::player::Player;
-use anyhow::{Context, Result};
+use crate::{
+    events::{Outbox, PlayerEvent},
+    logic::{
+        player::{Player, PlayerError},
+        xp_event::{XpEvent, XpSource},
+    },
+};
 use habi2ca_database::{
-    player::PlayerId,
-    task::{self, ActiveModel, Model, TaskId},
+    account::AccountId,
+    player::{self, PlayerId},
+    task::{self, Model, TaskId},
 };
 use sea_orm::{
-    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, IntoActiveModel, JoinType, QueryFilter, QuerySelect, RelationTrait,
     TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug_span, Instrument};
+
+use super::{flatten_transaction_error, is_foreign_key_violation};
+
+#[derive(Debug, Error)]
+pub enum TaskError {
+    #[error("No task with id {0} exists.")]
+    NotFound(TaskId),
+    #[error("Cannot create task for player {0} since no such player exists.")]
+    UnknownPlayer(PlayerId),
+    #[error("Failed to update owner of task {task_id}.")]
+    Player {
+        task_id: TaskId,
+        #[source]
+        source: PlayerError,
+    },
+    #[error("Database error while accessing tasks.")]
+    Database(#[from] DbErr),
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TaskData {
@@ -32,46 +57,47 @@ impl TaskData {
     }
 }
 
-#[derive(Debug)]
-struct DbError(anyhow::Error);
-
-impl Display for DbError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
+/// Changes to the editable fields of a task. Fields that are `None` are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TaskUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
-impl std::error::Error for DbError {}
-
-impl From<anyhow::Error> for DbError {
-    fn from(error: anyhow::Error) -> Self {
-        Self(error)
-    }
-}
+/// XP granted for completing a task.
+const TASK_XP: f64 = 1.0;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
     #[serde(flatten)]
     pub(super) model: Model,
 }
 
 impl Task {
-    pub async fn create(db: &impl ConnectionTrait, task_data: TaskData) -> Result<Self> {
+    pub async fn create(db: &impl ConnectionTrait, task_data: TaskData) -> Result<Self, TaskError> {
+        let player_id = task_data.player_id;
         let model = task::Entity::insert(task_data.into_active_model())
             .exec_with_returning(db)
             .await
-            .context("Failed to insert task into database.")?;
+            .map_err(|error| {
+                if is_foreign_key_violation(&error) {
+                    TaskError::UnknownPlayer(player_id)
+                } else {
+                    error.into()
+                }
+            })?;
 
         Ok(Task { model })
     }
 
-    pub async fn from_id(db: &impl ConnectionTrait, id: TaskId) -> Result<Self> {
+    pub async fn from_id(db: &impl ConnectionTrait, id: TaskId) -> Result<Self, TaskError> {
         Ok(Self {
             model: task::Entity::find_by_id(id)
                 .one(db)
-                .await
-                .with_context(|| format!("Failed to get task with id {id} from database."))?
-                .with_context(|| format!("No task with id {id} exists."))?,
+                .await?
+                .ok_or(TaskError::NotFound(id))?,
         })
     }
 
@@ -80,63 +106,128 @@ impl Task {
         self.model.id
     }
 
-    #[cfg(test)]
     pub fn player_id(&self) -> PlayerId {
         self.model.player_id
     }
 
-    pub async fn all_tasks(db: &impl ConnectionTrait) -> Result<Vec<Task>> {
+    /// All tasks of the players owned by the account.
+    pub async fn account_tasks(
+        db: &impl ConnectionTrait,
+        account_id: AccountId,
+    ) -> Result<Vec<Task>, TaskError> {
         let models = task::Entity::find()
+            .join(JoinType::InnerJoin, task::Relation::Player.def())
+            .filter(player::Column::AccountId.eq(account_id))
             .all(db)
-            .await
-            .context("Failed to get all tasks from database.")?;
+            .await?;
         Ok(models.into_iter().map(|model| Self { model }).collect())
     }
 
-    pub async fn player_tasks(db: &DatabaseConnection, player_id: PlayerId) -> Result<Vec<Task>> {
+    pub async fn player_tasks(
+        db: &DatabaseConnection,
+        player_id: PlayerId,
+    ) -> Result<Vec<Task>, TaskError> {
         let models = task::Entity::find()
             .filter(task::Column::PlayerId.eq(player_id))
             .all(db)
-            .await
-            .with_context(|| format!("Failed to get tasks for player '{player_id}'"))?;
+            .await?;
 
         Ok(models.into_iter().map(|model| Task { model }).collect())
     }
 
-    pub async fn complete_task(&mut self, db: &DatabaseConnection) -> Result<()> {
-        if self.model.completed {
-            return Ok(());
+    pub async fn update(
+        &mut self,
+        db: &impl ConnectionTrait,
+        update: TaskUpdate,
+    ) -> Result<(), TaskError> {
+        let TaskUpdate { name, description } = update;
+        let mut active_model = self.model.clone().into_active_model();
+        if let Some(name) = name {
+            active_model.name = sea_orm::ActiveValue::Set(name);
+        }
+        if let Some(description) = description {
+            active_model.description = sea_orm::ActiveValue::Set(description);
         }
+        self.model = active_model.update(db).await?;
+        Ok(())
+    }
+
+    /// Deletes the task from the database and returns it.
+    pub async fn delete(self, db: &impl ConnectionTrait) -> Result<Self, TaskError> {
+        task::Entity::delete_by_id(self.model.id).exec(db).await?;
+        Ok(self)
+    }
+
+    /// Sets the completion state of the task and grants or revokes the player's xp accordingly.
+    /// Only xp the task granted is revoked, which is none for tasks created as completed.
+    /// The task is only changed if it is not in that state yet, checked in the same statement as
+    /// the update, so that concurrent requests cannot grant the xp twice.
+    async fn set_completed(
+        &mut self,
+        db: &DatabaseConnection,
+        completed: bool,
+    ) -> Result<Outbox, TaskError> {
         let task_id = self.model.id;
         let player_id = self.model.player_id;
-        let mut new_task = self.model.clone();
-        self.model = db
-            .transaction::<_, Model, DbError>(|txn| {
+        let outbox;
+        (self.model, outbox) = db
+            .transaction::<_, (Model, Outbox), TaskError>(|txn| {
                 Box::pin(async move {
-                    new_task.completed = true;
-                    let active_model = ActiveModel {
-                        completed: sea_orm::ActiveValue::Set(true),
-                        ..new_task.clone().into_active_model()
-                    };
-                    task::Entity::update(active_model)
+                    let result = task::Entity::update_many()
+                        .col_expr(task::Column::Completed, Expr::value(completed))
+                        .filter(task::Column::Id.eq(task_id))
+                        .filter(task::Column::Completed.eq(!completed))
                         .exec(txn)
-                        .await
-                        .with_context(|| format!("Failed to update task with id {task_id}."))?;
-
+                        .await?;
+                    let model = task::Entity::find_by_id(task_id)
+                        .one(txn)
+                        .await?
+                        .ok_or(TaskError::NotFound(task_id))?;
+                    if result.rows_affected != 1 {
+                        return Ok((model, Outbox::default()));
+                    }
+
+                    let map_player_error = |source| TaskError::Player { task_id, source };
                     let mut player = Player::from_id(txn, player_id)
                         .await
-                        .with_context(|| format!("Failed to get owner of task {}", task_id))?;
-
-                    player.add_xp(txn, 1.0).await.with_context(|| {
-                        format!(
-                        "Failed to add xp to player {player_id} while completing task {task_id}."
-                    )
-                    })?;
-                    Ok(new_task)
+                        .map_err(map_player_error)?;
+
+                    let source = XpSource::Task(task_id);
+                    let mut outbox = if completed {
+                        player.add_xp(txn, TASK_XP, source).await
+                    } else {
+                        // Earlier revocations may have taken back less than the task granted, see `revoke_xp`.
+                        let granted = XpEvent::source_total(txn, source)
+                            .await?
+                            .clamp(0.0, TASK_XP);
+                        player.revoke_xp(txn, granted, source).await
+                    }
+                    .map_err(map_player_error)?;
+                    if completed {
+                        outbox.push(
+                            player_id,
+                            PlayerEvent::TaskCompleted {
+                                task: Task {
+                                    model: model.clone(),
+                                },
+                            },
+                        );
+                    }
+                    Ok((model, outbox))
                 })
             })
+            .instrument(debug_span!("transaction"))
             .await
-            .context("Failed to complete transaction")?;
-        Ok(())
+            .map_err(flatten_transaction_error)?;
+        Ok(outbox)
+    }
+
+    pub async fn complete_task(&mut self, db: &DatabaseConnection) -> Result<Outbox, TaskError> {
+        self.set_completed(db, true).await
+    }
+
+    /// Reverts a completion, removing the xp it granted. Does nothing if the task is not completed.
+    pub async fn uncomplete_task(&mut self, db: &DatabaseConnection) -> Result<Outbox, TaskError> {
+        self.set_completed(db, false).await
     }
 }
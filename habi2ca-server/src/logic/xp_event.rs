@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use habi2ca_database::{
+    daily::DailyId,
+    habit::HabitId,
+    player::PlayerId,
+    task::TaskId,
+    xp_event::{self, Model, XpSourceKind},
+};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use serde::{Deserialize, Serialize};
+
+/// What caused a change in a player's xp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XpSource {
+    Task(TaskId),
+    Habit(HabitId),
+    Daily(DailyId),
+    Manual,
+    Death,
+}
+
+impl XpSource {
+    fn kind_and_id(self) -> (XpSourceKind, Option<i64>) {
+        match self {
+            XpSource::Task(id) => (XpSourceKind::Task, Some(id.0)),
+            XpSource::Habit(id) => (XpSourceKind::Habit, Some(id.0)),
+            XpSource::Daily(id) => (XpSourceKind::Daily, Some(id.0)),
+            XpSource::Manual => (XpSourceKind::Manual, None),
+            XpSource::Death => (XpSourceKind::Death, None),
+        }
+    }
+}
+
+/// An entry in the append-only ledger of xp changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XpEvent {
+    #[serde(flatten)]
+    pub(super) model: Model,
+}
+
+impl XpEvent {
+    /// Appends an event to the ledger. Should be called in the same transaction as the xp change.
+    pub(super) async fn record(
+        db: &impl ConnectionTrait,
+        player_id: PlayerId,
+        amount: f64,
+        source: XpSource,
+    ) -> Result<Self, DbErr> {
+        let (source_kind, source_id) = source.kind_and_id();
+        let model = xp_event::Entity::insert(xp_event::ActiveModel {
+            player_id: Set(player_id),
+            amount: Set(amount),
+            source_kind: Set(source_kind),
+            source_id: Set(source_id),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+        Ok(Self { model })
+    }
+
+    /// All events of the player created in `[from, to)`, oldest first.
+    pub(super) async fn player_events(
+        db: &impl ConnectionTrait,
+        player_id: PlayerId,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Self>, DbErr> {
+        let mut query = xp_event::Entity::find().filter(xp_event::Column::PlayerId.eq(player_id));
+        if let Some(from) = from {
+            query = query.filter(xp_event::Column::CreatedAt.gte(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(xp_event::Column::CreatedAt.lt(to));
+        }
+        let models = query
+            .order_by_asc(xp_event::Column::CreatedAt)
+            .order_by_asc(xp_event::Column::Id)
+            .all(db)
+            .await?;
+        Ok(models.into_iter().map(|model| Self { model }).collect())
+    }
+
+    /// Net xp the source has granted, i.e. the sum of its events.
+    pub(super) async fn source_total(
+        db: &impl ConnectionTrait,
+        source: XpSource,
+    ) -> Result<f64, DbErr> {
+        let (source_kind, source_id) = source.kind_and_id();
+        let amounts: Vec<f64> = xp_event::Entity::find()
+            .select_only()
+            .column(xp_event::Column::Amount)
+            .filter(xp_event::Column::SourceKind.eq(source_kind))
+            .filter(xp_event::Column::SourceId.eq(source_id))
+            .into_tuple()
+            .all(db)
+            .await?;
+        Ok(amounts.into_iter().sum())
+    }
+
+    pub fn amount(&self) -> f64 {
+        self.model.amount
+    }
+
+    #[cfg(test)]
+    pub fn source(&self) -> (XpSourceKind, Option<i64>) {
+        (self.model.source_kind, self.model.source_id)
+    }
+}
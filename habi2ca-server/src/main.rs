@@ -3,28 +3,25 @@ mod routes;
 mod start;
 mod state;
 
+mod backup;
+mod config;
 mod database_utils;
+mod events;
+mod export;
+mod frontend;
+mod gamedata;
+mod jobs;
 mod logic;
+mod metrics;
 #[cfg(test)]
 mod test_utils;
 mod tracing;
 
-use std::process::Termination;
-
 use anyhow::Result;
 use clap::Parser;
-use cli::ServerConfig;
-
-pub enum Never {}
-
-impl Termination for Never {
-    fn report(self) -> std::process::ExitCode {
-        unreachable!()
-    }
-}
+use cli::Cli;
 
 #[tokio::main]
-pub async fn main() -> Result<Never> {
-    let server_config = ServerConfig::parse();
-    server_config.start().await
+pub async fn main() -> Result<()> {
+    Cli::parse().run().await
 }
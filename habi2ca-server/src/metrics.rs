@@ -0,0 +1,290 @@
+use std::{
+    sync::{atomic::AtomicU64, LazyLock},
+    time::Instant,
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    http::header::ContentType,
+    middleware::Next,
+    HttpResponse,
+};
+use prometheus_client::{
+    encoding::{text, EncodeLabelSet},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+
+use crate::events::PlayerEvent;
+
+/// Content type of the OpenMetrics text format, which Prometheus scrapes.
+const TEXT_FORMAT: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct RequestLabels {
+    pub method: String,
+    /// Pattern of the matched route, e.g. `/api/tasks/{id}`, so requests for different ids are
+    /// counted together.
+    pub route: String,
+    pub status: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct QueryLabels {
+    /// First keyword of the statement, e.g. `SELECT`.
+    pub operation: String,
+}
+
+fn duration_histogram() -> Histogram {
+    // From 1 ms to about 16 s.
+    Histogram::new(exponential_buckets(0.001, 2.0, 15))
+}
+
+/// Metrics of the server, exported at `/metrics`.
+///
+/// The metrics are global, since database queries are recorded by a callback without access to the
+/// app state.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: Family<RequestLabels, Counter>,
+    pub http_request_duration: Family<RequestLabels, Histogram, fn() -> Histogram>,
+    pub db_query_duration: Family<QueryLabels, Histogram, fn() -> Histogram>,
+    pub db_query_errors: Family<QueryLabels, Counter>,
+    pub tasks_completed: Counter,
+    pub habits_incremented: Counter,
+    pub xp_awarded: Counter<f64, AtomicU64>,
+    pub xp_revoked: Counter<f64, AtomicU64>,
+    pub level_ups: Counter,
+    pub deaths: Counter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let mut metrics = Self {
+            registry: Registry::with_prefix("habi2ca"),
+            http_requests: Family::default(),
+            http_request_duration: Family::new_with_constructor(duration_histogram),
+            db_query_duration: Family::new_with_constructor(duration_histogram),
+            db_query_errors: Family::default(),
+            tasks_completed: Counter::default(),
+            habits_incremented: Counter::default(),
+            xp_awarded: Counter::default(),
+            xp_revoked: Counter::default(),
+            level_ups: Counter::default(),
+            deaths: Counter::default(),
+        };
+        let registry = &mut metrics.registry;
+        registry.register(
+            "http_requests",
+            "HTTP requests handled",
+            metrics.http_requests.clone(),
+        );
+        registry.register(
+            "http_request_duration_seconds",
+            "Time taken to handle HTTP requests",
+            metrics.http_request_duration.clone(),
+        );
+        registry.register(
+            "db_query_duration_seconds",
+            "Time taken by database queries",
+            metrics.db_query_duration.clone(),
+        );
+        registry.register(
+            "db_query_errors",
+            "Database queries that failed",
+            metrics.db_query_errors.clone(),
+        );
+        registry.register(
+            "tasks_completed",
+            "Tasks completed",
+            metrics.tasks_completed.clone(),
+        );
+        registry.register(
+            "habits_incremented",
+            "Times positive habits were performed",
+            metrics.habits_incremented.clone(),
+        );
+        registry.register(
+            "xp_awarded",
+            "Xp awarded to players",
+            metrics.xp_awarded.clone(),
+        );
+        registry.register(
+            "xp_revoked",
+            "Xp taken back from players, e.g. by uncompleting tasks",
+            metrics.xp_revoked.clone(),
+        );
+        registry.register(
+            "level_ups",
+            "Levels gained by players",
+            metrics.level_ups.clone(),
+        );
+        registry.register("deaths", "Times players died", metrics.deaths.clone());
+        metrics
+    }
+
+    /// Renders all metrics in the OpenMetrics text format.
+    pub fn encode(&self) -> String {
+        let mut output = String::new();
+        text::encode(&mut output, &self.registry).expect("Writing to a string cannot fail.");
+        output
+    }
+
+    /// Records the game metrics of a published event.
+    pub fn record_event(&self, event: &PlayerEvent) {
+        match event {
+            PlayerEvent::TaskCompleted { .. } => {
+                self.tasks_completed.inc();
+            }
+            PlayerEvent::HabitIncremented { .. } => {
+                self.habits_incremented.inc();
+            }
+            PlayerEvent::XpGained { xp_event, .. } if xp_event.amount() > 0.0 => {
+                self.xp_awarded.inc_by(xp_event.amount());
+            }
+            PlayerEvent::XpGained { xp_event, .. } => {
+                self.xp_revoked.inc_by(-xp_event.amount());
+            }
+            PlayerEvent::LevelUp { levels_gained, .. } => {
+                self.level_ups.inc_by(*levels_gained);
+            }
+            PlayerEvent::Died { .. } => {
+                self.deaths.inc();
+            }
+            PlayerEvent::TaskCreated { .. }
+            | PlayerEvent::DamageTaken { .. }
+            | PlayerEvent::PartyJoined { .. }
+            | PlayerEvent::PartyLeft { .. } => {}
+        }
+    }
+
+    /// Records a database query. Used as the metric callback of the database connection.
+    pub fn record_query(&self, info: &sea_orm::metric::Info<'_>) {
+        let operation = info
+            .statement
+            .sql
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let labels = QueryLabels { operation };
+        self.db_query_duration
+            .get_or_create(&labels)
+            .observe(info.elapsed.as_secs_f64());
+        if info.failed {
+            self.db_query_errors.get_or_create(&labels).inc();
+        }
+    }
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Middleware counting requests and measuring how long they take.
+pub async fn record_requests(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let response = next.call(request).await?;
+    let labels = RequestLabels {
+        method,
+        route: response
+            .request()
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_owned()),
+        status: response.status().as_u16(),
+    };
+    metrics().http_requests.get_or_create(&labels).inc();
+    metrics()
+        .http_request_duration
+        .get_or_create(&labels)
+        .observe(start.elapsed().as_secs_f64());
+    Ok(response)
+}
+
+#[get("/metrics")]
+pub async fn get_metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType(TEXT_FORMAT.parse().unwrap()))
+        .body(metrics().encode())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        test::{self as actix_test, TestRequest},
+    };
+
+    use super::metrics;
+    use crate::{
+        events::EventBus,
+        logic::{
+            player::Player,
+            task::{Task, TaskData},
+        },
+        start::create_app,
+        test_utils,
+    };
+
+    #[tokio::test]
+    async fn records_metrics() {
+        let mut database = test_utils::setup_database().await;
+        database.set_metric_callback(|info| metrics().record_query(info));
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        let tasks_completed = metrics().tasks_completed.get();
+        let xp_awarded = metrics().xp_awarded.get();
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let mut task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task".to_string(),
+                description: "Description".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+        task.complete_task(&database)
+            .await
+            .unwrap()
+            .publish(&EventBus::new());
+        // Other tests may complete tasks concurrently.
+        assert!(metrics().tasks_completed.get() > tasks_completed);
+        assert!(metrics().xp_awarded.get() > xp_awarded);
+
+        let response =
+            actix_test::call_service(&app, TestRequest::get().uri("/api/levels").to_request())
+                .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Metrics need no authentication.
+        let response =
+            actix_test::call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = actix_test::read_body(response).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains(
+            "habi2ca_http_requests_total{method=\"GET\",route=\"/api/levels\",status=\"200\"}"
+        ));
+        assert!(body.contains("habi2ca_http_request_duration_seconds_bucket"));
+        assert!(body.contains("habi2ca_db_query_duration_seconds_count{operation=\"SELECT\"}"));
+        assert!(body.contains("habi2ca_tasks_completed_total"));
+        assert!(body.contains("habi2ca_level_ups_total"));
+    }
+}
@@ -1,31 +1,51 @@
 mod admin;
+mod auth;
+mod dailies;
+mod error;
 mod habits;
+mod health;
 mod levels;
+mod parties;
 mod players;
 mod tasks;
+mod tokens;
+mod ws;
 
-use actix_web::{web, ResponseError, Scope};
-use thiserror::Error;
+use actix_web::{web, HttpRequest, Scope};
+use serde::de::DeserializeOwned;
 
-#[derive(Debug, Error)]
-pub enum RouteError {
-    #[error("{0:?}\n")]
-    Anyhow(anyhow::Error),
-}
+pub use admin::hold_off_maintenance;
+pub use auth::authorize;
+use auth::authorize_player;
+#[cfg(test)]
+pub use auth::SESSION_COOKIE;
+pub use error::RouteError;
+#[cfg(test)]
+pub use error::{ErrorCode, Problem, PROBLEM_JSON};
 
-impl From<anyhow::Error> for RouteError {
-    fn from(error: anyhow::Error) -> Self {
-        RouteError::Anyhow(error)
-    }
+/// Loads the `{id}` segment of the request path.
+fn id_parameter<T: DeserializeOwned>(request: &HttpRequest) -> Result<T, RouteError> {
+    request
+        .match_info()
+        .load()
+        .map_err(|error| RouteError::InvalidParameter {
+            name: "id",
+            reason: error.to_string(),
+        })
 }
 
-impl ResponseError for RouteError {}
-
 pub fn add_routes(scope: Scope) -> Scope {
-    scope
+    health::add_routes(scope)
+        .app_data(error::json_config())
+        .app_data(error::query_config())
         .service(admin::add_routes(web::scope("/admin")))
+        .service(auth::add_routes(web::scope("/auth")))
         .service(players::add_routes(web::scope("/players")))
         .service(tasks::add_routes(web::scope("/tasks")))
         .service(habits::add_routes(web::scope("/habits")))
+        .service(dailies::add_routes(web::scope("/dailies")))
         .service(levels::add_routes(web::scope("/levels")))
+        .service(parties::add_routes(web::scope("/parties")))
+        .service(tokens::add_routes(web::scope("/tokens")))
+        .service(ws::add_routes(web::scope("/ws")))
 }
@@ -1,18 +1,473 @@
-use actix_web::{get, web, Responder, Scope};
+use std::collections::HashMap;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    middleware::Next,
+    post, put, web, HttpRequest, Responder, Scope,
+};
+use habi2ca_database::account::AccountId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tracing::info;
 
-use crate::{database_utils, routes::RouteError, state::State};
+use crate::{
+    backup::{Backup, Backups, Verification},
+    database_utils,
+    routes::RouteError,
+    state::State,
+    tracing::{LogFilterDirectives, LogFilters, AUDIT_TARGET},
+};
 
-#[get("reinitialize-database")]
-pub async fn reinitialize_database(state: web::Data<State>) -> Result<impl Responder, RouteError> {
-    info!("Reinitializing database...");
-    if let Some(database_path) = state.database_path() {
-        database_utils::reinitialize_database(database_path).await?;
+/// Header carrying the admin secret configured on the server.
+pub const ADMIN_SECRET_HEADER: &str = "X-Admin-Secret";
+
+/// Checks that the request presents the admin secret of the server.
+pub fn authorize_admin(state: &State, request: &ServiceRequest) -> Result<(), RouteError> {
+    let admin_secret = state.admin_secret().ok_or(RouteError::AdminDisabled)?;
+    let given_secret = request
+        .headers()
+        .get(ADMIN_SECRET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(RouteError::InvalidAdminSecret)?;
+    // Comparing digests keeps the comparison time independent of how much of the secret matches.
+    if Sha256::digest(given_secret) != Sha256::digest(admin_secret) {
+        return Err(RouteError::InvalidAdminSecret);
+    }
+    Ok(())
+}
+
+/// Middleware handling requests while holding the [maintenance lock](State::maintenance) for
+/// reading, so that they never see the database in the middle of maintenance. Admin routes are
+/// left out, since those doing maintenance take the lock for writing.
+pub async fn hold_off_maintenance(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if request.path().starts_with("/api/admin/") {
+        return next.call(request).await;
+    }
+    let maintenance = request
+        .app_data::<web::Data<State>>()
+        .expect("State should be registered on the app.")
+        .maintenance()
+        .clone();
+    let _guard = maintenance.read().await;
+    next.call(request).await
+}
+
+/// Checks that the request explicitly confirms a destructive action with `confirm=true`.
+fn require_confirmation(
+    query: &HashMap<String, String>,
+    consequence: &str,
+) -> Result<(), RouteError> {
+    match query.get("confirm").map(String::as_str) {
+        None => Err(RouteError::MissingParameter("confirm")),
+        Some("true") => Ok(()),
+        Some(_) => Err(RouteError::InvalidParameter {
+            name: "confirm",
+            reason: format!("must be 'true' to confirm that {consequence}"),
+        }),
     }
-    info!("Database reinitialized.");
-    Ok(("", actix_web::http::StatusCode::OK))
+}
+
+fn backups(state: &State) -> Result<&Backups, RouteError> {
+    state.backups().ok_or(RouteError::BackupsUnavailable)
+}
+
+fn log_filters(state: &State) -> Result<&LogFilters, RouteError> {
+    state.log_filters().ok_or(RouteError::LoggingUnavailable)
+}
+
+/// Records who performed an admin action in the audit log.
+fn audit(
+    request: &HttpRequest,
+    account_id: Option<web::ReqData<AccountId>>,
+    action: &str,
+    backup: Option<&Backup>,
+) {
+    info!(
+        target: AUDIT_TARGET,
+        action,
+        peer = request.connection_info().realip_remote_addr().unwrap_or("unknown"),
+        account = account_id.map(|account_id| account_id.0),
+        backup = backup.map(|backup| backup.path().display().to_string()),
+        "Admin action performed."
+    );
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReinitializeResponse {
+    /// Backup of the database taken before it was reinitialized.
+    pub backup: Option<Backup>,
+}
+
+/// Wipes the database, after backing it up if backups are available. Waits for other requests to
+/// finish and holds off new ones until it is done. Requires `confirm=true`.
+#[post("/reinitialize-database")]
+pub async fn reinitialize_database(
+    state: web::Data<State>,
+    request: HttpRequest,
+    account_id: Option<web::ReqData<AccountId>>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<impl Responder, RouteError> {
+    require_confirmation(&query, "all data should be deleted")?;
+
+    let _guard = state.maintenance().write().await;
+    info!("Reinitializing database...");
+    let backup = database_utils::reinitialize_database(state.database(), state.backups()).await?;
+    audit(
+        &request,
+        account_id,
+        "reinitialize_database",
+        backup.as_ref(),
+    );
+    Ok(web::Json(ReinitializeResponse { backup }))
+}
+
+#[get("/backups")]
+pub async fn get_backups(state: web::Data<State>) -> Result<impl Responder, RouteError> {
+    Ok(web::Json(backups(&state)?.list()?))
+}
+
+#[post("/backups")]
+pub async fn create_backup(
+    state: web::Data<State>,
+    request: HttpRequest,
+    account_id: Option<web::ReqData<AccountId>>,
+) -> Result<impl Responder, RouteError> {
+    let backup = backups(&state)?.create(state.database()).await?;
+    audit(&request, account_id, "create_backup", Some(&backup));
+    Ok(web::Json(backup))
+}
+
+#[get("/backups/{name}/verify")]
+pub async fn verify_backup(
+    state: web::Data<State>,
+    name: web::Path<String>,
+) -> Result<impl Responder, RouteError> {
+    Ok(web::Json(backups(&state)?.verify(&name).await?))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreResponse {
+    pub restored: Verification,
+    /// Backup of the database taken before it was replaced.
+    pub backup: Backup,
+}
+
+/// Replaces the contents of the database with a backup. Requires `confirm=true`.
+#[post("/backups/{name}/restore")]
+pub async fn restore_backup(
+    state: web::Data<State>,
+    request: HttpRequest,
+    account_id: Option<web::ReqData<AccountId>>,
+    name: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<impl Responder, RouteError> {
+    require_confirmation(&query, "the current data should be replaced")?;
+    let backups = backups(&state)?;
+    let _guard = state.maintenance().write().await;
+    let restored = backups.verify(&name).await?;
+    let backup = backups.restore(&name).await?;
+    audit(
+        &request,
+        account_id,
+        "restore_backup",
+        Some(&restored.backup),
+    );
+    Ok(web::Json(RestoreResponse { restored, backup }))
+}
+
+#[get("/log-filters")]
+pub async fn get_log_filters(state: web::Data<State>) -> Result<impl Responder, RouteError> {
+    Ok(web::Json(log_filters(&state)?.current()?))
+}
+
+/// Changes which events are logged until the server is restarted. Logs left out of the body keep
+/// their filter.
+#[put("/log-filters")]
+pub async fn update_log_filters(
+    state: web::Data<State>,
+    request: HttpRequest,
+    account_id: Option<web::ReqData<AccountId>>,
+    directives: web::Json<LogFilterDirectives>,
+) -> Result<impl Responder, RouteError> {
+    let log_filters = log_filters(&state)?;
+    log_filters.update(&directives)?;
+    audit(&request, account_id, "update_log_filters", None);
+    info!("Log filters changed to {:?}.", directives.into_inner());
+    Ok(web::Json(log_filters.current()?))
 }
 
 pub fn add_routes(scope: Scope) -> Scope {
-    scope.service(reinitialize_database)
+    scope
+        .service(reinitialize_database)
+        .service(get_backups)
+        .service(create_backup)
+        .service(verify_backup)
+        .service(restore_backup)
+        .service(get_log_filters)
+        .service(update_log_filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::pin, slice, time::Duration};
+
+    use actix_web::{
+        http::StatusCode,
+        test::{self as actix_test, TestRequest},
+        web,
+    };
+    use tokio::time;
+
+    use super::{ReinitializeResponse, RestoreResponse, ADMIN_SECRET_HEADER};
+    use crate::{
+        backup::{Backup, Backups, Retention},
+        database_utils,
+        logic::player::Player,
+        routes::ErrorCode,
+        start::create_app_with_state,
+        state::State,
+        test_utils,
+        tracing::{LogFilterDirectives, LogFilters},
+    };
+
+    const SECRET: &str = "admin-secret";
+
+    #[tokio::test]
+    async fn reinitialize_database() {
+        let database = test_utils::setup_database().await;
+        let state = State::new(database, None).with_admin_secret(Some(SECRET.to_owned()));
+        let app = actix_test::init_service(create_app_with_state(web::Data::new(state))).await;
+
+        let response = actix_test::call_service(
+            &app,
+            TestRequest::get()
+                .uri("/api/admin/reinitialize-database?confirm=true")
+                .insert_header((ADMIN_SECRET_HEADER, SECRET))
+                .to_request(),
+        )
+        .await;
+        // State-changing admin actions are only available through POST.
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/admin/reinitialize-database?confirm=true")
+                .insert_header((ADMIN_SECRET_HEADER, "wrong"))
+                .to_request(),
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::InvalidAdminSecret,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/admin/reinitialize-database")
+                .insert_header((ADMIN_SECRET_HEADER, SECRET))
+                .to_request(),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::MissingParameter,
+        )
+        .await;
+
+        let response: ReinitializeResponse = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/admin/reinitialize-database?confirm=true")
+                .insert_header((ADMIN_SECRET_HEADER, SECRET))
+                .to_request(),
+        )
+        .await;
+        assert!(response.backup.is_none());
+
+        // The session of the test account was deleted along with it.
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get().uri("/api/players").to_request(),
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::Unauthenticated,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn reinitialize_database_waits_for_requests() {
+        let database = test_utils::setup_database().await;
+        let state = web::Data::new(
+            State::new(database.clone(), None).with_admin_secret(Some(SECRET.to_owned())),
+        );
+        let app = actix_test::init_service(create_app_with_state(state.clone())).await;
+
+        // Stands in for a request that is being handled.
+        let in_flight = state.maintenance().clone().read_owned().await;
+        let mut reinitialize = pin!(actix_test::call_service(
+            &app,
+            TestRequest::post()
+                .uri("/api/admin/reinitialize-database?confirm=true")
+                .insert_header((ADMIN_SECRET_HEADER, SECRET))
+                .to_request(),
+        ));
+        assert!(time::timeout(Duration::from_millis(100), &mut reinitialize)
+            .await
+            .is_err());
+        assert!(Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .is_ok());
+
+        // Requests arriving meanwhile wait for the reinitialization.
+        let mut player_request = pin!(actix_test::call_service(
+            &app,
+            test_utils::authenticate(TestRequest::get().uri("/api/players").to_request()),
+        ));
+        assert!(
+            time::timeout(Duration::from_millis(100), &mut player_request)
+                .await
+                .is_err()
+        );
+
+        drop(in_flight);
+        assert_eq!(reinitialize.await.status(), StatusCode::OK);
+        assert_eq!(player_request.await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn admin_disabled() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app_with_state(web::Data::new(State::new(
+            database, None,
+        ))))
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/admin/reinitialize-database?confirm=true")
+                .insert_header((ADMIN_SECRET_HEADER, SECRET))
+                .to_request(),
+            StatusCode::FORBIDDEN,
+            ErrorCode::AdminDisabled,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn backups() {
+        let directory = tempfile::tempdir().unwrap();
+        let backups = Backups::new(directory.path().join("data.db"), Retention::default());
+        let database = database_utils::open_or_initialize_database(&backups, false, false)
+            .await
+            .unwrap();
+        let state = State::new(database, Some(backups)).with_admin_secret(Some(SECRET.to_owned()));
+        let app = actix_test::init_service(create_app_with_state(web::Data::new(state))).await;
+        let admin_request = |request: TestRequest| {
+            request
+                .insert_header((ADMIN_SECRET_HEADER, SECRET))
+                .to_request()
+        };
+
+        let backup: Backup = test_utils::assert_ok_response(
+            &app,
+            admin_request(TestRequest::post().uri("/api/admin/backups")),
+        )
+        .await;
+        let listed: Vec<Backup> = test_utils::assert_ok_response(
+            &app,
+            admin_request(TestRequest::get().uri("/api/admin/backups")),
+        )
+        .await;
+        assert_eq!(listed, slice::from_ref(&backup));
+
+        test_utils::assert_error_response(
+            &app,
+            admin_request(TestRequest::get().uri("/api/admin/backups/missing.db/verify")),
+            StatusCode::NOT_FOUND,
+            ErrorCode::BackupNotFound,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            admin_request(
+                TestRequest::post().uri(&format!("/api/admin/backups/{}/restore", backup.name)),
+            ),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::MissingParameter,
+        )
+        .await;
+
+        let response: RestoreResponse = test_utils::assert_ok_response(
+            &app,
+            admin_request(TestRequest::post().uri(&format!(
+                "/api/admin/backups/{}/restore?confirm=true",
+                backup.name
+            ))),
+        )
+        .await;
+        assert_eq!(response.restored.backup.name, backup.name);
+        assert_ne!(response.backup.name, backup.name);
+    }
+
+    #[tokio::test]
+    async fn log_filters() {
+        let database = test_utils::setup_database().await;
+        let (log_filters, _layers) = LogFilters::detached("warn", None);
+        let state = State::new(database, None)
+            .with_admin_secret(Some(SECRET.to_owned()))
+            .with_log_filters(Some(log_filters));
+        let app = actix_test::init_service(create_app_with_state(web::Data::new(state))).await;
+        let update = |directives: LogFilterDirectives| {
+            TestRequest::put()
+                .uri("/api/admin/log-filters")
+                .insert_header((ADMIN_SECRET_HEADER, SECRET))
+                .set_json(directives)
+                .to_request()
+        };
+
+        let directives: LogFilterDirectives = test_utils::assert_ok_response(
+            &app,
+            update(LogFilterDirectives {
+                stdout: Some("warn,habi2ca=debug".to_owned()),
+                file: None,
+            }),
+        )
+        .await;
+        assert!(directives.stdout.unwrap().contains("habi2ca=debug"));
+        assert_eq!(directives.file, None);
+
+        test_utils::assert_error_response(
+            &app,
+            update(LogFilterDirectives {
+                stdout: Some("habi2ca=loud".to_owned()),
+                file: None,
+            }),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidLogFilter,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            update(LogFilterDirectives {
+                stdout: Some("error".to_owned()),
+                file: Some("debug".to_owned()),
+            }),
+            StatusCode::CONFLICT,
+            ErrorCode::LoggingUnavailable,
+        )
+        .await;
+
+        // Failed updates change nothing.
+        let directives: LogFilterDirectives = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/admin/log-filters")
+                .insert_header((ADMIN_SECRET_HEADER, SECRET))
+                .to_request(),
+        )
+        .await;
+        assert!(directives.stdout.unwrap().contains("habi2ca=debug"));
+    }
 }
@@ -0,0 +1,533 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    cookie::{self, Cookie, SameSite},
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    http::{header::AUTHORIZATION, Method},
+    middleware::Next,
+    post,
+    web::{self, Json},
+    HttpMessage, HttpRequest, HttpResponse, Responder, Scope,
+};
+use habi2ca_database::{
+    account::AccountId, api_token::TokenScope, daily::DailyId, habit::HabitId, player::PlayerId,
+    task::TaskId,
+};
+use sea_orm::ConnectionTrait;
+
+use crate::{
+    logic::{
+        account::{Account, Credentials, Session, SESSION_DURATION},
+        api_token::ApiToken,
+        daily::{Daily, DailyError},
+        habit::{Habit, HabitError},
+        player::{Player, PlayerError},
+        task::{Task, TaskError},
+    },
+    routes::{admin, RouteError},
+    state::State,
+};
+
+/// Name of the cookie holding the session token.
+pub const SESSION_COOKIE: &str = "habi2ca_session";
+
+/// API routes that can be used without logging in.
+const PUBLIC_ROUTES: [&str; 7] = [
+    "/api/auth/register",
+    "/api/auth/login",
+    "/api/auth/logout",
+    "/api/levels",
+    "/api/health",
+    "/api/ready",
+    "/api/version",
+];
+
+/// Route of the WebSocket, whose messages are checked against the scope of the caller's API token
+/// one by one.
+const WEBSOCKET_ROUTE: &str = "/api/ws";
+
+fn session_cookie(token: String) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(cookie::time::Duration::seconds(
+            SESSION_DURATION.num_seconds(),
+        ))
+        .finish()
+}
+
+#[post("/register")]
+pub async fn register(
+    state: web::Data<State>,
+    credentials: Json<Credentials>,
+) -> Result<impl Responder, RouteError> {
+    let account = Account::create(state.database(), credentials.into_inner()).await?;
+    Ok(web::Json(account))
+}
+
+#[post("/login")]
+pub async fn login(
+    state: web::Data<State>,
+    credentials: Json<Credentials>,
+) -> Result<impl Responder, RouteError> {
+    let account = Account::authenticate(state.database(), &credentials).await?;
+    let token = Session::create(state.database(), account.id()).await?;
+    Ok(HttpResponse::Ok()
+        .cookie(session_cookie(token))
+        .json(account))
+}
+
+#[post("/logout")]
+pub async fn logout(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    if let Some(cookie) = request.cookie(SESSION_COOKIE) {
+        Session::delete(state.database(), cookie.value()).await?;
+    }
+    let mut removal = session_cookie(String::new());
+    removal.make_removal();
+    Ok(HttpResponse::NoContent().cookie(removal).finish())
+}
+
+#[get("/me")]
+pub async fn me(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+) -> Result<impl Responder, RouteError> {
+    let account = Account::from_id(state.database(), *account_id).await?;
+    Ok(web::Json(account))
+}
+
+pub fn add_routes(scope: Scope) -> Scope {
+    scope
+        .service(register)
+        .service(login)
+        .service(logout)
+        .service(me)
+}
+
+/// Fails with [`RouteError::Forbidden`] if the player exists and is not owned by the account.
+/// Missing players are left for the route to report.
+pub async fn authorize_player(
+    database: &impl ConnectionTrait,
+    account_id: AccountId,
+    player_id: PlayerId,
+) -> Result<(), RouteError> {
+    match Player::from_id(database, player_id).await {
+        Ok(player) if player.account_id() == Some(account_id) => Ok(()),
+        Ok(_) => Err(RouteError::Forbidden(player_id)),
+        Err(PlayerError::NotFound(_)) => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Whether a token with the given scope may be used for a request.
+pub fn scope_allows(scope: TokenScope, method: &Method, path: &str) -> bool {
+    let is_in = |collection: &str| {
+        path.strip_prefix(collection)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    match scope {
+        TokenScope::Full => true,
+        _ if path == WEBSOCKET_ROUTE => true,
+        TokenScope::ReadOnly => method.is_safe(),
+        TokenScope::Tasks => is_in("/api/tasks"),
+        TokenScope::Habits => is_in("/api/habits"),
+    }
+}
+
+/// The player a request acts on, found from the `{id}` of a player, task, habit or daily in the
+/// path or from the `player` query parameter.
+async fn requested_player(
+    database: &impl ConnectionTrait,
+    request: &ServiceRequest,
+) -> Result<Option<PlayerId>, RouteError> {
+    let mut segments = request.path().trim_start_matches("/api/").split('/');
+    let collection = segments.next();
+    let id = segments.next().and_then(|id| id.parse().ok());
+    let player_id = match (collection, id) {
+        (Some("players"), Some(id)) => Some(PlayerId(id)),
+        (Some("tasks"), Some(id)) => match Task::from_id(database, TaskId(id)).await {
+            Ok(task) => Some(task.player_id()),
+            Err(TaskError::NotFound(_)) => None,
+            Err(error) => return Err(error.into()),
+        },
+        (Some("habits"), Some(id)) => match Habit::from_id(database, HabitId(id)).await {
+            Ok(habit) => Some(habit.player()),
+            Err(HabitError::NotFound(_)) => None,
+            Err(error) => return Err(error.into()),
+        },
+        (Some("dailies"), Some(id)) => match Daily::from_id(database, DailyId(id)).await {
+            Ok(daily) => Some(daily.player_id()),
+            Err(DailyError::NotFound(_)) => None,
+            Err(error) => return Err(error.into()),
+        },
+        _ => None,
+    };
+    if player_id.is_some() {
+        return Ok(player_id);
+    }
+    // Malformed queries are left for the route to report.
+    Ok(
+        web::Query::<HashMap<String, String>>::from_query(request.query_string())
+            .ok()
+            .and_then(|query| query.get("player")?.parse().ok())
+            .map(PlayerId),
+    )
+}
+
+/// Authenticates the request by its API token or session cookie and checks that the caller owns the requested
+/// player, if any. Admin routes instead require the admin secret.
+async fn authenticate(request: &ServiceRequest) -> Result<(), RouteError> {
+    let state = request
+        .app_data::<web::Data<State>>()
+        .expect("State should be registered on the app.");
+    let database = state.database();
+
+    // API tokens take precedence over sessions, and are rejected outright if they are invalid.
+    let (account_id, scope) = if let Some(authorization) = request.headers().get(AUTHORIZATION) {
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .ok_or(RouteError::Unauthenticated)?;
+        let (account_id, scope) = ApiToken::authenticate(database, token)
+            .await?
+            .ok_or(RouteError::Unauthenticated)?;
+        (Some(account_id), scope)
+    } else {
+        let account_id = match request.cookie(SESSION_COOKIE) {
+            Some(cookie) => Session::authenticate(database, cookie.value()).await?,
+            None => None,
+        };
+        (account_id, TokenScope::Full)
+    };
+    if let Some(account_id) = account_id {
+        request.extensions_mut().insert(account_id);
+        request.extensions_mut().insert(scope);
+    }
+
+    let path = request.path();
+    if path.starts_with("/api/admin/") {
+        return admin::authorize_admin(state, request);
+    }
+    if path.starts_with("/api/") && !PUBLIC_ROUTES.contains(&path) {
+        let account_id = account_id.ok_or(RouteError::Unauthenticated)?;
+        if !scope_allows(scope, request.method(), path) {
+            return Err(RouteError::InsufficientScope(scope));
+        }
+        if let Some(player_id) = requested_player(database, request).await? {
+            authorize_player(database, account_id, player_id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Middleware making the [`AccountId`] and [`TokenScope`] of the caller available to routes, and
+/// rejecting unauthenticated requests, requests outside the scope of the caller's API token and
+/// requests for players the caller does not own.
+pub async fn authorize(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    match authenticate(&request).await {
+        Ok(()) => next
+            .call(request)
+            .await
+            .map(ServiceResponse::map_into_left_body),
+        Err(error) => Ok(request.error_response(error).map_into_right_body()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_http::Request;
+    use actix_service::Service;
+    use actix_web::{
+        body::MessageBody,
+        cookie::Cookie,
+        dev::ServiceResponse,
+        http::StatusCode,
+        test::{self as actix_test, TestRequest},
+    };
+
+    use habi2ca_database::migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+
+    use crate::{
+        logic::{
+            account::{Account, Credentials},
+            player::Player,
+            task::{Task, TaskData},
+        },
+        routes::{auth::SESSION_COOKIE, ErrorCode},
+        start::create_app,
+        test_utils,
+    };
+
+    /// Database created by the first released version of the server, before accounts existed.
+    const INITIAL_FIXTURE: &[u8] =
+        include_bytes!("../../../habi2ca-database/fixtures/m20240727_133538_initial.db");
+
+    fn credentials(username: &str, password: &str) -> Credentials {
+        Credentials {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    /// Logs in and returns the session cookie.
+    async fn login<M, S, E>(app: &S, credentials: &Credentials) -> Cookie<'static>
+    where
+        M: MessageBody,
+        S: Service<Request, Response = ServiceResponse<M>, Error = E>,
+        E: std::fmt::Debug,
+    {
+        let response = actix_test::call_service(
+            app,
+            TestRequest::post()
+                .uri("/api/auth/login")
+                .set_json(credentials)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        response
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == SESSION_COOKIE)
+            .expect("Login should set the session cookie.")
+            .into_owned()
+    }
+
+    #[tokio::test]
+    async fn register_and_login() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+        let alice = credentials("alice", "correct horse");
+
+        let account: Account = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/auth/register")
+                .set_json(&alice)
+                .to_request(),
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/auth/register")
+                .set_json(&alice)
+                .to_request(),
+            StatusCode::CONFLICT,
+            ErrorCode::UsernameTaken,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/auth/register")
+                .set_json(credentials("bob", "short"))
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidPassword,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/auth/login")
+                .set_json(credentials("alice", "wrong horse"))
+                .to_request(),
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::InvalidCredentials,
+        )
+        .await;
+
+        let cookie = login(&app, &alice).await;
+        let me: Account = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/auth/me")
+                .cookie(cookie.clone())
+                .to_request(),
+        )
+        .await;
+        assert_eq!(me.id(), account.id());
+
+        let response = actix_test::call_service(
+            &app,
+            TestRequest::post()
+                .uri("/api/auth/logout")
+                .cookie(cookie.clone())
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/auth/me")
+                .cookie(cookie)
+                .to_request(),
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::Unauthenticated,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn unauthenticated() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+        let invalid_session = Cookie::new(SESSION_COOKIE, "invalid");
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players")
+                .cookie(invalid_session.clone())
+                .to_request(),
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::Unauthenticated,
+        )
+        .await;
+
+        let response = actix_test::call_service(
+            &app,
+            TestRequest::get()
+                .uri("/api/levels")
+                .cookie(invalid_session)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn players_of_other_accounts() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let task_data = TaskData {
+            player_id: player.id(),
+            name: "Task".to_string(),
+            description: "Description".to_string(),
+            completed: false,
+        };
+        let task = Task::create(&database, task_data.clone()).await.unwrap();
+        let mallory = credentials("mallory", "correct horse");
+        Account::create(&database, mallory.clone()).await.unwrap();
+
+        let app = actix_test::init_service(create_app(database)).await;
+        let cookie = login(&app, &mallory).await;
+
+        let players: Vec<Player> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players")
+                .cookie(cookie.clone())
+                .to_request(),
+        )
+        .await;
+        assert!(players.is_empty());
+        let tasks: Vec<Task> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/tasks")
+                .cookie(cookie.clone())
+                .to_request(),
+        )
+        .await;
+        assert!(tasks.is_empty());
+
+        for request in [
+            TestRequest::get().uri(&format!("/api/players/{}", player.id())),
+            TestRequest::patch().uri(&format!("/api/players/{}/add_xp?xp=100", player.id())),
+            TestRequest::get().uri(&format!("/api/tasks?player={}", player.id())),
+            TestRequest::patch().uri(&format!("/api/tasks/{}/complete", task.id())),
+            TestRequest::post().uri("/api/tasks").set_json(&task_data),
+        ] {
+            test_utils::assert_error_response(
+                &app,
+                request.cookie(cookie.clone()).to_request(),
+                StatusCode::FORBIDDEN,
+                ErrorCode::Forbidden,
+            )
+            .await;
+        }
+
+        // The owner still has access.
+        let _: Player = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/players/{}", player.id()))
+                .to_request(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn first_account_gets_players_from_before_accounts() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("data.db");
+        std::fs::write(&path, INITIAL_FIXTURE).unwrap();
+        let database = Database::connect(format!("sqlite:{}?mode=rw", path.display()))
+            .await
+            .unwrap();
+        Migrator::up(&database, None).await.unwrap();
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let alice = credentials("alice", "correct horse");
+        let bob = credentials("bob", "battery staple");
+        for credentials in [&alice, &bob] {
+            let _: Account = test_utils::assert_ok_response(
+                &app,
+                TestRequest::post()
+                    .uri("/api/auth/register")
+                    .set_json(credentials)
+                    .to_request(),
+            )
+            .await;
+        }
+
+        let cookie = login(&app, &alice).await;
+        let players: Vec<Player> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players")
+                .cookie(cookie.clone())
+                .to_request(),
+        )
+        .await;
+        assert_eq!(players.len(), 2);
+        let tasks: Vec<Task> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/tasks")
+                .cookie(cookie)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(tasks.len(), 2);
+
+        let cookie = login(&app, &bob).await;
+        let players: Vec<Player> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players")
+                .cookie(cookie)
+                .to_request(),
+        )
+        .await;
+        assert!(players.is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    delete, get, patch, post, route,
+    web::{self, Json},
+    HttpRequest, Responder, Scope,
+};
+use habi2ca_database::{account::AccountId, daily::DailyId, player::PlayerId};
+
+use crate::{
+    logic::daily::{Daily, DailyData, DailyUpdate},
+    routes::{authorize_player, id_parameter, RouteError},
+    state::State,
+};
+
+#[post("")]
+pub async fn create_daily(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    daily: Json<DailyData>,
+) -> Result<impl Responder, RouteError> {
+    authorize_player(state.database(), *account_id, daily.player_id).await?;
+    let daily = Daily::create(state.database(), daily.into_inner()).await?;
+    Ok(web::Json(daily))
+}
+
+#[get("")]
+pub async fn get_dailies(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<impl Responder, RouteError> {
+    let player_id = query
+        .get("player")
+        .map(|s| {
+            s.parse()
+                .map(PlayerId)
+                .map_err(|error| RouteError::InvalidParameter {
+                    name: "player",
+                    reason: format!("Failed to parse player id '{s}': {error}"),
+                })
+        })
+        .transpose()?;
+
+    let result = if let Some(player_id) = player_id {
+        Daily::player_dailies(state.database(), player_id).await?
+    } else {
+        Daily::account_dailies(state.database(), *account_id).await?
+    };
+    Ok(web::Json(result))
+}
+
+#[get("/{id}")]
+pub async fn get_daily(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let daily_id: DailyId = id_parameter(&request)?;
+    let daily = Daily::from_id(state.database(), daily_id).await?;
+    Ok(web::Json(daily))
+}
+
+#[route("/{id}", method = "PUT", method = "PATCH")]
+pub async fn update_daily(
+    state: web::Data<State>,
+    request: HttpRequest,
+    update: Json<DailyUpdate>,
+) -> Result<impl Responder, RouteError> {
+    let daily_id: DailyId = id_parameter(&request)?;
+    let mut daily = Daily::from_id(state.database(), daily_id).await?;
+    daily.update(state.database(), update.into_inner()).await?;
+    Ok(web::Json(daily))
+}
+
+#[delete("/{id}")]
+pub async fn delete_daily(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let daily_id: DailyId = id_parameter(&request)?;
+    let daily = Daily::from_id(state.database(), daily_id).await?;
+    let daily = daily.delete(state.database()).await?;
+    Ok(web::Json(daily))
+}
+
+#[patch("/{id}/complete")]
+pub async fn complete_daily(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let daily_id: DailyId = id_parameter(&request)?;
+    let mut daily = Daily::from_id(state.database(), daily_id).await?;
+    daily
+        .complete(state.database())
+        .await?
+        .publish(state.events());
+    Ok(web::Json(daily))
+}
+
+#[patch("/{id}/uncomplete")]
+pub async fn uncomplete_daily(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let daily_id: DailyId = id_parameter(&request)?;
+    let mut daily = Daily::from_id(state.database(), daily_id).await?;
+    daily
+        .uncomplete(state.database())
+        .await?
+        .publish(state.events());
+    Ok(web::Json(daily))
+}
+
+pub fn add_routes(scope: Scope) -> Scope {
+    scope
+        .service(create_daily)
+        .service(get_dailies)
+        .service(get_daily)
+        .service(update_daily)
+        .service(delete_daily)
+        .service(complete_daily)
+        .service(uncomplete_daily)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        test::{self as actix_test, TestRequest},
+    };
+    use chrono::{NaiveDate, Weekday};
+    use habi2ca_database::daily::Schedule;
+    use sea_orm::DatabaseConnection;
+
+    use crate::{
+        events::{EventBus, PlayerEvent},
+        logic::{
+            daily::{Daily, DailyData},
+            player::{Player, MAX_HEALTH},
+            xp_event::XpSource,
+        },
+        routes::ErrorCode,
+        start::create_app,
+        test_utils,
+    };
+
+    // A Monday.
+    const START: NaiveDate = NaiveDate::from_ymd_opt(2026, 10, 12).unwrap();
+
+    async fn setup_database() -> (DatabaseConnection, Player) {
+        let database = test_utils::setup_database().await;
+
+        let mut player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        player.set_last_rollover(&database, START).await.unwrap();
+
+        (database, player)
+    }
+
+    fn daily_data(player: &Player, schedule: Schedule) -> DailyData {
+        DailyData {
+            player_id: player.id(),
+            name: "Daily1".to_string(),
+            description: "Description1".to_string(),
+            schedule,
+            start_date: Some(START),
+        }
+    }
+
+    #[test]
+    fn schedules() {
+        let day = |day| START + chrono::Days::new(day);
+
+        let weekly = Schedule::Weekly {
+            weekdays: vec![Weekday::Mon, Weekday::Thu],
+        };
+        assert!(weekly.is_due(START, day(0)));
+        assert!(!weekly.is_due(START, day(1)));
+        assert!(weekly.is_due(START, day(3)));
+        assert!(weekly.is_due(START, day(7)));
+        assert!(!weekly.is_due(day(1), day(0)));
+
+        let every_3_days = Schedule::EveryNDays { interval: 3 };
+        assert!(every_3_days.is_due(START, day(0)));
+        assert!(!every_3_days.is_due(START, day(2)));
+        assert!(every_3_days.is_due(START, day(6)));
+
+        let monthly = Schedule::Monthly { day: 31 };
+        let date = |month, day| NaiveDate::from_ymd_opt(2026, month, day).unwrap();
+        assert!(monthly.is_due(START, date(10, 31)));
+        assert!(!monthly.is_due(START, date(11, 29)));
+        assert!(monthly.is_due(START, date(11, 30)));
+    }
+
+    #[tokio::test]
+    async fn create_daily() {
+        let (database, player) = setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let request = TestRequest::post()
+            .uri("/api/dailies")
+            .set_json(daily_data(
+                &player,
+                Schedule::Weekly {
+                    weekdays: vec![Weekday::Mon],
+                },
+            ))
+            .to_request();
+        let daily: Daily = test_utils::assert_ok_response(&app, request).await;
+        assert_eq!(daily.completed(), false);
+        assert_eq!(daily.streak(), 0);
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/dailies")
+                .set_json(daily_data(&player, Schedule::EveryNDays { interval: 0 }))
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidSchedule,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn complete_daily() {
+        let (database, player) = setup_database().await;
+        let daily = Daily::create(
+            &database,
+            daily_data(&player, Schedule::EveryNDays { interval: 1 }),
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        let response: Daily = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/dailies/{}/complete", daily.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.completed(), true);
+        let post_player = Player::from_id(&database, player.id()).await.unwrap();
+        assert!(post_player.xp() > player.xp());
+
+        let response: Daily = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/dailies/{}/uncomplete", daily.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.completed(), false);
+        let post_player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(post_player.xp(), player.xp());
+    }
+
+    #[tokio::test]
+    async fn uncomplete_daily_without_xp() {
+        let (database, player) = setup_database().await;
+        let mut daily = Daily::create(
+            &database,
+            daily_data(&player, Schedule::EveryNDays { interval: 1 }),
+        )
+        .await
+        .unwrap();
+        let _ = daily.complete(&database).await.unwrap();
+        let mut player = Player::from_id(&database, player.id()).await.unwrap();
+        let _ = player
+            .add_xp(&database, -player.xp(), XpSource::Manual)
+            .await
+            .unwrap();
+
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        // The player already lost the xp, so uncompleting takes back nothing.
+        let response: Daily = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/dailies/{}/uncomplete", daily.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.completed(), false);
+        let post_player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(post_player.xp(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn complete_daily_not_due() {
+        let (database, player) = setup_database().await;
+        let daily = Daily::create(
+            &database,
+            daily_data(
+                &player,
+                Schedule::Weekly {
+                    weekdays: vec![Weekday::Tue],
+                },
+            ),
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/dailies/{}/complete", daily.id()))
+                .to_request(),
+            StatusCode::CONFLICT,
+            ErrorCode::DailyNotDue,
+        )
+        .await;
+        let daily = Daily::from_id(&database, daily.id()).await.unwrap();
+        assert_eq!(daily.completed(), false);
+        let post_player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(post_player.xp(), player.xp());
+
+        // The daily is due once the player's day is a Tuesday.
+        Daily::rollover_player(&database, player.id(), START.succ_opt().unwrap())
+            .await
+            .unwrap()
+            .unwrap()
+            .publish(&EventBus::new());
+        let response: Daily = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/dailies/{}/complete", daily.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.completed(), true);
+    }
+
+    #[tokio::test]
+    async fn concurrent_completion() {
+        let (database, player) = setup_database().await;
+        let daily = Daily::create(
+            &database,
+            daily_data(&player, Schedule::EveryNDays { interval: 1 }),
+        )
+        .await
+        .unwrap();
+
+        // Both requests load the daily before either completes it.
+        let mut first = Daily::from_id(&database, daily.id()).await.unwrap();
+        let mut second = Daily::from_id(&database, daily.id()).await.unwrap();
+        let (first_result, second_result) =
+            tokio::join!(first.complete(&database), second.complete(&database));
+        let _ = first_result.unwrap();
+        let _ = second_result.unwrap();
+        let post_player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(post_player.xp(), player.xp() + 1.0);
+    }
+
+    #[tokio::test]
+    async fn rollover() {
+        let (database, player) = setup_database().await;
+        let mut completed_daily = Daily::create(
+            &database,
+            daily_data(&player, Schedule::EveryNDays { interval: 1 }),
+        )
+        .await
+        .unwrap();
+        let missed_daily = Daily::create(
+            &database,
+            daily_data(
+                &player,
+                Schedule::Weekly {
+                    weekdays: vec![Weekday::Mon],
+                },
+            ),
+        )
+        .await
+        .unwrap();
+        let not_due_daily = Daily::create(
+            &database,
+            daily_data(
+                &player,
+                Schedule::Weekly {
+                    weekdays: vec![Weekday::Tue],
+                },
+            ),
+        )
+        .await
+        .unwrap();
+        let _ = completed_daily.complete(&database).await.unwrap();
+
+        // Rolling over to the current day does nothing.
+        assert!(Daily::rollover_player(&database, player.id(), START)
+            .await
+            .unwrap()
+            .is_none());
+
+        let events = EventBus::new();
+        let mut listener = events.listen();
+        let tomorrow = START.succ_opt().unwrap();
+        assert_eq!(
+            Daily::rollover_all(&database, &events, tomorrow)
+                .await
+                .unwrap(),
+            1
+        );
+        let event = listener.try_recv().unwrap();
+        assert_eq!(event.player_id, player.id());
+        assert!(matches!(event.event, PlayerEvent::DamageTaken { .. }));
+        assert!(listener.try_recv().is_err());
+
+        let completed_daily = Daily::from_id(&database, completed_daily.id())
+            .await
+            .unwrap();
+        assert_eq!(completed_daily.completed(), false);
+        assert_eq!(completed_daily.streak(), 1);
+
+        let missed_daily = Daily::from_id(&database, missed_daily.id()).await.unwrap();
+        assert_eq!(missed_daily.streak(), 0);
+
+        let not_due_daily = Daily::from_id(&database, not_due_daily.id()).await.unwrap();
+        assert_eq!(not_due_daily.streak(), 0);
+
+        let player = Player::from_id(&database, player.id()).await.unwrap();
+        assert!(player.health() < MAX_HEALTH);
+        assert_eq!(player.last_rollover(), Some(tomorrow));
+
+        // Rolling over again on the same day does nothing.
+        assert_eq!(
+            Daily::rollover_all(&database, &events, tomorrow)
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(listener.try_recv().is_err());
+        let player_after = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(player_after.health(), player.health());
+    }
+}
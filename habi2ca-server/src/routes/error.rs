@@ -0,0 +1,417 @@
+use std::error::Error as StdError;
+
+use actix_web::{
+    error::JsonPayloadError,
+    http::{header, StatusCode},
+    web, HttpResponse, ResponseError,
+};
+use habi2ca_database::{api_token::TokenScope, player::PlayerId};
+use sea_orm::{DbErr, SqlErr};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::error;
+
+use crate::{
+    backup::BackupError,
+    logic::{
+        account::AccountError, api_token::ApiTokenError, daily::DailyError, habit::HabitError,
+        level::LevelError, party::PartyError, player::PlayerError, task::TaskError,
+    },
+    tracing::LogFilterError,
+};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Stable, machine-readable identifier of an error.
+/// Clients should match on this instead of the human-readable parts of a [`Problem`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    MissingParameter,
+    InvalidParameter,
+    MalformedBody,
+    InvalidBody,
+    PlayerNotFound,
+    TaskNotFound,
+    HabitNotFound,
+    DailyNotFound,
+    LevelNotFound,
+    UnknownPlayer,
+    InvalidXp,
+    InvalidDamage,
+    HabitNotPositive,
+    HabitNotNegative,
+    InvalidHabitDirection,
+    InvalidSchedule,
+    DailyNotDue,
+    PartyNotFound,
+    InvalidPartyName,
+    AlreadyInParty,
+    NotInParty,
+    AccountNotFound,
+    UsernameTaken,
+    InvalidUsername,
+    InvalidPassword,
+    InvalidCredentials,
+    Unauthenticated,
+    Forbidden,
+    InsufficientScope,
+    AdminDisabled,
+    InvalidAdminSecret,
+    BackupsUnavailable,
+    BackupNotFound,
+    InvalidBackup,
+    IncompatibleBackup,
+    LoggingUnavailable,
+    InvalidLogFilter,
+    InvalidHandshake,
+    TooManySubscriptions,
+    ApiTokenNotFound,
+    InvalidTokenName,
+    Conflict,
+    DatabaseUnavailable,
+    PendingMigrations,
+    InternalError,
+}
+
+/// RFC 7807 problem details body returned for every failed request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: ErrorCode,
+}
+
+#[derive(Debug, Error)]
+pub enum RouteError {
+    #[error("Missing '{0}' parameter.")]
+    MissingParameter(&'static str),
+    #[error("Invalid '{name}' parameter: {reason}")]
+    InvalidParameter { name: &'static str, reason: String },
+    #[error("Malformed request body: {0}")]
+    MalformedBody(String),
+    #[error("Invalid request body: {0}")]
+    InvalidBody(String),
+    #[error("You must be logged in to access this resource.")]
+    Unauthenticated,
+    #[error("You do not have access to player {0}.")]
+    Forbidden(PlayerId),
+    #[error("API tokens with scope {0:?} cannot be used for this request.")]
+    InsufficientScope(TokenScope),
+    #[error("Admin routes are disabled since no admin secret is configured.")]
+    AdminDisabled,
+    #[error("Missing or invalid admin secret.")]
+    InvalidAdminSecret,
+    #[error("Backups are only available for databases stored in a file.")]
+    BackupsUnavailable,
+    #[error("Log filters can only be changed while the server is running.")]
+    LoggingUnavailable,
+    #[error("Invalid WebSocket handshake: {0}")]
+    InvalidHandshake(String),
+    #[error("A connection can subscribe to at most {0} channels.")]
+    TooManySubscriptions(usize),
+    #[error("The database is unreachable.")]
+    DatabaseUnreachable(#[source] DbErr),
+    #[error("Migrations {0:?} have not been applied to the database.")]
+    PendingMigrations(Vec<String>),
+    #[error(transparent)]
+    Player(#[from] PlayerError),
+    #[error(transparent)]
+    Task(#[from] TaskError),
+    #[error(transparent)]
+    Habit(#[from] HabitError),
+    #[error(transparent)]
+    Daily(#[from] DailyError),
+    #[error(transparent)]
+    Level(#[from] LevelError),
+    #[error(transparent)]
+    Party(#[from] PartyError),
+    #[error(transparent)]
+    Account(#[from] AccountError),
+    #[error(transparent)]
+    ApiToken(#[from] ApiTokenError),
+    #[error(transparent)]
+    Backup(#[from] BackupError),
+    #[error(transparent)]
+    LogFilter(#[from] LogFilterError),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+fn database_error_kind(error: &DbErr) -> (StatusCode, ErrorCode) {
+    match error.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(_) | SqlErr::ForeignKeyConstraintViolation(_)) => {
+            (StatusCode::CONFLICT, ErrorCode::Conflict)
+        }
+        _ => match error {
+            DbErr::ConnectionAcquire(_) | DbErr::Conn(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::DatabaseUnavailable,
+            ),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError),
+        },
+    }
+}
+
+fn level_error_kind(error: &LevelError) -> (StatusCode, ErrorCode) {
+    match error {
+        LevelError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::LevelNotFound),
+        LevelError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn player_error_kind(error: &PlayerError) -> (StatusCode, ErrorCode) {
+    match error {
+        PlayerError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::PlayerNotFound),
+        PlayerError::InvalidXp(_) => (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidXp),
+        PlayerError::InvalidDamage(_) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidDamage)
+        }
+        // A player pointing at a missing level is a broken invariant, not a client error.
+        PlayerError::LevelNotFound { .. } | PlayerError::Level(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+        }
+        PlayerError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn task_error_kind(error: &TaskError) -> (StatusCode, ErrorCode) {
+    match error {
+        TaskError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::TaskNotFound),
+        TaskError::UnknownPlayer(_) => (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::UnknownPlayer),
+        TaskError::Player { source, .. } => player_error_kind(source),
+        TaskError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn habit_error_kind(error: &HabitError) -> (StatusCode, ErrorCode) {
+    match error {
+        HabitError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::HabitNotFound),
+        HabitError::NotPositive(_) => (StatusCode::CONFLICT, ErrorCode::HabitNotPositive),
+        HabitError::NotNegative(_) => (StatusCode::CONFLICT, ErrorCode::HabitNotNegative),
+        HabitError::InvalidDirection(_) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidHabitDirection,
+        ),
+        HabitError::UnknownPlayer(_) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::UnknownPlayer)
+        }
+        HabitError::Player { source, .. } => player_error_kind(source),
+        HabitError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn daily_error_kind(error: &DailyError) -> (StatusCode, ErrorCode) {
+    match error {
+        DailyError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::DailyNotFound),
+        DailyError::UnknownPlayer(_) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::UnknownPlayer)
+        }
+        DailyError::InvalidSchedule(_) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidSchedule)
+        }
+        DailyError::NotDue { .. } => (StatusCode::CONFLICT, ErrorCode::DailyNotDue),
+        DailyError::Player { source, .. } => player_error_kind(source),
+        DailyError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn party_error_kind(error: &PartyError) -> (StatusCode, ErrorCode) {
+    match error {
+        PartyError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::PartyNotFound),
+        PartyError::InvalidName(_) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidPartyName,
+        ),
+        PartyError::UnknownPlayer(_) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::UnknownPlayer)
+        }
+        PartyError::AlreadyInParty(_) => (StatusCode::CONFLICT, ErrorCode::AlreadyInParty),
+        PartyError::NotAMember { .. } => (StatusCode::NOT_FOUND, ErrorCode::NotInParty),
+        PartyError::Player { source, .. } => player_error_kind(source),
+        PartyError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn account_error_kind(error: &AccountError) -> (StatusCode, ErrorCode) {
+    match error {
+        AccountError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::AccountNotFound),
+        AccountError::UsernameTaken(_) => (StatusCode::CONFLICT, ErrorCode::UsernameTaken),
+        AccountError::InvalidUsername(_) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidUsername)
+        }
+        AccountError::InvalidPassword(_) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidPassword)
+        }
+        AccountError::InvalidCredentials => {
+            (StatusCode::UNAUTHORIZED, ErrorCode::InvalidCredentials)
+        }
+        AccountError::PasswordHash(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+        }
+        AccountError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn api_token_error_kind(error: &ApiTokenError) -> (StatusCode, ErrorCode) {
+    match error {
+        ApiTokenError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::ApiTokenNotFound),
+        ApiTokenError::InvalidName(_) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidTokenName,
+        ),
+        ApiTokenError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn backup_error_kind(error: &BackupError) -> (StatusCode, ErrorCode) {
+    match error {
+        BackupError::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::BackupNotFound),
+        BackupError::Corrupt { .. } => (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidBackup),
+        BackupError::UnknownMigrations { .. } => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::IncompatibleBackup,
+        ),
+        BackupError::Create(_) | BackupError::Io(_) | BackupError::Restore(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+        }
+        BackupError::Database(error) => database_error_kind(error),
+    }
+}
+
+fn log_filter_error_kind(error: &LogFilterError) -> (StatusCode, ErrorCode) {
+    match error {
+        LogFilterError::Invalid { .. } => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidLogFilter,
+        ),
+        LogFilterError::NoLogFiles => (StatusCode::CONFLICT, ErrorCode::LoggingUnavailable),
+        LogFilterError::Reload(_) => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError),
+    }
+}
+
+/// Formats an error together with all of its sources.
+pub(super) fn error_chain(error: &dyn StdError) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+impl RouteError {
+    pub fn kind(&self) -> (StatusCode, ErrorCode) {
+        match self {
+            RouteError::MissingParameter(_) => {
+                (StatusCode::BAD_REQUEST, ErrorCode::MissingParameter)
+            }
+            RouteError::InvalidParameter { .. } => {
+                (StatusCode::BAD_REQUEST, ErrorCode::InvalidParameter)
+            }
+            RouteError::MalformedBody(_) => (StatusCode::BAD_REQUEST, ErrorCode::MalformedBody),
+            RouteError::InvalidBody(_) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidBody)
+            }
+            RouteError::Unauthenticated => (StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated),
+            RouteError::Forbidden(_) => (StatusCode::FORBIDDEN, ErrorCode::Forbidden),
+            RouteError::InsufficientScope(_) => {
+                (StatusCode::FORBIDDEN, ErrorCode::InsufficientScope)
+            }
+            RouteError::AdminDisabled => (StatusCode::FORBIDDEN, ErrorCode::AdminDisabled),
+            RouteError::InvalidAdminSecret => {
+                (StatusCode::UNAUTHORIZED, ErrorCode::InvalidAdminSecret)
+            }
+            RouteError::BackupsUnavailable => (StatusCode::CONFLICT, ErrorCode::BackupsUnavailable),
+            RouteError::LoggingUnavailable => (StatusCode::CONFLICT, ErrorCode::LoggingUnavailable),
+            RouteError::InvalidHandshake(_) => {
+                (StatusCode::BAD_REQUEST, ErrorCode::InvalidHandshake)
+            }
+            RouteError::TooManySubscriptions(_) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::TooManySubscriptions,
+            ),
+            RouteError::DatabaseUnreachable(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::DatabaseUnavailable,
+            ),
+            RouteError::PendingMigrations(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::PendingMigrations,
+            ),
+            RouteError::Player(error) => player_error_kind(error),
+            RouteError::Task(error) => task_error_kind(error),
+            RouteError::Habit(error) => habit_error_kind(error),
+            RouteError::Daily(error) => daily_error_kind(error),
+            RouteError::Level(error) => level_error_kind(error),
+            RouteError::Party(error) => party_error_kind(error),
+            RouteError::Account(error) => account_error_kind(error),
+            RouteError::ApiToken(error) => api_token_error_kind(error),
+            RouteError::Backup(error) => backup_error_kind(error),
+            RouteError::LogFilter(error) => log_filter_error_kind(error),
+            RouteError::Internal(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+            }
+        }
+    }
+
+    pub fn problem(&self) -> Problem {
+        let (status, code) = self.kind();
+        let detail = if status.is_server_error() {
+            // Internal details are logged rather than leaked to the client.
+            "An internal error occurred.".to_owned()
+        } else {
+            error_chain(self)
+        };
+        Problem {
+            problem_type: "about:blank".to_owned(),
+            title: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: status.as_u16(),
+            detail,
+            code,
+        }
+    }
+}
+
+impl ResponseError for RouteError {
+    fn status_code(&self) -> StatusCode {
+        self.kind().0
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = self.problem();
+        if self.status_code().is_server_error() {
+            error!("{:?}: {}", problem.code, error_chain(self));
+        }
+        HttpResponse::build(self.status_code())
+            .insert_header((header::CONTENT_TYPE, PROBLEM_JSON))
+            .json(problem)
+    }
+}
+
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|error, _request| {
+        match error {
+            JsonPayloadError::Deserialize(error) if error.is_data() => {
+                RouteError::InvalidBody(error.to_string())
+            }
+            error => RouteError::MalformedBody(error.to_string()),
+        }
+        .into()
+    })
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|error, _request| {
+        RouteError::InvalidParameter {
+            name: "query",
+            reason: error.to_string(),
+        }
+        .into()
+    })
+}
@@ -1,45 +1,49 @@
 use actix_web::{
-    get, patch, post,
+    delete, get, patch, post, route,
     web::{self, Json},
     HttpRequest, Responder, Scope,
 };
-use anyhow::Context;
+use habi2ca_database::{account::AccountId, habit::HabitId, player::PlayerId};
 
 use crate::{
-    logic::habit::{Habit, HabitData},
-    routes::RouteError,
+    logic::habit::{Habit, HabitData, HabitUpdate},
+    routes::{authorize_player, id_parameter, RouteError},
     state::State,
 };
 
 #[post("")]
 pub async fn create_habit(
     state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
     habit: Json<HabitData>,
 ) -> Result<impl Responder, RouteError> {
-    let habit = Habit::create(state.database(), habit.into_inner())
-        .await
-        .context("Failed to create habit.")?;
+    authorize_player(state.database(), *account_id, habit.player_id).await?;
+    let habit = Habit::create(state.database(), habit.into_inner()).await?;
     Ok(web::Json(habit))
 }
 
 #[get("")]
 pub async fn get_habits(
     state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
     query: web::Query<std::collections::HashMap<String, String>>,
 ) -> Result<impl Responder, RouteError> {
     let player_id = query
         .get("player")
         .map(|s| {
             s.parse()
-                .with_context(|| format!("Failed to parse player id '{s}'."))
-                .map(habi2ca_database::player::PlayerId)
+                .map(PlayerId)
+                .map_err(|error| RouteError::InvalidParameter {
+                    name: "player",
+                    reason: format!("Failed to parse player id '{s}': {error}"),
+                })
         })
         .transpose()?;
 
     let result = if let Some(player_id) = player_id {
         Habit::player_habits(state.database(), player_id).await?
     } else {
-        Habit::all_habits(state.database()).await?
+        Habit::account_habits(state.database(), *account_id).await?
     };
     Ok(web::Json(result))
 }
@@ -49,13 +53,8 @@ pub async fn get_habit(
     state: web::Data<State>,
     request: HttpRequest,
 ) -> Result<impl Responder, RouteError> {
-    let habit_id = request
-        .match_info()
-        .load()
-        .context("Missing 'id' parameter")?;
-    let habit = Habit::from_id(state.database(), habit_id)
-        .await
-        .with_context(|| format!("Failed to get habit with id {habit_id}."))?;
+    let habit_id: HabitId = id_parameter(&request)?;
+    let habit = Habit::from_id(state.database(), habit_id).await?;
     Ok(web::Json(habit))
 }
 
@@ -64,17 +63,49 @@ pub async fn increment_habit(
     state: web::Data<State>,
     request: HttpRequest,
 ) -> Result<impl Responder, RouteError> {
-    let habit_id = request
-        .match_info()
-        .load()
-        .context("Missing 'id' parameter")?;
-    let mut habit = Habit::from_id(state.database(), habit_id)
-        .await
-        .with_context(|| format!("Failed to get habit with id {habit_id}."))?;
+    let habit_id: HabitId = id_parameter(&request)?;
+    let mut habit = Habit::from_id(state.database(), habit_id).await?;
     habit
         .increment(state.database())
-        .await
-        .with_context(|| format!("Failed to increment habit with id {habit_id}."))?;
+        .await?
+        .publish(state.events());
+    Ok(web::Json(habit))
+}
+
+#[patch("/{id}/decrement")]
+pub async fn decrement_habit(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let habit_id: HabitId = id_parameter(&request)?;
+    let mut habit = Habit::from_id(state.database(), habit_id).await?;
+    habit
+        .decrement(state.database())
+        .await?
+        .publish(state.events());
+    Ok(web::Json(habit))
+}
+
+#[route("/{id}", method = "PUT", method = "PATCH")]
+pub async fn update_habit(
+    state: web::Data<State>,
+    request: HttpRequest,
+    update: Json<HabitUpdate>,
+) -> Result<impl Responder, RouteError> {
+    let habit_id: HabitId = id_parameter(&request)?;
+    let mut habit = Habit::from_id(state.database(), habit_id).await?;
+    habit.update(state.database(), update.into_inner()).await?;
+    Ok(web::Json(habit))
+}
+
+#[delete("/{id}")]
+pub async fn delete_habit(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let habit_id: HabitId = id_parameter(&request)?;
+    let habit = Habit::from_id(state.database(), habit_id).await?;
+    let habit = habit.delete(state.database()).await?;
     Ok(web::Json(habit))
 }
 
@@ -83,28 +114,44 @@ pub fn add_routes(scope: Scope) -> Scope {
         .service(create_habit)
         .service(get_habits)
         .service(get_habit)
+        .service(update_habit)
+        .service(delete_habit)
         .service(increment_habit)
+        .service(decrement_habit)
 }
 
 #[cfg(test)]
 mod test {
-    use actix_web::test::{self as actix_test, TestRequest};
-    use habi2ca_database::habit::HabitId;
+    use std::iter;
+
+    use actix_web::{
+        http::StatusCode,
+        test::{self as actix_test, TestRequest},
+        web,
+    };
+    use habi2ca_database::{habit::HabitId, level::LevelId};
     use sea_orm::DatabaseConnection;
 
     use crate::{
+        events::PlayerEvent,
         logic::{
-            habit::{Habit, HabitData},
-            player::Player,
+            habit::{Habit, HabitData, HabitUpdate},
+            level::Level,
+            player::{Player, MAX_HEALTH},
+            xp_event::XpSource,
         },
-        start::create_app,
+        routes::ErrorCode,
+        start::{create_app, create_app_with_state},
+        state::State,
         test_utils,
     };
 
     async fn setup_database() -> (DatabaseConnection, Player) {
         let database = test_utils::setup_database().await;
 
-        let player = Player::create(&database, "Alice").await.unwrap();
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
 
         (database, player)
     }
@@ -120,6 +167,8 @@ mod test {
                 player_id: player.id(),
                 name: "Habit1".to_string(),
                 description: "Description1".to_string(),
+                positive: true,
+                negative: false,
             })
             .to_request();
 
@@ -141,6 +190,8 @@ mod test {
                 player_id: player.id(),
                 name: "Habit1".to_string(),
                 description: "Description1".to_string(),
+                positive: true,
+                negative: false,
             },
         )
         .await
@@ -152,6 +203,8 @@ mod test {
                 player_id: player.id(),
                 name: "Habit2".to_string(),
                 description: "Description2".to_string(),
+                positive: true,
+                negative: false,
             },
         )
         .await
@@ -182,7 +235,9 @@ mod test {
     async fn get_player_habits() {
         let (database, player) = setup_database().await;
 
-        let player2 = Player::create(&database, "Bob").await.unwrap();
+        let player2 = Player::create(&database, test_utils::TEST_ACCOUNT, "Bob")
+            .await
+            .unwrap();
 
         let _habit1 = Habit::create(
             &database,
@@ -190,6 +245,8 @@ mod test {
                 player_id: player.id(),
                 name: "Habit1".to_string(),
                 description: "Description1".to_string(),
+                positive: true,
+                negative: false,
             },
         )
         .await
@@ -201,6 +258,8 @@ mod test {
                 player_id: player2.id(),
                 name: "Habit2".to_string(),
                 description: "Description2".to_string(),
+                positive: true,
+                negative: false,
             },
         )
         .await
@@ -232,6 +291,8 @@ mod test {
                 player_id: player.id(),
                 name: "Habit1".to_string(),
                 description: "Description1".to_string(),
+                positive: true,
+                negative: false,
             },
         )
         .await
@@ -261,6 +322,8 @@ mod test {
                 player_id: player.id(),
                 name: "Habit1".to_string(),
                 description: "Description1".to_string(),
+                positive: true,
+                negative: false,
             },
         )
         .await
@@ -287,4 +350,341 @@ mod test {
         assert_eq!(habit.name(), "Habit1");
         assert_eq!(habit.description(), "Description1");
     }
+
+    #[tokio::test]
+    async fn increment_missing_habit() {
+        let (database, _player) = setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri("/api/habits/1/increment")
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::HabitNotFound,
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/habits?player=bob")
+                .to_request(),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidParameter,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn update_habit() {
+        let (database, player) = setup_database().await;
+
+        let habit1 = Habit::create(
+            &database,
+            HabitData {
+                player_id: player.id(),
+                name: "Habit1".to_string(),
+                description: "Description1".to_string(),
+                positive: true,
+                negative: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let habit: Habit = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/habits/{}", habit1.id()))
+                .set_json(HabitUpdate {
+                    description: Some("Changed".to_string()),
+                    ..Default::default()
+                })
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(habit.id(), habit1.id());
+        assert_eq!(habit.name(), "Habit1");
+        assert_eq!(habit.description(), "Changed");
+
+        let habit: Habit = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/habits/{}", habit1.id()))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(habit.name(), "Habit1");
+        assert_eq!(habit.description(), "Changed");
+    }
+
+    #[tokio::test]
+    async fn delete_habit() {
+        let (database, player) = setup_database().await;
+
+        let habit1 = Habit::create(
+            &database,
+            HabitData {
+                player_id: player.id(),
+                name: "Habit1".to_string(),
+                description: "Description1".to_string(),
+                positive: true,
+                negative: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let habit: Habit = test_utils::assert_ok_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("/api/habits/{}", habit1.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(habit.id(), habit1.id());
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/habits/{}", habit1.id()))
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::HabitNotFound,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn create_habit_defaults_to_positive() {
+        let (database, player) = setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let habit: Habit = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/habits")
+                .set_json(serde_json::json!({
+                    "player_id": player.id(),
+                    "name": "Habit1",
+                    "description": "Description1",
+                }))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(habit.positive(), true);
+        assert_eq!(habit.negative(), false);
+    }
+
+    #[tokio::test]
+    async fn habit_without_direction() {
+        let (database, player) = setup_database().await;
+        let habit = Habit::create(
+            &database,
+            HabitData {
+                player_id: player.id(),
+                name: "Habit1".to_string(),
+                description: "Description1".to_string(),
+                positive: true,
+                negative: false,
+            },
+        )
+        .await
+        .unwrap();
+        let app = actix_test::init_service(create_app(database)).await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/habits")
+                .set_json(HabitData {
+                    player_id: player.id(),
+                    name: "Habit2".to_string(),
+                    description: "Description2".to_string(),
+                    positive: false,
+                    negative: false,
+                })
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidHabitDirection,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/habits/{}", habit.id()))
+                .set_json(HabitUpdate {
+                    positive: Some(false),
+                    ..Default::default()
+                })
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidHabitDirection,
+        )
+        .await;
+
+        // Switching the direction in one update is fine.
+        let habit: Habit = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/habits/{}", habit.id()))
+                .set_json(HabitUpdate {
+                    positive: Some(false),
+                    negative: Some(true),
+                    ..Default::default()
+                })
+                .to_request(),
+        )
+        .await;
+        assert_eq!(habit.positive(), false);
+        assert_eq!(habit.negative(), true);
+    }
+
+    #[tokio::test]
+    async fn decrement_habit() {
+        let (database, player) = setup_database().await;
+
+        let habit1 = Habit::create(
+            &database,
+            HabitData {
+                player_id: player.id(),
+                name: "Habit1".to_string(),
+                description: "Description1".to_string(),
+                positive: false,
+                negative: true,
+            },
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        let habit: Habit = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/habits/{}/decrement", habit1.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(habit.id(), habit1.id());
+
+        let post_player = Player::from_id(&database, player.id()).await.unwrap();
+        assert!(post_player.health() < player.health());
+        assert_eq!(post_player.xp(), player.xp());
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/habits/{}/increment", habit1.id()))
+                .to_request(),
+            StatusCode::CONFLICT,
+            ErrorCode::HabitNotPositive,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn decrement_positive_habit() {
+        let (database, player) = setup_database().await;
+
+        let habit1 = Habit::create(
+            &database,
+            HabitData {
+                player_id: player.id(),
+                name: "Habit1".to_string(),
+                description: "Description1".to_string(),
+                positive: true,
+                negative: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/habits/{}/decrement", habit1.id()))
+                .to_request(),
+            StatusCode::CONFLICT,
+            ErrorCode::HabitNotNegative,
+        )
+        .await;
+
+        let post_player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(post_player.health(), MAX_HEALTH);
+    }
+
+    #[tokio::test]
+    async fn decrement_habit_kills_player() {
+        let (database, mut player) = setup_database().await;
+
+        let level_1_xp = Level::from_id(&database, LevelId(1))
+            .await
+            .unwrap()
+            .xp_requirement();
+        let _ = player
+            .add_xp(&database, level_1_xp + 5., XpSource::Manual)
+            .await
+            .unwrap();
+        let _ = player
+            .take_damage(&database, MAX_HEALTH - 0.5)
+            .await
+            .unwrap();
+        assert_eq!(player.level(), LevelId(2));
+
+        let habit1 = Habit::create(
+            &database,
+            HabitData {
+                player_id: player.id(),
+                name: "Habit1".to_string(),
+                description: "Description1".to_string(),
+                positive: true,
+                negative: true,
+            },
+        )
+        .await
+        .unwrap();
+
+        let state = web::Data::new(State::new(database.clone(), None));
+        let mut listener = state.events().listen();
+        let app = actix_test::init_service(create_app_with_state(state)).await;
+
+        let _: Habit = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/habits/{}/decrement", habit1.id()))
+                .to_request(),
+        )
+        .await;
+
+        let player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(player.health(), MAX_HEALTH);
+        assert_eq!(player.level(), LevelId(1));
+        assert_eq!(player.xp(), 0.0);
+
+        let events: Vec<_> = iter::from_fn(|| listener.try_recv().ok()).collect();
+        let names: Vec<_> = events.iter().map(|event| event.event.name()).collect();
+        assert_eq!(names, ["damage_taken", "died"]);
+        match &events[1].event {
+            PlayerEvent::Died {
+                player: died_player,
+                xp_event: Some(xp_event),
+            } => {
+                assert_eq!(died_player.level(), LevelId(1));
+                assert_eq!(xp_event.amount(), -(level_1_xp + 5.));
+            }
+            event => panic!("Expected a death with lost xp, got {event:?}."),
+        }
+    }
 }
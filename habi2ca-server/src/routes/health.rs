@@ -0,0 +1,134 @@
+use actix_web::{get, web, Responder, Scope};
+use habi2ca_database::migration::{Migrator, MigratorTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::{routes::RouteError, state::State};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub status: String,
+}
+
+impl Status {
+    fn new(status: &str) -> Self {
+        Self {
+            status: status.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    /// Version of the server crate.
+    pub version: String,
+    /// Commit the server was built from, or `unknown` if it was built outside the git repository.
+    pub commit: String,
+    /// Name of the latest migration applied to the database.
+    pub schema_version: Option<String>,
+}
+
+/// Succeeds as long as the server is running.
+#[get("/health")]
+pub async fn get_health() -> impl Responder {
+    web::Json(Status::new("ok"))
+}
+
+/// Succeeds if the database is reachable and fully migrated, so the server can handle requests.
+#[get("/ready")]
+pub async fn get_ready(state: web::Data<State>) -> Result<impl Responder, RouteError> {
+    let database = state.database();
+    database
+        .ping()
+        .await
+        .map_err(RouteError::DatabaseUnreachable)?;
+    let pending_migrations = Migrator::get_pending_migrations(database)
+        .await
+        .map_err(RouteError::DatabaseUnreachable)?;
+    if !pending_migrations.is_empty() {
+        return Err(RouteError::PendingMigrations(
+            pending_migrations
+                .iter()
+                .map(|migration| migration.name().to_owned())
+                .collect(),
+        ));
+    }
+    Ok(web::Json(Status::new("ready")))
+}
+
+#[get("/version")]
+pub async fn get_version(state: web::Data<State>) -> Result<impl Responder, RouteError> {
+    let applied_migrations = Migrator::get_applied_migrations(state.database())
+        .await
+        .map_err(RouteError::DatabaseUnreachable)?;
+    Ok(web::Json(Version {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        commit: env!("HABI2CA_GIT_COMMIT").to_owned(),
+        schema_version: applied_migrations
+            .last()
+            .map(|migration| migration.name().to_owned()),
+    }))
+}
+
+pub fn add_routes(scope: Scope) -> Scope {
+    scope
+        .service(get_health)
+        .service(get_ready)
+        .service(get_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        test::{self as actix_test, TestRequest},
+    };
+    use habi2ca_database::migration::{Migrator, MigratorTrait};
+
+    use super::{Status, Version};
+    use crate::{routes::ErrorCode, start::create_app, test_utils};
+
+    #[tokio::test]
+    async fn health_and_readiness() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        // Health checks need no authentication.
+        for (uri, status) in [("/api/health", "ok"), ("/api/ready", "ready")] {
+            let response =
+                actix_test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body: Status = actix_test::read_body_json(response).await;
+            assert_eq!(body, Status::new(status));
+        }
+
+        Migrator::down(&database, Some(1)).await.unwrap();
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get().uri("/api/ready").to_request(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::PendingMigrations,
+        )
+        .await;
+        let _: Status = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get().uri("/api/health").to_request(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn version() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let version: Version = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get().uri("/api/version").to_request(),
+        )
+        .await;
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+        assert!(!version.commit.is_empty());
+        let latest_migration = Migrator::migrations().last().unwrap().name().to_owned();
+        assert_eq!(version.schema_version, Some(latest_migration));
+    }
+}
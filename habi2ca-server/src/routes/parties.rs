@@ -0,0 +1,368 @@
+use actix_web::{
+    delete, get, post,
+    web::{self, Json},
+    HttpRequest, Responder, Scope,
+};
+use habi2ca_database::{account::AccountId, party::PartyId, player::PlayerId};
+use serde::Deserialize;
+
+use crate::{
+    logic::party::{Party, PartyData, PartyError},
+    routes::{authorize_player, id_parameter, RouteError},
+    state::State,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct JoinParty {
+    pub player_id: PlayerId,
+}
+
+#[derive(Debug, Deserialize)]
+struct MemberPath {
+    id: PartyId,
+    player_id: PlayerId,
+}
+
+/// Gets a party the account has a player in. Other parties are reported as not found.
+async fn member_party(
+    state: &State,
+    account_id: AccountId,
+    party_id: PartyId,
+) -> Result<Party, RouteError> {
+    let party = Party::from_id(state.database(), party_id).await?;
+    if !party.has_member_of(account_id) {
+        return Err(PartyError::NotFound(party_id).into());
+    }
+    Ok(party)
+}
+
+#[post("")]
+pub async fn create_party(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    party: Json<PartyData>,
+) -> Result<impl Responder, RouteError> {
+    authorize_player(state.database(), *account_id, party.player_id).await?;
+    let (party, outbox) = Party::create(state.database(), party.into_inner()).await?;
+    outbox.publish(state.events());
+    Ok(web::Json(party))
+}
+
+#[get("")]
+pub async fn get_parties(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+) -> Result<impl Responder, RouteError> {
+    let parties = Party::account_parties(state.database(), *account_id).await?;
+    Ok(web::Json(parties))
+}
+
+#[get("/{id}")]
+pub async fn get_party(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let party_id: PartyId = id_parameter(&request)?;
+    let party = member_party(&state, *account_id, party_id).await?;
+    Ok(web::Json(party))
+}
+
+/// Adds a player of the account to the party. Anyone who knows the id of a party can join it.
+#[post("/{id}/members")]
+pub async fn join_party(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    request: HttpRequest,
+    member: Json<JoinParty>,
+) -> Result<impl Responder, RouteError> {
+    let party_id: PartyId = id_parameter(&request)?;
+    authorize_player(state.database(), *account_id, member.player_id).await?;
+    let mut party = Party::from_id(state.database(), party_id).await?;
+    party
+        .join(state.database(), member.player_id)
+        .await?
+        .publish(state.events());
+    Ok(web::Json(party))
+}
+
+/// Removes a player of the account from the party, disbanding it if the player was its last
+/// member.
+#[delete("/{id}/members/{player_id}")]
+pub async fn leave_party(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let MemberPath { id, player_id } =
+        request
+            .match_info()
+            .load()
+            .map_err(|error| RouteError::InvalidParameter {
+                name: "player_id",
+                reason: error.to_string(),
+            })?;
+    authorize_player(state.database(), *account_id, player_id).await?;
+    let mut party = member_party(&state, *account_id, id).await?;
+    party
+        .leave(state.database(), player_id)
+        .await?
+        .publish(state.events());
+    Ok(web::Json(party))
+}
+
+pub fn add_routes(scope: Scope) -> Scope {
+    scope
+        .service(create_party)
+        .service(get_parties)
+        .service(get_party)
+        .service(join_party)
+        .service(leave_party)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        test::{self as actix_test, TestRequest},
+    };
+    use sea_orm::DatabaseConnection;
+    use serde_json::json;
+
+    use crate::{
+        logic::{
+            account::{Account, Credentials},
+            party::{Party, PartyData, PartyError},
+            player::Player,
+        },
+        routes::ErrorCode,
+        start::create_app,
+        test_utils,
+    };
+
+    /// Creates a player of the test account and a party founded by a player of another account.
+    async fn setup_database() -> (DatabaseConnection, Player, Party) {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let other_account = Account::create(
+            &database,
+            Credentials {
+                username: "mallory".to_owned(),
+                password: "correct horse battery staple".to_owned(),
+            },
+        )
+        .await
+        .unwrap();
+        let founder = Player::create(&database, other_account.id(), "Mallory")
+            .await
+            .unwrap();
+        let (party, _) = Party::create(
+            &database,
+            PartyData {
+                name: "Guild".to_string(),
+                player_id: founder.id(),
+            },
+        )
+        .await
+        .unwrap();
+        (database, player, party)
+    }
+
+    #[tokio::test]
+    async fn create_party() {
+        let (database, player, _) = setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let party: serde_json::Value = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/parties")
+                .set_json(json!({ "name": "Fellowship", "player_id": player.id() }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(party["name"], "Fellowship");
+        assert_eq!(party["members"].as_array().unwrap().len(), 1);
+        assert_eq!(party["members"][0]["player_id"], json!(player.id()));
+        assert_eq!(party["members"][0]["name"], "Alice");
+        assert!(party["members"][0].get("account_id").is_none());
+
+        let parties: serde_json::Value = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get().uri("/api/parties").to_request(),
+        )
+        .await;
+        assert_eq!(parties, json!([party]));
+        let fetched: serde_json::Value = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/parties/{}", party["id"]))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(fetched, party);
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/parties")
+                .set_json(json!({ "name": "Second", "player_id": player.id() }))
+                .to_request(),
+            StatusCode::CONFLICT,
+            ErrorCode::AlreadyInParty,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/parties")
+                .set_json(json!({ "name": " ", "player_id": player.id() }))
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidPartyName,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/parties")
+                .set_json(json!({ "name": "Nobody", "player_id": 1000 }))
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::UnknownPlayer,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn join_and_leave_party() {
+        let (database, player, party) = setup_database().await;
+        let founder = party.members()[0].player_id;
+        let bob = Player::create(&database, test_utils::TEST_ACCOUNT, "Bob")
+            .await
+            .unwrap();
+        let app = actix_test::init_service(create_app(database.clone())).await;
+        let party_uri = format!("/api/parties/{}", party.id());
+
+        // Parties without a player of the account are hidden.
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get().uri(&party_uri).to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::PartyNotFound,
+        )
+        .await;
+
+        let joined: Party = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri(&format!("{party_uri}/members"))
+                .set_json(json!({ "player_id": player.id() }))
+                .to_request(),
+        )
+        .await;
+        let members: Vec<_> = joined
+            .members()
+            .iter()
+            .map(|member| member.player_id)
+            .collect();
+        assert_eq!(members, [founder, player.id()]);
+        let _: Party =
+            test_utils::assert_ok_response(&app, TestRequest::get().uri(&party_uri).to_request())
+                .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri(&format!("{party_uri}/members"))
+                .set_json(json!({ "player_id": player.id() }))
+                .to_request(),
+            StatusCode::CONFLICT,
+            ErrorCode::AlreadyInParty,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/parties/1000/members")
+                .set_json(json!({ "player_id": bob.id() }))
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::PartyNotFound,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("{party_uri}/members/{founder}"))
+                .to_request(),
+            StatusCode::FORBIDDEN,
+            ErrorCode::Forbidden,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("{party_uri}/members/{}", bob.id()))
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::NotInParty,
+        )
+        .await;
+
+        let left: Party = test_utils::assert_ok_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("{party_uri}/members/{}", player.id()))
+                .to_request(),
+        )
+        .await;
+        let members: Vec<_> = left
+            .members()
+            .iter()
+            .map(|member| member.player_id)
+            .collect();
+        assert_eq!(members, [founder]);
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get().uri(&party_uri).to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::PartyNotFound,
+        )
+        .await;
+
+        // The party is disbanded once its last member leaves.
+        let mut party = Party::from_id(&database, party.id()).await.unwrap();
+        let _ = party.leave(&database, founder).await.unwrap();
+        assert!(matches!(
+            Party::from_id(&database, party.id()).await,
+            Err(PartyError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn deleted_player_leaves_party() {
+        let (database, player, party) = setup_database().await;
+        let app = actix_test::init_service(create_app(database.clone())).await;
+        let _: Party = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri(&format!("/api/parties/{}/members", party.id()))
+                .set_json(json!({ "player_id": player.id() }))
+                .to_request(),
+        )
+        .await;
+
+        let _: Player = test_utils::assert_ok_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("/api/players/{}", player.id()))
+                .to_request(),
+        )
+        .await;
+        let party = Party::from_id(&database, party.id()).await.unwrap();
+        assert_eq!(party.members().len(), 1);
+    }
+}
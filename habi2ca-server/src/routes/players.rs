@@ -1,16 +1,33 @@
 use std::collections::HashMap;
 
-use actix_web::{get, patch, post, web, HttpRequest, Responder, Scope};
-use anyhow::Context;
-use habi2ca_database::player::PlayerId;
-
-use crate::{logic::player::Player, routes::RouteError, state::State};
+use actix_web::{
+    delete, get,
+    http::header,
+    patch, post, route,
+    web::{self, Json},
+    HttpRequest, HttpResponse, Responder, Scope,
+};
+use chrono::{DateTime, Utc};
+use habi2ca_database::{account::AccountId, player::PlayerId};
+use sea_orm::TransactionTrait;
+use serde::Deserialize;
+
+use crate::{
+    events,
+    logic::{
+        player::{Player, PlayerError, PlayerUpdate},
+        xp_event::XpSource,
+    },
+    routes::{id_parameter, RouteError},
+    state::State,
+};
 
 #[get("")]
-pub async fn get_players(state: web::Data<State>) -> Result<impl Responder, RouteError> {
-    let players = Player::all(state.database())
-        .await
-        .context("Failed to get players from database.")?;
+pub async fn get_players(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+) -> Result<impl Responder, RouteError> {
+    let players = Player::account_players(state.database(), *account_id).await?;
 
     Ok(web::Json(players))
 }
@@ -18,10 +35,13 @@ pub async fn get_players(state: web::Data<State>) -> Result<impl Responder, Rout
 #[post("")]
 pub async fn create_player(
     state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
     query: web::Query<HashMap<String, String>>,
 ) -> Result<impl Responder, RouteError> {
-    let player_name = query.get("name").context("Missing 'name' parameter")?;
-    let player = Player::create(state.database(), player_name).await?;
+    let player_name = query
+        .get("name")
+        .ok_or(RouteError::MissingParameter("name"))?;
+    let player = Player::create(state.database(), *account_id, player_name).await?;
 
     Ok(web::Json(player))
 }
@@ -31,13 +51,8 @@ pub async fn get_player(
     state: web::Data<State>,
     request: HttpRequest,
 ) -> Result<impl Responder, RouteError> {
-    let player_id: PlayerId = request
-        .match_info()
-        .load()
-        .context("Missing 'id' parameter")?;
-    let player = Player::from_id(state.database(), player_id)
-        .await
-        .with_context(|| format!("Failed to get player with id '{player_id}'."))?;
+    let player_id: PlayerId = id_parameter(&request)?;
+    let player = Player::from_id(state.database(), player_id).await?;
     Ok(web::Json(player))
 }
 
@@ -47,41 +62,136 @@ pub async fn add_xp(
     request: HttpRequest,
     query: web::Query<HashMap<String, f64>>,
 ) -> Result<impl Responder, RouteError> {
-    let player_id: PlayerId = request
-        .match_info()
-        .load()
-        .context("Missing 'id' parameter")?;
-    let &xp_delta = query.get("xp").context("Missing 'xp' parameter")?;
+    let player_id: PlayerId = id_parameter(&request)?;
+    let &xp_delta = query.get("xp").ok_or(RouteError::MissingParameter("xp"))?;
 
-    let mut player = Player::from_id(state.database(), player_id).await?;
+    // The player is read inside the transaction, so concurrent changes to their xp are not lost.
+    let txn = state.database().begin().await.map_err(PlayerError::from)?;
+    let mut player = Player::from_id(&txn, player_id).await?;
+    let outbox = player.add_xp(&txn, xp_delta, XpSource::Manual).await?;
+    txn.commit().await.map_err(PlayerError::from)?;
+    outbox.publish(state.events());
 
-    player
-        .add_xp(state.database(), xp_delta)
-        .await
-        .with_context(|| format!("Failed to add xp to player '{player_id}'."))?;
+    Ok(web::Json(player))
+}
 
+/// Time range of an xp history query. `from` is inclusive and `to` is exclusive.
+#[derive(Debug, Deserialize)]
+pub struct XpHistoryQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+#[get("/{id}/xp-history")]
+pub async fn get_xp_history(
+    state: web::Data<State>,
+    request: HttpRequest,
+    query: web::Query<XpHistoryQuery>,
+) -> Result<impl Responder, RouteError> {
+    let player_id: PlayerId = id_parameter(&request)?;
+    let XpHistoryQuery { from, to } = query.into_inner();
+
+    let player = Player::from_id(state.database(), player_id).await?;
+    let history = player.xp_history(state.database(), from, to).await?;
+
+    Ok(web::Json(history))
+}
+
+#[route("/{id}", method = "PUT", method = "PATCH")]
+pub async fn update_player(
+    state: web::Data<State>,
+    request: HttpRequest,
+    update: Json<PlayerUpdate>,
+) -> Result<impl Responder, RouteError> {
+    let player_id: PlayerId = id_parameter(&request)?;
+    let mut player = Player::from_id(state.database(), player_id).await?;
+    player.update(state.database(), update.into_inner()).await?;
+    Ok(web::Json(player))
+}
+
+#[delete("/{id}")]
+pub async fn delete_player(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let player_id: PlayerId = id_parameter(&request)?;
+    let player = Player::from_id(state.database(), player_id).await?;
+    let (player, outbox) = player.delete(state.database()).await?;
+    outbox.publish(state.events());
     Ok(web::Json(player))
 }
 
+/// Streams the events of the player as Server-Sent Events. Clients reconnecting with a
+/// `Last-Event-ID` header first get the events they missed, or a `resync` event if those are no
+/// longer known and the player should be fetched again.
+#[get("/{id}/events")]
+pub async fn get_events(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let player_id: PlayerId = id_parameter(&request)?;
+    Player::from_id(state.database(), player_id).await?;
+    let last_event_id = request
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+
+    let subscription = state.events().subscribe(last_event_id);
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Keeps nginx from buffering the stream.
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(events::player_stream(
+            player_id,
+            subscription,
+            state.shutdown().clone(),
+        )))
+}
+
 pub fn add_routes(scope: Scope) -> Scope {
     scope
         .service(get_players)
         .service(get_player)
         .service(create_player)
+        .service(update_player)
+        .service(delete_player)
         .service(add_xp)
+        .service(get_xp_history)
+        .service(get_events)
 }
 
 #[cfg(test)]
 mod tests {
-    use actix_web::test::{self as actix_test, TestRequest};
+    use std::{pin::Pin, time::Duration};
+
+    use actix_web::{
+        body::{BoxBody, MessageBody},
+        http::{header, StatusCode},
+        test::{self as actix_test, TestRequest},
+        web,
+    };
+    use chrono::{SecondsFormat, Utc};
+    use futures_util::future;
     use habi2ca_database::{
-        level::LevelId,
+        level::{self, LevelId},
         player::{self, PlayerId},
+        xp_event::XpSourceKind,
     };
+    use tokio::time;
 
     use crate::{
-        logic::{level::Level, player::Player},
-        start::create_app,
+        logic::{
+            habit::{Habit, HabitData},
+            level::{Level, MAX_LEVEL},
+            player::{Player, PlayerError, PlayerUpdate, MAX_XP_DELTA},
+            task::{Task, TaskData},
+            xp_event::{XpEvent, XpSource},
+        },
+        routes::ErrorCode,
+        start::{create_app, create_app_with_state},
+        state::State,
         test_utils,
     };
 
@@ -149,7 +259,9 @@ mod tests {
     #[tokio::test]
     async fn get_player() {
         let database = test_utils::setup_database().await;
-        let _player = Player::create(&database, "Alice").await.unwrap();
+        let _player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
 
         let app = actix_test::init_service(create_app(database)).await;
 
@@ -173,8 +285,13 @@ mod tests {
             .unwrap()
             .xp_requirement();
 
-        let mut player = Player::create(&database, "Alice").await.unwrap();
-        player.add_xp(&database, level_1_xp - 5.).await.unwrap();
+        let mut player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let _ = player
+            .add_xp(&database, level_1_xp - 5., XpSource::Manual)
+            .await
+            .unwrap();
 
         let app = actix_test::init_service(create_app(database)).await;
 
@@ -210,4 +327,579 @@ mod tests {
         assert_eq!(player.xp(), 5.);
         assert_eq!(player.level(), LevelId(2));
     }
+
+    #[tokio::test]
+    async fn grind_to_level_100() {
+        let database = test_utils::setup_database().await;
+        let mut player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+
+        while player.level().0 < 100 {
+            let missing_xp = player.xp_requirement() - player.xp();
+            let _ = player
+                .add_xp(&database, missing_xp, XpSource::Manual)
+                .await
+                .unwrap();
+        }
+        assert_eq!(player.xp(), 0.0);
+
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let player: Player = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get().uri("/api/players/1").to_request(),
+        )
+        .await;
+        assert_eq!(player.level(), LevelId(100));
+
+        let levels: Vec<level::Model> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get().uri("/api/levels").to_request(),
+        )
+        .await;
+        assert!(levels.len() >= 100);
+        // The seeded curve is 20n^2 + 20, which extrapolation continues exactly.
+        assert_eq!(levels[6].xp_requirement, 1000.0);
+        assert!(levels
+            .windows(2)
+            .all(|pair| pair[0].xp_requirement < pair[1].xp_requirement));
+
+        // Losing all xp takes the player back to the first level.
+        let total_xp: f64 = levels[..99].iter().map(|level| level.xp_requirement).sum();
+        let player: Player = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/players/1/add_xp?xp=-{total_xp}"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(player.level(), LevelId(1));
+        assert_eq!(player.xp(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn get_missing_player() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let problem = test_utils::assert_error_response(
+            &app,
+            TestRequest::get().uri("/api/players/1").to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::PlayerNotFound,
+        )
+        .await;
+        assert_eq!(problem.detail, "No player with id 1 exists.");
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get().uri("/api/players/alice").to_request(),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidParameter,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn create_player_without_name() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post().uri("/api/players").to_request(),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::MissingParameter,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn add_invalid_xp() {
+        let database = test_utils::setup_database().await;
+        let _player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let app = actix_test::init_service(create_app(database)).await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri("/api/players/1/add_xp?xp=-1.0")
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidXp,
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri("/api/players/1/add_xp?xp=many")
+                .to_request(),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidParameter,
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri("/api/players/1/add_xp?xp=1e300")
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidXp,
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri("/api/players/1/add_xp?xp=NaN")
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidXp,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn add_huge_xp() {
+        let database = test_utils::setup_database().await;
+        let mut player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            player.add_xp(&database, 1e300, XpSource::Manual).await,
+            Err(PlayerError::InvalidXp(_))
+        ));
+        assert_eq!(player.level(), LevelId(1));
+
+        // Players stop leveling up at the highest level and keep the xp they earn there.
+        for _ in 0..10 {
+            let _ = player
+                .add_xp(&database, MAX_XP_DELTA, XpSource::Manual)
+                .await
+                .unwrap();
+        }
+        assert_eq!(player.level(), MAX_LEVEL);
+        assert!(player.xp() > player.xp_requirement());
+        let levels = Level::all_levels(&database).await.unwrap();
+        assert_eq!(levels.len(), MAX_LEVEL.0 as usize);
+
+        let player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(player.level(), MAX_LEVEL);
+    }
+
+    #[tokio::test]
+    async fn add_xp_concurrently() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        let requests = (0..5).map(|_| {
+            test_utils::assert_ok_response::<_, _, _, Player>(
+                &app,
+                TestRequest::patch()
+                    .uri("/api/players/1/add_xp?xp=1.0")
+                    .to_request(),
+            )
+        });
+        future::join_all(requests).await;
+
+        let player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(player.xp(), 5.0);
+        let history = player.xp_history(&database, None, None).await.unwrap();
+        let ledger_xp: f64 = history.iter().map(XpEvent::amount).sum();
+        assert_eq!(ledger_xp, player.xp());
+    }
+
+    #[tokio::test]
+    async fn update_player() {
+        let database = test_utils::setup_database().await;
+        let _player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let player: Player = test_utils::assert_ok_response(
+            &app,
+            TestRequest::put()
+                .uri("/api/players/1")
+                .set_json(PlayerUpdate {
+                    name: Some("Alicia".to_string()),
+                })
+                .to_request(),
+        )
+        .await;
+        assert_eq!(player.id(), PlayerId(1));
+        assert_eq!(player.name(), "Alicia");
+
+        let player: Player = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get().uri("/api/players/1").to_request(),
+        )
+        .await;
+        assert_eq!(player.name(), "Alicia");
+        assert_eq!(player.xp(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn delete_player() {
+        let database = test_utils::setup_database().await;
+        let alice = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let bob = Player::create(&database, test_utils::TEST_ACCOUNT, "Bob")
+            .await
+            .unwrap();
+
+        for player in [&alice, &bob] {
+            Task::create(
+                &database,
+                TaskData {
+                    player_id: player.id(),
+                    name: "Task".to_string(),
+                    description: "Description".to_string(),
+                    completed: false,
+                },
+            )
+            .await
+            .unwrap();
+            Habit::create(
+                &database,
+                HabitData {
+                    player_id: player.id(),
+                    name: "Habit".to_string(),
+                    description: "Description".to_string(),
+                    positive: true,
+                    negative: false,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let app = actix_test::init_service(create_app(database.clone())).await;
+
+        let player: Player = test_utils::assert_ok_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("/api/players/{}", alice.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(player.id(), alice.id());
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/players/{}", alice.id()))
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::PlayerNotFound,
+        )
+        .await;
+
+        let tasks = Task::account_tasks(&database, test_utils::TEST_ACCOUNT)
+            .await
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].player_id(), bob.id());
+
+        let habits = Habit::account_habits(&database, test_utils::TEST_ACCOUNT)
+            .await
+            .unwrap();
+        assert_eq!(habits.len(), 1);
+        assert_eq!(habits[0].player(), bob.id());
+    }
+
+    #[tokio::test]
+    async fn xp_history() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task".to_string(),
+                description: "Description".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+        let habit = Habit::create(
+            &database,
+            HabitData {
+                player_id: player.id(),
+                name: "Habit".to_string(),
+                description: "Description".to_string(),
+                positive: true,
+                negative: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let before = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+        let _: Task = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}/complete", task.id()))
+                .to_request(),
+        )
+        .await;
+        let _: Habit = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/habits/{}/increment", habit.id()))
+                .to_request(),
+        )
+        .await;
+        let _: Task = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}/uncomplete", task.id()))
+                .to_request(),
+        )
+        .await;
+        let after = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+
+        let history: Vec<XpEvent> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players/1/xp-history")
+                .to_request(),
+        )
+        .await;
+        let history: Vec<_> = history
+            .iter()
+            .map(|event| (event.source(), event.amount()))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                ((XpSourceKind::Task, Some(task.id().0)), 1.0),
+                ((XpSourceKind::Habit, Some(habit.id().0)), 1.0),
+                ((XpSourceKind::Task, Some(task.id().0)), -1.0),
+            ]
+        );
+
+        let history: Vec<XpEvent> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!(
+                    "/api/players/1/xp-history?from={before}&to={after}"
+                ))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(history.len(), 3);
+
+        let history: Vec<XpEvent> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/players/1/xp-history?from={after}"))
+                .to_request(),
+        )
+        .await;
+        assert!(history.is_empty());
+
+        let history: Vec<XpEvent> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/players/1/xp-history?to={before}"))
+                .to_request(),
+        )
+        .await;
+        assert!(history.is_empty());
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players/2/xp-history")
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::PlayerNotFound,
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players/1/xp-history?from=yesterday")
+                .to_request(),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidParameter,
+        )
+        .await;
+    }
+
+    /// An event read from an event stream.
+    struct StreamedEvent {
+        id: Option<u64>,
+        name: String,
+        data: serde_json::Value,
+    }
+
+    /// Reads the next event from an event stream, skipping keep-alive comments.
+    async fn next_event(body: &mut BoxBody) -> StreamedEvent {
+        loop {
+            let chunk = time::timeout(
+                Duration::from_secs(5),
+                future::poll_fn(|cx| Pin::new(&mut *body).poll_next(cx)),
+            )
+            .await
+            .expect("No event was streamed.")
+            .expect("The stream ended.")
+            .unwrap();
+            let mut event = StreamedEvent {
+                id: None,
+                name: String::new(),
+                data: serde_json::Value::Null,
+            };
+            for line in std::str::from_utf8(&chunk).unwrap().lines() {
+                match line.split_once(": ") {
+                    Some(("id", id)) => event.id = Some(id.parse().unwrap()),
+                    Some(("event", name)) => event.name = name.to_owned(),
+                    Some(("data", data)) => event.data = serde_json::from_str(data).unwrap(),
+                    _ => {}
+                }
+            }
+            if !event.name.is_empty() {
+                return event;
+            }
+        }
+    }
+
+    /// Reads the next events of a stream and checks that they are `names`, in order.
+    async fn expect_events(body: &mut BoxBody, names: &[&str]) -> Vec<StreamedEvent> {
+        let mut events = Vec::new();
+        for name in names {
+            let event = next_event(body).await;
+            assert_eq!(event.name, *name);
+            events.push(event);
+        }
+        events
+    }
+
+    #[actix_web::test]
+    async fn player_events() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let app = actix_test::init_service(create_app(database.clone())).await;
+        let subscribe = |last_event_id: Option<u64>| {
+            let mut request =
+                TestRequest::get().uri(&format!("/api/players/{}/events", player.id()));
+            if let Some(last_event_id) = last_event_id {
+                request = request.insert_header(("Last-Event-ID", last_event_id.to_string()));
+            }
+            let app = &app;
+            async move {
+                let response =
+                    actix_test::call_service(app, test_utils::authenticate(request.to_request()))
+                        .await;
+                assert_eq!(response.status(), StatusCode::OK);
+                assert_eq!(
+                    response.headers().get(header::CONTENT_TYPE).unwrap(),
+                    "text/event-stream"
+                );
+                response.into_body().boxed()
+            }
+        };
+
+        let mut stream = subscribe(None).await;
+        let task: Task = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/tasks")
+                .set_json(TaskData {
+                    player_id: player.id(),
+                    name: "Task".to_string(),
+                    description: "Description".to_string(),
+                    completed: false,
+                })
+                .to_request(),
+        )
+        .await;
+        let _: Task = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}/complete", task.id()))
+                .to_request(),
+        )
+        .await;
+
+        let events = expect_events(
+            &mut stream,
+            &["task_created", "xp_gained", "task_completed"],
+        )
+        .await;
+        assert_eq!(events[0].data["type"], "task_created");
+        assert_eq!(events[1].data["player"]["id"], player.id().0);
+        assert_eq!(events[1].data["xp_event"]["amount"], 1.0);
+        assert_eq!(events[1].data["xp_event"]["source_id"], task.id().0);
+        assert_eq!(events[2].data["task"]["completed"], true);
+
+        // Reconnecting clients get the events they missed.
+        let mut resumed = subscribe(events[0].id).await;
+        let resumed = expect_events(&mut resumed, &["xp_gained", "task_completed"]).await;
+        assert_eq!(resumed[0].id, events[1].id);
+
+        // Clients whose events are no longer known are told to fetch the player again.
+        let mut outdated = subscribe(Some(0)).await;
+        let event = next_event(&mut outdated).await;
+        assert_eq!(event.name, "resync");
+        assert!(event.id.is_some());
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get().uri("/api/players/2/events").to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::PlayerNotFound,
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn player_events_end_on_shutdown() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let state = web::Data::new(State::new(database, None));
+        let app = actix_test::init_service(create_app_with_state(state.clone())).await;
+        let response = actix_test::call_service(
+            &app,
+            test_utils::authenticate(
+                TestRequest::get()
+                    .uri(&format!("/api/players/{}/events", player.id()))
+                    .to_request(),
+            ),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().boxed();
+
+        state.shutdown().cancel();
+        let chunk = time::timeout(
+            Duration::from_secs(5),
+            future::poll_fn(|cx| Pin::new(&mut body).poll_next(cx)),
+        )
+        .await
+        .expect("The stream did not end.");
+        assert!(chunk.is_none());
+    }
 }
@@ -1,46 +1,56 @@
 use std::collections::HashMap;
 
 use actix_web::{
-    get, patch, post,
+    delete, get, patch, post, route,
     web::{self, Json},
     HttpRequest, Responder, Scope,
 };
-use anyhow::Context;
-use habi2ca_database::{player::PlayerId, task::TaskId};
+use habi2ca_database::{account::AccountId, player::PlayerId, task::TaskId};
 
 use crate::{
-    logic::task::{Task, TaskData},
-    routes::RouteError,
+    events::PlayerEvent,
+    logic::task::{Task, TaskData, TaskUpdate},
+    routes::{authorize_player, id_parameter, RouteError},
     state::State,
 };
 
 #[post("")]
 pub async fn create_task(
     state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
     task: Json<TaskData>,
 ) -> Result<impl Responder, RouteError> {
+    authorize_player(state.database(), *account_id, task.player_id).await?;
     let task = Task::create(state.database(), task.into_inner()).await?;
+    state.events().publish(
+        task.player_id(),
+        PlayerEvent::TaskCreated { task: task.clone() },
+    );
     Ok(web::Json(task))
 }
 
 #[get("")]
 pub async fn get_tasks(
     state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
     query: web::Query<HashMap<String, String>>,
 ) -> Result<impl Responder, RouteError> {
     let player_id = query
         .get("player")
         .map(|s| {
             s.parse()
-                .with_context(|| format!("Failed to parse player id '{s}'."))
                 .map(PlayerId)
+                .map_err(|error| RouteError::InvalidParameter {
+                    name: "player",
+                    reason: format!("Failed to parse player id '{s}': {error}"),
+                })
         })
         .transpose()?;
 
     let result = if let Some(player_id) = player_id {
         Task::player_tasks(state.database(), player_id).await?
     } else {
-        Task::all_tasks(state.database()).await?
+        Task::account_tasks(state.database(), *account_id).await?
     };
     Ok(web::Json(result))
 }
@@ -50,10 +60,7 @@ pub async fn get_task(
     state: web::Data<State>,
     request: HttpRequest,
 ) -> Result<impl Responder, RouteError> {
-    let task_id: TaskId = request
-        .match_info()
-        .load()
-        .context("Missing 'id' parameter")?;
+    let task_id: TaskId = id_parameter(&request)?;
 
     let task = Task::from_id(state.database(), task_id).await?;
     Ok(web::Json(task))
@@ -64,14 +71,55 @@ pub async fn complete_task(
     state: web::Data<State>,
     request: HttpRequest,
 ) -> Result<impl Responder, RouteError> {
-    let task_id: TaskId = request
-        .match_info()
-        .load()
-        .context("Missing 'id' parameter")?;
+    let task_id: TaskId = id_parameter(&request)?;
 
     let mut task = Task::from_id(state.database(), task_id).await?;
 
-    task.complete_task(state.database()).await?;
+    task.complete_task(state.database())
+        .await?
+        .publish(state.events());
+    Ok(web::Json(task))
+}
+
+#[route("/{id}", method = "PUT", method = "PATCH")]
+pub async fn update_task(
+    state: web::Data<State>,
+    request: HttpRequest,
+    update: Json<TaskUpdate>,
+) -> Result<impl Responder, RouteError> {
+    let task_id: TaskId = id_parameter(&request)?;
+
+    let mut task = Task::from_id(state.database(), task_id).await?;
+
+    task.update(state.database(), update.into_inner()).await?;
+    Ok(web::Json(task))
+}
+
+#[delete("/{id}")]
+pub async fn delete_task(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let task_id: TaskId = id_parameter(&request)?;
+
+    let task = Task::from_id(state.database(), task_id).await?;
+
+    let task = task.delete(state.database()).await?;
+    Ok(web::Json(task))
+}
+
+#[patch("/{id}/uncomplete")]
+pub async fn uncomplete_task(
+    state: web::Data<State>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let task_id: TaskId = id_parameter(&request)?;
+
+    let mut task = Task::from_id(state.database(), task_id).await?;
+
+    task.uncomplete_task(state.database())
+        .await?
+        .publish(state.events());
     Ok(web::Json(task))
 }
 
@@ -80,18 +128,33 @@ pub fn add_routes(scope: Scope) -> Scope {
         .service(create_task)
         .service(get_tasks)
         .service(get_task)
+        .service(update_task)
+        .service(delete_task)
         .service(complete_task)
+        .service(uncomplete_task)
 }
 
 #[cfg(test)]
 mod tests {
-    use actix_web::test::{self as actix_test, TestRequest};
-    use habi2ca_database::{level::LevelId, player, task};
+    use actix_web::{
+        http::StatusCode,
+        test::{self as actix_test, TestRequest},
+    };
+    use habi2ca_database::{
+        level::LevelId,
+        player::{self, PlayerId},
+        task,
+    };
     use sea_orm::DatabaseConnection;
 
     use crate::{
-        logic::{level::Level, player::Player, task::Task},
-        routes::tasks::TaskData,
+        logic::{
+            level::Level,
+            player::Player,
+            task::{Task, TaskUpdate},
+            xp_event::XpSource,
+        },
+        routes::{tasks::TaskData, ErrorCode},
         start::create_app,
         test_utils,
     };
@@ -99,7 +162,9 @@ mod tests {
     async fn setup_database() -> (DatabaseConnection, Player) {
         let database = test_utils::setup_database().await;
 
-        let player = Player::create(&database, "Alice").await.unwrap();
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
 
         (database, player)
     }
@@ -179,7 +244,9 @@ mod tests {
     async fn get_player_tasks() {
         let (database, player1) = setup_database().await;
 
-        let player2 = Player::create(&database, "Bob").await.unwrap();
+        let player2 = Player::create(&database, test_utils::TEST_ACCOUNT, "Bob")
+            .await
+            .unwrap();
 
         Task::create(
             &database,
@@ -261,7 +328,10 @@ mod tests {
             .unwrap()
             .xp_requirement();
 
-        player.add_xp(&database, level_1_xp - 0.5).await.unwrap();
+        let _ = player
+            .add_xp(&database, level_1_xp - 0.5, XpSource::Manual)
+            .await
+            .unwrap();
 
         let task = Task::create(
             &database,
@@ -314,4 +384,368 @@ mod tests {
         assert_eq!(response_player.level_id, LevelId(2));
         assert_eq!(response_player.xp, 0.5);
     }
+
+    #[tokio::test]
+    async fn create_task_errors() {
+        let (database, _player) = setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/tasks")
+                .set_json(TaskData {
+                    player_id: PlayerId(2),
+                    name: "Task1".to_string(),
+                    description: "Description1".to_string(),
+                    completed: false,
+                })
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::UnknownPlayer,
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/tasks")
+                .set_json(serde_json::json!({ "player_id": 1, "name": "Task1" }))
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidBody,
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/tasks")
+                .insert_header(("content-type", "application/json"))
+                .set_payload("{")
+                .to_request(),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::MalformedBody,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn complete_missing_task() {
+        let (database, _player) = setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::patch()
+                .uri("/api/tasks/1/complete")
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::TaskNotFound,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn update_task() {
+        let (database, player) = setup_database().await;
+
+        let task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task1".to_string(),
+                description: "Description1".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let response_task: task::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}", task.id()))
+                .set_json(TaskUpdate {
+                    name: Some("Renamed".to_string()),
+                    description: None,
+                })
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(response_task.id, task.id());
+        assert_eq!(response_task.name, "Renamed");
+        assert_eq!(response_task.description, "Description1");
+
+        let response_task: task::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::put()
+                .uri(&format!("/api/tasks/{}", task.id()))
+                .set_json(TaskUpdate {
+                    name: Some("Task2".to_string()),
+                    description: Some("Description2".to_string()),
+                })
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(response_task.name, "Task2");
+        assert_eq!(response_task.description, "Description2");
+
+        let response_task: task::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/tasks/{}", task.id()))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(response_task.name, "Task2");
+        assert_eq!(response_task.description, "Description2");
+        assert_eq!(response_task.completed, false);
+    }
+
+    #[tokio::test]
+    async fn delete_task() {
+        let (database, player) = setup_database().await;
+
+        let task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task1".to_string(),
+                description: "Description1".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let response_task: task::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("/api/tasks/{}", task.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response_task.id, task.id());
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/tasks/{}", task.id()))
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::TaskNotFound,
+        )
+        .await;
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("/api/tasks/{}", task.id()))
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::TaskNotFound,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn uncomplete_task() {
+        let (database, mut player) = setup_database().await;
+
+        let level_1_xp = Level::from_id(&database, LevelId(1))
+            .await
+            .unwrap()
+            .xp_requirement();
+
+        let _ = player
+            .add_xp(&database, level_1_xp - 0.5, XpSource::Manual)
+            .await
+            .unwrap();
+
+        let mut task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task1".to_string(),
+                description: "Description1".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+        let _ = task.complete_task(&database).await.unwrap();
+
+        let player = Player::from_id(&database, player.id()).await.unwrap();
+        assert_eq!(player.level(), LevelId(2));
+
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let response_task: task::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}/uncomplete", task.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response_task.completed, false);
+
+        let response_player: player::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/players/{}", player.id()))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(response_player.level_id, LevelId(1));
+        assert_eq!(response_player.xp, level_1_xp - 0.5);
+
+        // Uncompleting again must not remove xp twice.
+        let _: task::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}/uncomplete", task.id()))
+                .to_request(),
+        )
+        .await;
+
+        let response_player: player::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/players/{}", player.id()))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(response_player.level_id, LevelId(1));
+        assert_eq!(response_player.xp, level_1_xp - 0.5);
+    }
+
+    #[tokio::test]
+    async fn uncomplete_task_created_completed() {
+        let (database, mut player) = setup_database().await;
+        let _ = player
+            .add_xp(&database, 0.5, XpSource::Manual)
+            .await
+            .unwrap();
+        let task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task1".to_string(),
+                description: "Description1".to_string(),
+                completed: true,
+            },
+        )
+        .await
+        .unwrap();
+        let app = actix_test::init_service(create_app(database)).await;
+
+        // The task never granted xp, so none is taken back.
+        let response_task: task::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}/uncomplete", task.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response_task.completed, false);
+        let response_player: player::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/players/{}", player.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response_player.xp, 0.5);
+    }
+
+    #[tokio::test]
+    async fn uncomplete_task_without_xp() {
+        let (database, player) = setup_database().await;
+        let mut task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task1".to_string(),
+                description: "Description1".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+        let _ = task.complete_task(&database).await.unwrap();
+        let mut player = Player::from_id(&database, player.id()).await.unwrap();
+        let _ = player
+            .add_xp(&database, -player.xp(), XpSource::Manual)
+            .await
+            .unwrap();
+        let app = actix_test::init_service(create_app(database)).await;
+
+        // The player already lost the xp, so uncompleting takes back nothing.
+        let response_task: task::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}/uncomplete", task.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response_task.completed, false);
+        let response_player: player::Model = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/players/{}", player.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response_player.level_id, LevelId(1));
+        assert_eq!(response_player.xp, 0.0);
+    }
+
+    #[tokio::test]
+    async fn concurrent_completion() {
+        let (database, player) = setup_database().await;
+        let task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task1".to_string(),
+                description: "Description1".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        // Both requests load the task before either completes it.
+        let mut first = Task::from_id(&database, task.id()).await.unwrap();
+        let mut second = Task::from_id(&database, task.id()).await.unwrap();
+        let (first_result, second_result) = tokio::join!(
+            first.complete_task(&database),
+            second.complete_task(&database)
+        );
+        let _ = first_result.unwrap();
+        let _ = second_result.unwrap();
+        let xp = Player::from_id(&database, player.id()).await.unwrap().xp();
+        assert_eq!(xp, 1.0);
+
+        let mut first = Task::from_id(&database, task.id()).await.unwrap();
+        let mut second = Task::from_id(&database, task.id()).await.unwrap();
+        let (first_result, second_result) = tokio::join!(
+            first.uncomplete_task(&database),
+            second.uncomplete_task(&database)
+        );
+        let _ = first_result.unwrap();
+        let _ = second_result.unwrap();
+        let xp = Player::from_id(&database, player.id()).await.unwrap().xp();
+        assert_eq!(xp, 0.0);
+    }
 }
@@ -0,0 +1,216 @@
+use actix_web::{
+    delete, get, post,
+    web::{self, Json},
+    HttpRequest, Responder, Scope,
+};
+use habi2ca_database::{account::AccountId, api_token::ApiTokenId};
+
+use crate::{
+    logic::api_token::{ApiToken, ApiTokenData},
+    routes::{id_parameter, RouteError},
+    state::State,
+};
+
+#[post("")]
+pub async fn create_token(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    data: Json<ApiTokenData>,
+) -> Result<impl Responder, RouteError> {
+    let token = ApiToken::create(state.database(), *account_id, data.into_inner()).await?;
+    Ok(web::Json(token))
+}
+
+#[get("")]
+pub async fn get_tokens(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+) -> Result<impl Responder, RouteError> {
+    let tokens = ApiToken::account_tokens(state.database(), *account_id).await?;
+    Ok(web::Json(tokens))
+}
+
+#[delete("/{id}")]
+pub async fn revoke_token(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    request: HttpRequest,
+) -> Result<impl Responder, RouteError> {
+    let token_id: ApiTokenId = id_parameter(&request)?;
+    let token = ApiToken::from_id(state.database(), *account_id, token_id).await?;
+    let token = token.revoke(state.database()).await?;
+    Ok(web::Json(token))
+}
+
+pub fn add_routes(scope: Scope) -> Scope {
+    scope
+        .service(create_token)
+        .service(get_tokens)
+        .service(revoke_token)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::{header, StatusCode},
+        test::{self as actix_test, TestRequest},
+    };
+    use habi2ca_database::api_token::TokenScope;
+
+    use crate::{
+        logic::{
+            api_token::{ApiToken, ApiTokenData, CreatedApiToken},
+            player::Player,
+            task::Task,
+        },
+        routes::ErrorCode,
+        start::create_app,
+        test_utils,
+    };
+
+    fn bearer(token: &str) -> (header::HeaderName, String) {
+        (header::AUTHORIZATION, format!("Bearer {token}"))
+    }
+
+    #[tokio::test]
+    async fn create_and_revoke_token() {
+        let database = test_utils::setup_database().await;
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let created: CreatedApiToken = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/tokens")
+                .set_json(ApiTokenData {
+                    name: "cron".to_string(),
+                    scope: TokenScope::Full,
+                })
+                .to_request(),
+        )
+        .await;
+        assert!(created.token.starts_with("h2c_"));
+        assert_eq!(created.api_token.last_used_at(), None);
+
+        let players: Vec<Player> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players")
+                .insert_header(bearer(&created.token))
+                .to_request(),
+        )
+        .await;
+        assert!(players.is_empty());
+
+        let tokens: Vec<ApiToken> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get().uri("/api/tokens").to_request(),
+        )
+        .await;
+        assert_eq!(tokens.len(), 1);
+        assert!(tokens[0].last_used_at().is_some());
+
+        let revoked: ApiToken = test_utils::assert_ok_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("/api/tokens/{}", created.api_token.id()))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(revoked.id(), created.api_token.id());
+
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get()
+                .uri("/api/players")
+                .insert_header(bearer(&created.token))
+                .to_request(),
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::Unauthenticated,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!("/api/tokens/{}", created.api_token.id()))
+                .to_request(),
+            StatusCode::NOT_FOUND,
+            ErrorCode::ApiTokenNotFound,
+        )
+        .await;
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::post()
+                .uri("/api/tokens")
+                .set_json(ApiTokenData {
+                    name: " ".to_string(),
+                    scope: TokenScope::Full,
+                })
+                .to_request(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidTokenName,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn token_scopes() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let token = |scope| {
+            let database = database.clone();
+            async move {
+                let data = ApiTokenData {
+                    name: format!("{scope:?}"),
+                    scope,
+                };
+                ApiToken::create(&database, test_utils::TEST_ACCOUNT, data)
+                    .await
+                    .unwrap()
+                    .token
+            }
+        };
+        let read_only = token(TokenScope::ReadOnly).await;
+        let tasks_only = token(TokenScope::Tasks).await;
+        let habits_only = token(TokenScope::Habits).await;
+
+        let app = actix_test::init_service(create_app(database.clone())).await;
+        let player_uri = format!("/api/players/{}", player.id());
+
+        let _: Player = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&player_uri)
+                .insert_header(bearer(&read_only))
+                .to_request(),
+        )
+        .await;
+        let _: Vec<Task> = test_utils::assert_ok_response(
+            &app,
+            TestRequest::get()
+                .uri(&format!("/api/tasks?player={}", player.id()))
+                .insert_header(bearer(&tasks_only))
+                .to_request(),
+        )
+        .await;
+
+        for (request, token) in [
+            (
+                TestRequest::patch().uri(&format!("{player_uri}/add_xp?xp=1")),
+                &read_only,
+            ),
+            (TestRequest::get().uri(&player_uri), &tasks_only),
+            (TestRequest::get().uri("/api/tasks"), &habits_only),
+            (TestRequest::get().uri("/api/tokens"), &habits_only),
+        ] {
+            test_utils::assert_error_response(
+                &app,
+                request.insert_header(bearer(token)).to_request(),
+                StatusCode::FORBIDDEN,
+                ErrorCode::InsufficientScope,
+            )
+            .await;
+        }
+    }
+}
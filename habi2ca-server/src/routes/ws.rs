@@ -0,0 +1,932 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    future::Future,
+    str::FromStr,
+    time::Duration,
+};
+
+use actix_web::{get, http::Method, rt, web, HttpRequest, HttpResponse, Scope};
+use actix_ws::{
+    AggregatedMessage, AggregatedMessageStream, CloseCode, CloseReason, Closed, ProtocolError,
+    Session,
+};
+use habi2ca_database::{
+    account::AccountId, api_token::TokenScope, habit::HabitId, party::PartyId, player::PlayerId,
+    task::TaskId,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{self, Instant, MissedTickBehavior},
+};
+use tracing::error;
+
+use crate::{
+    events::{Event, PlayerEvent},
+    logic::{
+        habit::Habit,
+        party::{Party, PartyError},
+        player::{LeaderboardEntry, Player},
+        task::Task,
+    },
+    routes::{
+        auth::scope_allows,
+        authorize_player,
+        error::{error_chain, Problem},
+        RouteError,
+    },
+    state::State,
+};
+
+/// Largest message a client may send. Commands are small, so anything bigger is a mistake.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024;
+/// Most channels a connection may be subscribed to at once.
+const MAX_SUBSCRIPTIONS: usize = 64;
+/// Time between pings sent to the client.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Time without hearing from the client, not even a pong, after which the connection is closed.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(45);
+/// Time the client may take to accept a message before the connection is dropped. Messages are
+/// not queued for clients that stop reading, so they cannot make the server run out of memory.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Number of players on the leaderboard.
+const LEADERBOARD_SIZE: u64 = 10;
+/// Least time between leaderboard updates, so a burst of xp changes is sent as one update.
+const LEADERBOARD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Something a connection can subscribe to, written as `player:{id}`, `party:{id}` or
+/// `leaderboard`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Channel {
+    /// The events of a player, as streamed at `/api/players/{id}/events`.
+    Player(PlayerId),
+    /// The events of every member of a party, including members joining and leaving.
+    Party(PartyId),
+    /// The top players across all accounts, sent again whenever xp changes.
+    Leaderboard,
+}
+
+impl FromStr for Channel {
+    type Err = RouteError;
+
+    fn from_str(channel: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| RouteError::InvalidParameter {
+            name: "channel",
+            reason,
+        };
+        match channel.split_once(':') {
+            None if channel == "leaderboard" => Ok(Channel::Leaderboard),
+            Some(("player", id)) => id
+                .parse()
+                .map(|id| Channel::Player(PlayerId(id)))
+                .map_err(|error| invalid(format!("Failed to parse player id '{id}': {error}"))),
+            Some(("party", id)) => id
+                .parse()
+                .map(|id| Channel::Party(PartyId(id)))
+                .map_err(|error| invalid(format!("Failed to parse party id '{id}': {error}"))),
+            _ => Err(invalid(format!("Unknown channel '{channel}'."))),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Player(player_id) => write!(f, "player:{player_id}"),
+            Channel::Party(party_id) => write!(f, "party:{party_id}"),
+            Channel::Leaderboard => write!(f, "leaderboard"),
+        }
+    }
+}
+
+impl Serialize for Channel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Command {
+    Subscribe { channel: String },
+    Unsubscribe { channel: String },
+    CompleteTask { task_id: TaskId },
+    IncrementHabit { habit_id: HabitId },
+}
+
+/// A message from the client. The `id` is optional and copied to the reply, so clients can tell
+/// which message a reply belongs to.
+#[derive(Debug, Deserialize)]
+struct ClientMessage {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(flatten)]
+    command: Command,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Resource {
+    Task(Task),
+    Habit(Habit),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Subscribed {
+        id: Option<u64>,
+        channel: Channel,
+    },
+    /// Also sent without an id when the connection loses access to a party, because the last
+    /// of the caller's players left it.
+    Unsubscribed {
+        id: Option<u64>,
+        channel: Channel,
+    },
+    /// The outcome of a command, e.g. the completed task.
+    Result {
+        id: Option<u64>,
+        data: Resource,
+    },
+    Event {
+        channel: Channel,
+        /// Id of the event, shared with the event stream of the player.
+        event_id: u64,
+        event: PlayerEvent,
+    },
+    Leaderboard {
+        players: Vec<LeaderboardEntry>,
+    },
+    /// Events may have been missed since the client fell behind, so the subscribed players
+    /// should be fetched again.
+    Resync,
+    Error {
+        id: Option<u64>,
+        problem: Problem,
+    },
+}
+
+impl ServerMessage {
+    fn error(id: Option<u64>, error: RouteError) -> Self {
+        let problem = error.problem();
+        if error.kind().0.is_server_error() {
+            error!("{:?}: {}", problem.code, error_chain(&error));
+        }
+        ServerMessage::Error { id, problem }
+    }
+}
+
+/// Parses a message the way JSON request bodies are parsed, so clients get the same errors.
+fn parse_message(text: &str) -> Result<ClientMessage, (Option<u64>, RouteError)> {
+    let value: serde_json::Value = serde_json::from_str(text)
+        .map_err(|error| (None, RouteError::MalformedBody(error.to_string())))?;
+    let id = value.get("id").and_then(serde_json::Value::as_u64);
+    serde_json::from_value(value).map_err(|error| (id, RouteError::InvalidBody(error.to_string())))
+}
+
+/// Gives up on sending a message if the client does not accept it in time.
+async fn deliver(sending: impl Future<Output = Result<(), Closed>>) -> Result<(), Closed> {
+    time::timeout(SEND_TIMEOUT, sending)
+        .await
+        .unwrap_or(Err(Closed))
+}
+
+/// A WebSocket connection of an account, which acts with the same permissions as the request
+/// that opened it.
+struct Connection {
+    state: web::Data<State>,
+    account_id: AccountId,
+    scope: TokenScope,
+    channels: HashSet<Channel>,
+    /// Members of the subscribed parties and the accounts owning them, kept up to date from the
+    /// events of players joining and leaving.
+    parties: HashMap<PartyId, HashMap<PlayerId, Option<AccountId>>>,
+    /// Whether xp changed since the leaderboard was last sent.
+    leaderboard_stale: bool,
+}
+
+impl Connection {
+    /// Checks that the caller's API token allows the REST request equivalent to a message.
+    fn check_scope(&self, method: Method, path: &str) -> Result<(), RouteError> {
+        if scope_allows(self.scope, &method, path) {
+            Ok(())
+        } else {
+            Err(RouteError::InsufficientScope(self.scope))
+        }
+    }
+
+    async fn leaderboard(&mut self) -> Result<ServerMessage, RouteError> {
+        let players = Player::leaderboard(self.state.database(), LEADERBOARD_SIZE).await?;
+        self.leaderboard_stale = false;
+        Ok(ServerMessage::Leaderboard { players })
+    }
+
+    async fn subscribe(
+        &mut self,
+        id: Option<u64>,
+        channel: Channel,
+    ) -> Result<Vec<ServerMessage>, RouteError> {
+        if !self.channels.contains(&channel) && self.channels.len() >= MAX_SUBSCRIPTIONS {
+            return Err(RouteError::TooManySubscriptions(MAX_SUBSCRIPTIONS));
+        }
+        let mut replies = vec![ServerMessage::Subscribed { id, channel }];
+        match channel {
+            Channel::Player(player_id) => {
+                self.check_scope(Method::GET, &format!("/api/players/{player_id}/events"))?;
+                authorize_player(self.state.database(), self.account_id, player_id).await?;
+                Player::from_id(self.state.database(), player_id).await?;
+            }
+            Channel::Party(party_id) => {
+                self.check_scope(Method::GET, &format!("/api/parties/{party_id}"))?;
+                // Parties the caller has no player in are reported as not found, like in the REST
+                // API.
+                let party = Party::from_id(self.state.database(), party_id).await?;
+                if !party.has_member_of(self.account_id) {
+                    return Err(PartyError::NotFound(party_id).into());
+                }
+                let members = party
+                    .members()
+                    .iter()
+                    .map(|member| (member.player_id, member.account_id()))
+                    .collect();
+                self.parties.insert(party_id, members);
+            }
+            Channel::Leaderboard => {
+                self.check_scope(Method::GET, "/api/players")?;
+                replies.push(self.leaderboard().await?);
+            }
+        }
+        self.channels.insert(channel);
+        Ok(replies)
+    }
+
+    async fn execute(
+        &mut self,
+        ClientMessage { id, command }: ClientMessage,
+    ) -> Result<Vec<ServerMessage>, RouteError> {
+        // Messages are handled like requests, so they must not run during maintenance either.
+        let state = self.state.clone();
+        let _guard = state.maintenance().read().await;
+        let database = state.database();
+        match command {
+            Command::Subscribe { channel } => self.subscribe(id, channel.parse()?).await,
+            Command::Unsubscribe { channel } => {
+                let channel = channel.parse()?;
+                self.channels.remove(&channel);
+                if let Channel::Party(party_id) = channel {
+                    self.parties.remove(&party_id);
+                }
+                Ok(vec![ServerMessage::Unsubscribed { id, channel }])
+            }
+            Command::CompleteTask { task_id } => {
+                self.check_scope(Method::PATCH, &format!("/api/tasks/{task_id}/complete"))?;
+                let mut task = Task::from_id(database, task_id).await?;
+                authorize_player(database, self.account_id, task.player_id()).await?;
+                task.complete_task(database)
+                    .await?
+                    .publish(self.state.events());
+                Ok(vec![ServerMessage::Result {
+                    id,
+                    data: Resource::Task(task),
+                }])
+            }
+            Command::IncrementHabit { habit_id } => {
+                self.check_scope(Method::PATCH, &format!("/api/habits/{habit_id}/increment"))?;
+                let mut habit = Habit::from_id(database, habit_id).await?;
+                authorize_player(database, self.account_id, habit.player()).await?;
+                habit
+                    .increment(database)
+                    .await?
+                    .publish(self.state.events());
+                Ok(vec![ServerMessage::Result {
+                    id,
+                    data: Resource::Habit(habit),
+                }])
+            }
+        }
+    }
+
+    async fn handle_text(&mut self, text: &str) -> Vec<ServerMessage> {
+        let message = match parse_message(text) {
+            Ok(message) => message,
+            Err((id, error)) => return vec![ServerMessage::error(id, error)],
+        };
+        let id = message.id;
+        self.execute(message)
+            .await
+            .unwrap_or_else(|error| vec![ServerMessage::error(id, error)])
+    }
+
+    /// Forwards the event to the channels it belongs to: the player's channel and the channel of
+    /// the player's party.
+    fn handle_event(&mut self, event: &Event) -> Vec<ServerMessage> {
+        if matches!(
+            event.event,
+            PlayerEvent::XpGained { .. } | PlayerEvent::LevelUp { .. } | PlayerEvent::Died { .. }
+        ) && self.channels.contains(&Channel::Leaderboard)
+        {
+            self.leaderboard_stale = true;
+        }
+        let message = |channel| ServerMessage::Event {
+            channel,
+            event_id: event.id,
+            event: event.event.clone(),
+        };
+        let mut messages = Vec::new();
+        let player_channel = Channel::Player(event.player_id);
+        if self.channels.contains(&player_channel) {
+            messages.push(message(player_channel));
+        }
+
+        if let PlayerEvent::PartyJoined { party_id, player } = &event.event {
+            if let Some(members) = self.parties.get_mut(party_id) {
+                members.insert(event.player_id, player.account_id());
+            }
+        }
+        messages.extend(
+            self.parties
+                .iter()
+                .filter(|(_, members)| members.contains_key(&event.player_id))
+                .map(|(&party_id, _)| message(Channel::Party(party_id))),
+        );
+        if let PlayerEvent::PartyLeft { party_id, .. } = &event.event {
+            if let Some(members) = self.parties.get_mut(party_id) {
+                members.remove(&event.player_id);
+                let account_id = Some(self.account_id);
+                if !members.values().any(|owner| *owner == account_id) {
+                    self.parties.remove(party_id);
+                    let channel = Channel::Party(*party_id);
+                    self.channels.remove(&channel);
+                    messages.push(ServerMessage::Unsubscribed { id: None, channel });
+                }
+            }
+        }
+        messages
+    }
+
+    /// Handles messages from the client and sends it events until either side closes the
+    /// connection or the server shuts down. Messages are handled one at a time, and nothing is read from the client while
+    /// it is not accepting replies.
+    async fn run(mut self, mut session: Session, mut messages: AggregatedMessageStream) {
+        let state = self.state.clone();
+        let mut events = state.events().listen();
+        let mut heartbeat =
+            time::interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut leaderboard_updates = time::interval(LEADERBOARD_INTERVAL);
+        leaderboard_updates.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_heard = Instant::now();
+
+        let close_reason = loop {
+            // Events go first, so that they are sent in the order they happened relative to the
+            // leaderboard updates they cause and to the replies of later messages.
+            let replies = tokio::select! {
+                biased;
+                _ = state.shutdown().cancelled() => break Some(CloseReason {
+                    code: CloseCode::Restart,
+                    description: Some("The server is shutting down.".to_owned()),
+                }),
+                event = events.recv() => match event {
+                    Ok(event) => self.handle_event(&event),
+                    Err(RecvError::Lagged(_)) => vec![ServerMessage::Resync],
+                    Err(RecvError::Closed) => break None,
+                },
+                message = messages.recv() => {
+                    last_heard = Instant::now();
+                    match message {
+                        Some(Ok(AggregatedMessage::Text(text))) => self.handle_text(&text).await,
+                        Some(Ok(AggregatedMessage::Binary(_))) => vec![ServerMessage::error(
+                            None,
+                            RouteError::MalformedBody("Messages must be JSON text.".to_owned()),
+                        )],
+                        Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                            if deliver(session.pong(&bytes)).await.is_err() {
+                                return;
+                            }
+                            continue;
+                        }
+                        Some(Ok(AggregatedMessage::Pong(_))) => continue,
+                        Some(Ok(AggregatedMessage::Close(reason))) => break reason,
+                        Some(Err(error)) => {
+                            let code = match error {
+                                ProtocolError::Overflow => CloseCode::Size,
+                                _ => CloseCode::Protocol,
+                            };
+                            break Some(CloseReason {
+                                code,
+                                description: Some(error.to_string()),
+                            });
+                        }
+                        None => break None,
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_heard.elapsed() > CLIENT_TIMEOUT {
+                        break Some(CloseReason {
+                            code: CloseCode::Away,
+                            description: Some("No heartbeat received.".to_owned()),
+                        });
+                    }
+                    if deliver(session.ping(b"")).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                _ = leaderboard_updates.tick(), if self.leaderboard_stale => {
+                    let _guard = state.maintenance().read().await;
+                    vec![self
+                        .leaderboard()
+                        .await
+                        .unwrap_or_else(|error| ServerMessage::error(None, error))]
+                }
+            };
+            for reply in replies {
+                let text =
+                    serde_json::to_string(&reply).expect("Messages should serialize to JSON.");
+                if deliver(session.text(text)).await.is_err() {
+                    return;
+                }
+            }
+        };
+        let _ = deliver(session.close(close_reason)).await;
+    }
+}
+
+/// Opens a WebSocket for subscribing to channels and sending commands. The connection acts as the
+/// caller of this request, and every message is checked like the equivalent REST request.
+#[get("")]
+pub async fn connect(
+    state: web::Data<State>,
+    account_id: web::ReqData<AccountId>,
+    scope: web::ReqData<TokenScope>,
+    request: HttpRequest,
+    body: web::Payload,
+) -> Result<HttpResponse, RouteError> {
+    let (response, session, messages) = actix_ws::handle(&request, body)
+        .map_err(|error| RouteError::InvalidHandshake(error.to_string()))?;
+    let messages = messages
+        .max_frame_size(MAX_MESSAGE_SIZE)
+        .aggregate_continuations()
+        .max_continuation_size(MAX_MESSAGE_SIZE);
+    let connection = Connection {
+        state,
+        account_id: *account_id,
+        scope: *scope,
+        channels: HashSet::new(),
+        parties: HashMap::new(),
+        leaderboard_stale: false,
+    };
+    rt::spawn(connection.run(session, messages));
+    Ok(response)
+}
+
+pub fn add_routes(scope: Scope) -> Scope {
+    scope.service(connect)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fmt::Debug, future, pin::Pin, time::Duration};
+
+    use actix_http::{
+        ws::{OpCode, Parser},
+        BoxedPayloadStream, Payload, Request,
+    };
+    use actix_service::Service;
+    use actix_web::{
+        body::{BoxBody, MessageBody},
+        dev::ServiceResponse,
+        http::{header, StatusCode},
+        test::{self as actix_test, TestRequest},
+        web::{self, Bytes, BytesMut},
+    };
+    use actix_ws::CloseCode;
+    use futures_util::stream;
+    use habi2ca_database::api_token::TokenScope;
+    use serde_json::{json, Value};
+    use tokio::{sync::mpsc, time};
+
+    use crate::{
+        logic::{
+            account::{Account, Credentials},
+            api_token::{ApiToken, ApiTokenData},
+            habit::{Habit, HabitData},
+            party::{Party, PartyData},
+            player::Player,
+            task::{Task, TaskData},
+        },
+        routes::ErrorCode,
+        start::{create_app, create_app_with_state},
+        state::State,
+        test_utils,
+    };
+
+    /// The client side of a WebSocket opened on the test app.
+    struct TestClient {
+        sender: mpsc::UnboundedSender<Bytes>,
+        body: BoxBody,
+        buffer: BytesMut,
+    }
+
+    impl TestClient {
+        async fn connect<M, S, E>(app: &S, request: TestRequest) -> Self
+        where
+            M: MessageBody + 'static,
+            S: Service<Request, Response = ServiceResponse<M>, Error = E>,
+            E: Debug,
+        {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let payload: BoxedPayloadStream =
+                Box::pin(stream::unfold(receiver, |mut receiver| async move {
+                    let chunk = receiver.recv().await?;
+                    Some((Ok(chunk), receiver))
+                }));
+            let (request, _) = request
+                .insert_header((header::CONNECTION, "upgrade"))
+                .insert_header((header::UPGRADE, "websocket"))
+                .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+                .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+                .to_request()
+                .replace_payload(Payload::from(payload));
+            let response = actix_test::call_service(app, test_utils::authenticate(request)).await;
+            assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+            Self {
+                sender,
+                body: response.into_body().boxed(),
+                buffer: BytesMut::new(),
+            }
+        }
+
+        fn send_frame(&self, payload: &[u8], op_code: OpCode) {
+            let mut frame = BytesMut::new();
+            Parser::write_message(&mut frame, payload, op_code, true, true);
+            self.sender.send(frame.freeze()).unwrap();
+        }
+
+        fn send(&self, message: Value) {
+            self.send_frame(message.to_string().as_bytes(), OpCode::Text);
+        }
+
+        /// Reads the next frame other than a ping, or `None` if the connection was closed.
+        async fn next_frame(&mut self) -> Option<(OpCode, BytesMut)> {
+            loop {
+                if let Some((_, op_code, payload)) =
+                    Parser::parse(&mut self.buffer, false, usize::MAX).unwrap()
+                {
+                    if op_code != OpCode::Ping {
+                        return Some((op_code, payload.unwrap_or_default()));
+                    }
+                    continue;
+                }
+                let chunk = time::timeout(
+                    Duration::from_secs(5),
+                    future::poll_fn(|cx| Pin::new(&mut self.body).poll_next(cx)),
+                )
+                .await
+                .expect("No message was sent.")?
+                .unwrap();
+                self.buffer.extend_from_slice(&chunk);
+            }
+        }
+
+        async fn next_message(&mut self) -> Value {
+            let (op_code, payload) = self.next_frame().await.expect("The connection closed.");
+            assert_eq!(op_code, OpCode::Text);
+            serde_json::from_slice(&payload).unwrap()
+        }
+
+        /// Reads the next message, which must be the reply to the message with the given id.
+        async fn reply(&mut self, id: u64) -> Value {
+            let message = self.next_message().await;
+            assert_eq!(message["id"], id, "Unexpected message: {message}");
+            message
+        }
+
+        /// Reads the next message, which must be an event of the given type on the channel.
+        async fn expect_event(&mut self, channel: &str, event_type: &str) -> Value {
+            let message = self.next_message().await;
+            assert_eq!(message["type"], "event", "Unexpected message: {message}");
+            assert_eq!(message["channel"], channel, "Unexpected message: {message}");
+            assert_eq!(
+                message["event"]["type"], event_type,
+                "Unexpected message: {message}"
+            );
+            assert!(message["event_id"].is_u64());
+            message["event"].clone()
+        }
+
+        async fn expect_error(&mut self, id: u64, code: ErrorCode) {
+            let reply = self.reply(id).await;
+            assert_eq!(reply["type"], "error");
+            assert_eq!(reply["problem"]["code"], json!(code));
+        }
+    }
+
+    #[actix_web::test]
+    async fn websocket() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Wanda")
+            .await
+            .unwrap();
+        let other_account = Account::create(
+            &database,
+            Credentials {
+                username: "mallory".to_owned(),
+                password: "correct horse battery staple".to_owned(),
+            },
+        )
+        .await
+        .unwrap();
+        let other_player = Player::create(&database, other_account.id(), "Mallory")
+            .await
+            .unwrap();
+        let task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Socket task".to_string(),
+                description: "Description".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+        let habit = Habit::create(
+            &database,
+            HabitData {
+                player_id: player.id(),
+                name: "Socket habit".to_string(),
+                description: "Description".to_string(),
+                positive: true,
+                negative: false,
+            },
+        )
+        .await
+        .unwrap();
+        let app = actix_test::init_service(create_app(database)).await;
+
+        let response =
+            actix_test::call_service(&app, TestRequest::get().uri("/api/ws").to_request()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        test_utils::assert_error_response(
+            &app,
+            TestRequest::get().uri("/api/ws").to_request(),
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidHandshake,
+        )
+        .await;
+
+        let mut client = TestClient::connect(&app, TestRequest::get().uri("/api/ws")).await;
+        let player_channel = format!("player:{}", player.id());
+        client.send(json!({ "type": "subscribe", "id": 1, "channel": player_channel }));
+        assert_eq!(
+            client.reply(1).await,
+            json!({ "type": "subscribed", "id": 1, "channel": player_channel })
+        );
+        client.send(json!({ "type": "subscribe", "id": 2, "channel": "party:one" }));
+        client.expect_error(2, ErrorCode::InvalidParameter).await;
+        client.send(json!({
+            "type": "subscribe",
+            "id": 3,
+            "channel": format!("player:{}", other_player.id()),
+        }));
+        client.expect_error(3, ErrorCode::Forbidden).await;
+        client.send(json!({ "type": "subscribe", "id": 4, "channel": "player:1000" }));
+        client.expect_error(4, ErrorCode::PlayerNotFound).await;
+        client.send(json!({ "type": "dance", "id": 5 }));
+        client.expect_error(5, ErrorCode::InvalidBody).await;
+        client.send_frame(b"{", OpCode::Text);
+        let reply = client.next_message().await;
+        assert_eq!(reply["type"], "error");
+        assert_eq!(reply["problem"]["code"], json!(ErrorCode::MalformedBody));
+
+        client.send(json!({ "type": "subscribe", "id": 6, "channel": "leaderboard" }));
+        assert_eq!(client.reply(6).await["type"], "subscribed");
+        let leaderboard = client.next_message().await;
+        assert_eq!(leaderboard["type"], "leaderboard");
+        assert_eq!(
+            leaderboard["players"],
+            json!([
+                { "player_id": player.id(), "name": "Wanda", "level": 1, "xp": 0.0 },
+                { "player_id": other_player.id(), "name": "Mallory", "level": 1, "xp": 0.0 },
+            ])
+        );
+
+        client.send(json!({ "type": "complete_task", "id": 7, "task_id": task.id() }));
+        let reply = client.reply(7).await;
+        assert_eq!(reply["type"], "result");
+        assert_eq!(reply["data"]["completed"], true);
+        let event = client.expect_event(&player_channel, "xp_gained").await;
+        assert_eq!(event["player"]["name"], "Wanda");
+        let event = client.expect_event(&player_channel, "task_completed").await;
+        assert_eq!(event["task"]["name"], "Socket task");
+        // Xp changes update the leaderboard, after the events causing them.
+        let leaderboard = client.next_message().await;
+        assert_eq!(leaderboard["type"], "leaderboard");
+        assert_eq!(leaderboard["players"][0]["name"], "Wanda");
+        assert!(leaderboard["players"][0]["xp"].as_f64() > Some(0.0));
+
+        client.send(json!({ "type": "increment_habit", "id": 8, "habit_id": habit.id() }));
+        let reply = client.reply(8).await;
+        assert_eq!(reply["type"], "result");
+        assert_eq!(reply["data"]["name"], "Socket habit");
+        client.expect_event(&player_channel, "xp_gained").await;
+        client
+            .expect_event(&player_channel, "habit_incremented")
+            .await;
+        client.send(json!({ "type": "complete_task", "id": 9, "task_id": 1000 }));
+        client.expect_error(9, ErrorCode::TaskNotFound).await;
+
+        client.send(json!({ "type": "unsubscribe", "id": 10, "channel": player_channel }));
+        assert_eq!(
+            client.reply(10).await,
+            json!({ "type": "unsubscribed", "id": 10, "channel": player_channel })
+        );
+
+        // The server answers a close with a close, and then ends the connection.
+        client.send_frame(&[], OpCode::Close);
+        loop {
+            match client.next_frame().await {
+                Some((OpCode::Close, _)) => break,
+                Some((OpCode::Text, _)) => {}
+                frame => panic!("Unexpected frame: {frame:?}"),
+            }
+        }
+        assert!(client.next_frame().await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn websocket_shutdown() {
+        let database = test_utils::setup_database().await;
+        let state = web::Data::new(State::new(database, None));
+        let app = actix_test::init_service(create_app_with_state(state.clone())).await;
+        let mut client = TestClient::connect(&app, TestRequest::get().uri("/api/ws")).await;
+
+        // Clients are told to reconnect once the server is back.
+        state.shutdown().cancel();
+        let (op_code, payload) = client.next_frame().await.expect("The connection closed.");
+        assert_eq!(op_code, OpCode::Close);
+        assert_eq!(
+            u16::from_be_bytes([payload[0], payload[1]]),
+            u16::from(CloseCode::Restart)
+        );
+        assert!(client.next_frame().await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn websocket_party() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let other_account = Account::create(
+            &database,
+            Credentials {
+                username: "mallory".to_owned(),
+                password: "correct horse battery staple".to_owned(),
+            },
+        )
+        .await
+        .unwrap();
+        let founder = Player::create(&database, other_account.id(), "Mallory")
+            .await
+            .unwrap();
+        let newcomer = Player::create(&database, other_account.id(), "Trudy")
+            .await
+            .unwrap();
+        let mut task = Task::create(
+            &database,
+            TaskData {
+                player_id: founder.id(),
+                name: "Party task".to_string(),
+                description: "Description".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+        let state = web::Data::new(State::new(database.clone(), None));
+        let app = actix_test::init_service(create_app_with_state(state.clone())).await;
+        let (mut party, outbox) = Party::create(
+            &database,
+            PartyData {
+                name: "Guild".to_string(),
+                player_id: founder.id(),
+            },
+        )
+        .await
+        .unwrap();
+        outbox.publish(state.events());
+        let party_channel = format!("party:{}", party.id());
+
+        let mut client = TestClient::connect(&app, TestRequest::get().uri("/api/ws")).await;
+        // Only accounts with a player in the party can subscribe to it.
+        client.send(json!({ "type": "subscribe", "id": 1, "channel": party_channel }));
+        client.expect_error(1, ErrorCode::PartyNotFound).await;
+        client.send(json!({ "type": "subscribe", "id": 2, "channel": "party:1000" }));
+        client.expect_error(2, ErrorCode::PartyNotFound).await;
+
+        let _: Party = test_utils::assert_ok_response(
+            &app,
+            TestRequest::post()
+                .uri(&format!("/api/parties/{}/members", party.id()))
+                .set_json(json!({ "player_id": player.id() }))
+                .to_request(),
+        )
+        .await;
+        client.send(json!({ "type": "subscribe", "id": 3, "channel": party_channel }));
+        assert_eq!(
+            client.reply(3).await,
+            json!({ "type": "subscribed", "id": 3, "channel": party_channel })
+        );
+
+        // The events of every member are sent, including those of other accounts.
+        task.complete_task(&database)
+            .await
+            .unwrap()
+            .publish(state.events());
+        let event = client.expect_event(&party_channel, "xp_gained").await;
+        assert_eq!(event["player"]["name"], "Mallory");
+        client.expect_event(&party_channel, "task_completed").await;
+        party
+            .join(&database, newcomer.id())
+            .await
+            .unwrap()
+            .publish(state.events());
+        let event = client.expect_event(&party_channel, "party_joined").await;
+        assert_eq!(event["party_id"], json!(party.id()));
+        assert_eq!(event["player"]["name"], "Trudy");
+
+        // Once the account's last player leaves, the connection is unsubscribed.
+        let _: Party = test_utils::assert_ok_response(
+            &app,
+            TestRequest::delete()
+                .uri(&format!(
+                    "/api/parties/{}/members/{}",
+                    party.id(),
+                    player.id()
+                ))
+                .to_request(),
+        )
+        .await;
+        let event = client.expect_event(&party_channel, "party_left").await;
+        assert_eq!(event["player"]["name"], "Alice");
+        assert_eq!(
+            client.next_message().await,
+            json!({ "type": "unsubscribed", "id": null, "channel": party_channel })
+        );
+        task.uncomplete_task(&database)
+            .await
+            .unwrap()
+            .publish(state.events());
+        client.send(json!({ "type": "subscribe", "id": 4, "channel": party_channel }));
+        client.expect_error(4, ErrorCode::PartyNotFound).await;
+    }
+
+    #[actix_web::test]
+    async fn websocket_scope() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task".to_string(),
+                description: "Description".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+        let created = ApiToken::create(
+            &database,
+            test_utils::TEST_ACCOUNT,
+            ApiTokenData {
+                name: "tasks".to_string(),
+                scope: TokenScope::Tasks,
+            },
+        )
+        .await
+        .unwrap();
+        let app = actix_test::init_service(create_app(database)).await;
+
+        // The socket can be opened with any token, but only used within its scope.
+        let mut client = TestClient::connect(
+            &app,
+            TestRequest::get()
+                .uri("/api/ws")
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", created.token))),
+        )
+        .await;
+        client.send(json!({ "type": "complete_task", "id": 1, "task_id": task.id() }));
+        assert_eq!(client.reply(1).await["type"], "result");
+        client.send(json!({ "type": "increment_habit", "id": 2, "habit_id": 1 }));
+        client.expect_error(2, ErrorCode::InsufficientScope).await;
+        client.send(json!({ "type": "subscribe", "id": 3, "channel": "leaderboard" }));
+        client.expect_error(3, ErrorCode::InsufficientScope).await;
+    }
+}
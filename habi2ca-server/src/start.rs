@@ -1,21 +1,35 @@
-use std::{fs, path::PathBuf};
+use std::{fs, io, sync::Arc, time::Duration};
 
-use ::tracing::info;
+use ::tracing::{info, warn};
 use actix_web::{
     body::MessageBody,
     dev::{ServiceFactory, ServiceRequest},
     middleware::{self, TrailingSlash},
     web, App, HttpServer,
 };
-use anyhow::{bail, Result};
-use sea_orm::DatabaseConnection;
+use anyhow::{bail, Context, Result};
+use tokio::{
+    signal,
+    sync::watch,
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
 use tracing_actix_web::TracingLogger;
 
-use crate::{cli::ServerConfig, database_utils, routes, state::State, tracing, Never};
+use crate::{
+    backup::Backups,
+    config::ServerConfig,
+    database_utils,
+    events::EventBus,
+    frontend,
+    gamedata::Gamedata,
+    jobs, metrics, routes,
+    state::{MaintenanceLock, State},
+    tracing,
+};
 
-pub fn create_app_with_database_path(
-    database: DatabaseConnection,
-    database_path: Option<PathBuf>,
+pub fn create_app_with_state(
+    state: web::Data<State>,
 ) -> App<
     impl ServiceFactory<
         ServiceRequest,
@@ -25,16 +39,26 @@ pub fn create_app_with_database_path(
         InitError = (),
     >,
 > {
-    App::new()
-        .app_data(web::Data::new(State::new(database, database_path)))
+    let serve_frontend = state.static_dir().is_some();
+    let app = App::new()
+        .app_data(state)
+        .wrap(middleware::from_fn(routes::authorize))
+        .wrap(middleware::from_fn(routes::hold_off_maintenance))
         .wrap(middleware::NormalizePath::new(TrailingSlash::Trim))
+        .wrap(middleware::from_fn(metrics::record_requests))
         .wrap(TracingLogger::default())
-        .service(routes::add_routes(web::scope("/api")))
+        .service(metrics::get_metrics)
+        .service(routes::add_routes(web::scope("/api")));
+    if serve_frontend {
+        app.default_service(web::to(frontend::serve))
+    } else {
+        app
+    }
 }
 
 #[cfg(test)]
 pub fn create_app(
-    database: DatabaseConnection,
+    database: sea_orm::DatabaseConnection,
 ) -> App<
     impl ServiceFactory<
         ServiceRequest,
@@ -44,31 +68,168 @@ pub fn create_app(
         InitError = (),
     >,
 > {
-    create_app_with_database_path(database, None)
+    create_app_with_state(web::Data::new(State::new(database, None)))
 }
 
-pub async fn start_server(config: ServerConfig) -> Result<Never> {
+/// Waits for SIGINT, or SIGTERM on Unix, which Docker and Cloud Run send to stop the server.
+async fn shutdown_signal() -> io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result.map(|()| "SIGINT"),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    signal::ctrl_c().await.map(|()| "SIGINT")
+}
+
+/// Runs the server until it receives a shutdown signal, and then shuts it down gracefully: it stops
+/// accepting connections, waits up to the shutdown timeout for in-flight requests and background
+/// jobs to finish, closes the database and flushes the logs.
+pub async fn start_server(config: ServerConfig) -> Result<()> {
+    let retention = config.retention();
+    let logging = config.logging();
+    let otlp = config.otlp();
     let ServerConfig {
         database_path,
+        database_url,
+        force_migrations,
+        migration_dry_run,
         hostname,
         port,
-        force_migrations,
-        log_dir,
+        shutdown_timeout,
+        log_format: _,
+        log_filter: _,
+        log_dir: _,
+        log_file_filter: _,
+        log_rotation: _,
+        log_max_files: _,
+        otlp_endpoint: _,
+        otlp_service_name: _,
+        otlp_sample_ratio: _,
+        backup_interval,
+        backup_keep_last: _,
+        backup_keep_daily: _,
+        backup_keep_weekly: _,
+        admin_secret,
+        gamedata_dir,
+        rollover_interval,
+        static_dir,
     } = config;
 
-    let _guard = log_dir.map(tracing::setup_tracing).transpose()?;
+    let tracing_guard = tracing::setup_tracing(&logging, otlp.as_ref())?;
 
     let hostname = hostname.as_ref();
-    fs::create_dir_all(database_path.parent().unwrap())?;
-    let database =
-        database_utils::open_or_initialize_database(&database_path, force_migrations).await?;
+    let (mut database, backups) = match (database_url, database_path) {
+        (Some(database_url), _) => (
+            database_utils::open_database_url(&database_url).await?,
+            None,
+        ),
+        (None, Some(database_path)) => {
+            fs::create_dir_all(database_path.parent().unwrap())?;
+            let backups = Backups::new(database_path, retention);
+            let database = database_utils::open_or_initialize_database(
+                &backups,
+                force_migrations,
+                migration_dry_run,
+            )
+            .await?;
+            (database, Some(backups))
+        }
+        (None, None) => bail!("Either a database path or a database URL is required."),
+    };
+    database.set_metric_callback(|info| metrics::metrics().record_query(info));
+
+    let gamedata = match gamedata_dir {
+        Some(gamedata_dir) => Gamedata::load(&gamedata_dir)?,
+        None => Gamedata::embedded()?,
+    };
+    gamedata.sync(&database).await?;
 
-    let server = HttpServer::new(move || {
-        create_app_with_database_path(database.clone(), Some(database_path.clone()))
-    });
+    let events = Arc::new(EventBus::new());
+    let maintenance = MaintenanceLock::default();
+    let (stop_jobs, jobs_shutdown) = watch::channel(());
+    let mut jobs = vec![jobs::spawn_daily_rollover(
+        database.clone(),
+        events.clone(),
+        maintenance.clone(),
+        Duration::from_secs(rollover_interval),
+        jobs_shutdown.clone(),
+    )];
+    if let (Some(backup_interval), Some(backups)) = (backup_interval, &backups) {
+        jobs.push(jobs::spawn_scheduled_backups(
+            database.clone(),
+            backups.clone(),
+            maintenance.clone(),
+            Duration::from_secs(backup_interval * 60 * 60),
+            jobs_shutdown,
+        ));
+    }
+
+    if admin_secret.is_none() {
+        info!("No admin secret configured. Admin routes are disabled.");
+    }
+    if let Some(static_dir) = &static_dir {
+        if !static_dir.join("index.html").is_file() {
+            bail!("{} has no index.html to serve.", static_dir.display());
+        }
+    }
+    let shutdown = CancellationToken::new();
+    let state = web::Data::new(
+        State::new(database.clone(), backups)
+            .with_events(events)
+            .with_maintenance_lock(maintenance)
+            .with_admin_secret(admin_secret)
+            .with_static_dir(static_dir)
+            .with_log_filters(Some(tracing_guard.log_filters()))
+            .with_shutdown(shutdown.clone()),
+    );
+    let server = HttpServer::new(move || create_app_with_state(state.clone()))
+        .shutdown_timeout(shutdown_timeout)
+        // Signals are handled below, so every signal shuts the server down gracefully.
+        .disable_signals();
 
     info!("Starting server at http://{hostname}:{port}");
-    server.bind((hostname, port))?.run().await?;
+    let mut server = server.bind((hostname, port))?.run();
+    let server_handle = server.handle();
+    tokio::select! {
+        result = &mut server => {
+            result?;
+            bail!("Server stopped unexpectedly.")
+        }
+        signal = shutdown_signal() => {
+            let signal = signal.context("Failed to listen for shutdown signals")?;
+            info!("Received {signal}. Shutting down...");
+        }
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(shutdown_timeout);
+    drop(stop_jobs);
+    // Streams to clients never end on their own, so they are closed for the server to stop.
+    shutdown.cancel();
+    // The server must be polled to process the stop command.
+    let ((), result) = tokio::join!(server_handle.stop(true), server);
+    result?;
+    let jobs_finished = time::timeout_at(deadline, async {
+        for job in jobs {
+            if let Err(error) = job.await {
+                warn!("Background job failed: {error}");
+            }
+        }
+    })
+    .await;
+    if jobs_finished.is_err() {
+        warn!("Background jobs did not finish within {shutdown_timeout} seconds.");
+    }
 
-    bail!("Server stopped unexpectedly.")
+    database
+        .close()
+        .await
+        .context("Failed to close database connection")?;
+    info!("Server stopped.");
+    // Dropping the guard flushes the logs and traces.
+    drop(tracing_guard);
+    Ok(())
 }
@@ -1,25 +1,112 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use sea_orm::DatabaseConnection;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+
+use crate::{backup::Backups, events::EventBus, tracing::LogFilters};
+
+/// Held for reading while the database is in use, and for writing by maintenance that replaces its
+/// contents, such as reinitializing it, so that the maintenance waits for requests and jobs in
+/// progress and holds off new ones.
+pub type MaintenanceLock = Arc<RwLock<()>>;
 
 pub struct State {
     database: DatabaseConnection,
-    database_path: Option<PathBuf>,
+    events: Arc<EventBus>,
+    maintenance: MaintenanceLock,
+    backups: Option<Backups>,
+    admin_secret: Option<String>,
+    static_dir: Option<PathBuf>,
+    log_filters: Option<LogFilters>,
+    shutdown: CancellationToken,
 }
 
 impl State {
-    pub fn new(database: DatabaseConnection, database_path: Option<PathBuf>) -> Self {
+    pub fn new(database: DatabaseConnection, backups: Option<Backups>) -> Self {
         State {
             database,
-            database_path,
+            events: Arc::new(EventBus::new()),
+            maintenance: MaintenanceLock::default(),
+            backups,
+            admin_secret: None,
+            static_dir: None,
+            log_filters: None,
+            shutdown: CancellationToken::new(),
         }
     }
 
+    /// Publishes events to `events`, so that they can be shared with background jobs.
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Shares the maintenance lock with background jobs.
+    pub fn with_maintenance_lock(mut self, maintenance: MaintenanceLock) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Enables the admin routes for requests presenting `admin_secret`.
+    pub fn with_admin_secret(mut self, admin_secret: Option<String>) -> Self {
+        self.admin_secret = admin_secret;
+        self
+    }
+
+    /// Serves the frontend build in `static_dir` for requests outside the API.
+    pub fn with_static_dir(mut self, static_dir: Option<PathBuf>) -> Self {
+        self.static_dir = static_dir;
+        self
+    }
+
+    /// Lets admins change which events are logged.
+    pub fn with_log_filters(mut self, log_filters: Option<LogFilters>) -> Self {
+        self.log_filters = log_filters;
+        self
+    }
+
+    /// Ends streams to clients once `shutdown` is cancelled, since they would otherwise keep the
+    /// server from shutting down.
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
     pub fn database(&self) -> &DatabaseConnection {
         &self.database
     }
 
-    pub fn database_path(&self) -> Option<&Path> {
-        self.database_path.as_deref()
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn maintenance(&self) -> &MaintenanceLock {
+        &self.maintenance
+    }
+
+    /// Backups of the database. Only available if the database is stored in a file.
+    pub fn backups(&self) -> Option<&Backups> {
+        self.backups.as_ref()
+    }
+
+    pub fn admin_secret(&self) -> Option<&str> {
+        self.admin_secret.as_deref()
+    }
+
+    pub fn static_dir(&self) -> Option<&Path> {
+        self.static_dir.as_deref()
+    }
+
+    pub fn log_filters(&self) -> Option<&LogFilters> {
+        self.log_filters.as_ref()
+    }
+
+    /// Cancelled when the server starts shutting down.
+    pub fn shutdown(&self) -> &CancellationToken {
+        &self.shutdown
     }
 }
@@ -1,22 +1,105 @@
 use actix_http::Request;
 use actix_service::Service;
-use actix_web::{body::MessageBody, test as actix_test};
-use habi2ca_database::migration::{Migrator, MigratorTrait};
-use sea_orm::{Database, DatabaseConnection};
+use actix_web::{
+    body::MessageBody,
+    http::{header, StatusCode},
+    test as actix_test,
+};
+use actix_web::{http::header::HeaderValue, HttpMessage};
+use habi2ca_database::{
+    account::{self, AccountId},
+    migration::{Migrator, MigratorTrait},
+};
+use rand_core::{OsRng, RngCore};
+use sea_orm::{ConnectOptions, ConnectionTrait, Database, DatabaseConnection, EntityTrait, Set};
 use serde::de::DeserializeOwned;
 
+use crate::{
+    logic::account::Session,
+    routes::{ErrorCode, Problem, PROBLEM_JSON, SESSION_COOKIE},
+};
+
+/// Account created by [`setup_database`] to own the players of tests.
+/// Requests sent through the `assert_*` helpers are authenticated as this account unless they
+/// carry their own cookies.
+pub const TEST_ACCOUNT: AccountId = AccountId(1);
+const TEST_SESSION_TOKEN: &str = "test-session";
+
+/// Environment variable with the URL of a PostgreSQL database to run the tests against instead of
+/// in-memory SQLite databases. Requires the `postgres` feature.
+pub const TEST_DATABASE_URL_VAR: &str = "HABI2CA_TEST_DATABASE_URL";
+
 pub async fn setup_database() -> DatabaseConnection {
-    let database = Database::connect("sqlite::memory:")
-        .await
-        .expect("Failed to connect to database.");
+    let database = match std::env::var(TEST_DATABASE_URL_VAR) {
+        Ok(url) => connect_to_new_schema(&url).await,
+        Err(_) => Database::connect("sqlite::memory:")
+            .await
+            .expect("Failed to connect to database."),
+    };
 
     Migrator::up(&database, None)
         .await
         .expect("Failed to run migrations.");
 
+    create_test_account(&database).await;
     database
 }
 
+/// Connects to a new schema in the database at `url`, so that tests running at the same time do not
+/// see each other's data. The schemas are not dropped afterwards.
+async fn connect_to_new_schema(url: &str) -> DatabaseConnection {
+    let mut bytes = [0; 8];
+    OsRng.fill_bytes(&mut bytes);
+    let schema = format!("test_{}", hex::encode(bytes));
+
+    let database = Database::connect(url)
+        .await
+        .expect("Failed to connect to test database.");
+    database
+        .execute_unprepared(&format!("CREATE SCHEMA \"{schema}\""))
+        .await
+        .expect("Failed to create test schema.");
+    database.close().await.unwrap();
+
+    let mut options = ConnectOptions::new(url);
+    options.set_schema_search_path(schema);
+    Database::connect(options)
+        .await
+        .expect("Failed to connect to test schema.")
+}
+
+/// Creates [`TEST_ACCOUNT`] and its session.
+pub async fn create_test_account(database: &DatabaseConnection) {
+    // Inserted directly since hashing a password is slow and tests never log in as this account.
+    // The id is left to the database, so that PostgreSQL's id sequence is advanced past it.
+    let account_id = account::Entity::insert(account::ActiveModel {
+        username: Set("test".to_owned()),
+        password_hash: Set(String::new()),
+        created_at: Set(chrono::Utc::now()),
+        ..Default::default()
+    })
+    .exec(database)
+    .await
+    .expect("Failed to create test account.")
+    .last_insert_id;
+    assert_eq!(account_id, TEST_ACCOUNT);
+    Session::create_with_token(database, TEST_ACCOUNT, TEST_SESSION_TOKEN)
+        .await
+        .expect("Failed to create test session.");
+}
+
+/// Authenticates the request as [`TEST_ACCOUNT`] if it has no cookies.
+pub fn authenticate(mut req: Request) -> Request {
+    if !req.headers().contains_key(header::COOKIE) {
+        let cookie = format!("{SESSION_COOKIE}={TEST_SESSION_TOKEN}");
+        req.headers_mut().insert(
+            header::COOKIE,
+            HeaderValue::from_str(&cookie).expect("Cookie should be a valid header value."),
+        );
+    }
+    req
+}
+
 pub async fn assert_ok_response<M, S, E, R>(app: &S, req: Request) -> R
 where
     M: MessageBody,
@@ -24,7 +107,7 @@ where
     E: std::fmt::Debug,
     R: DeserializeOwned,
 {
-    let response = actix_test::call_service(app, req).await;
+    let response = actix_test::call_service(app, authenticate(req)).await;
     if !response.status().is_success() {
         let status_code = response.status();
         let body = actix_test::read_body(response).await;
@@ -35,6 +118,34 @@ where
     }
 }
 
+pub async fn assert_error_response<M, S, E>(
+    app: &S,
+    req: Request,
+    expected_status: StatusCode,
+    expected_code: ErrorCode,
+) -> Problem
+where
+    M: MessageBody,
+    S: Service<Request, Response = actix_web::dev::ServiceResponse<M>, Error = E>,
+    E: std::fmt::Debug,
+{
+    let response = actix_test::call_service(app, authenticate(req)).await;
+    let status_code = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_owned());
+    let body = actix_test::read_body(response).await;
+    let body = std::str::from_utf8(&body).unwrap();
+    assert_eq!(status_code, expected_status, "Unexpected status: {body}");
+    assert_eq!(content_type.as_deref(), Some(PROBLEM_JSON));
+
+    let problem: Problem = serde_json::from_str(body).unwrap();
+    assert_eq!(problem.status, expected_status.as_u16());
+    assert_eq!(problem.code, expected_code);
+    problem
+}
+
 #[cfg(test)]
 mod test {
 
@@ -1,38 +1,517 @@
-use std::{io, path::Path};
+use std::path::PathBuf;
 
 use anyhow::{Context, Result};
-use tracing::{subscriber, Level};
-use tracing_appender::{non_blocking::WorkerGuard, rolling};
-use tracing_subscriber::{filter, fmt, layer::SubscriberExt, Layer};
-
-pub fn setup_tracing(log_dir: impl AsRef<Path>) -> Result<WorkerGuard> {
-    let log_dir = log_dir.as_ref();
-    let (log_file, guard) = tracing_appender::non_blocking(rolling::daily(log_dir, "debug"));
-    let log_layer = fmt::Layer::new()
-        .with_writer(log_file)
-        .with_ansi(false)
+use clap::ValueEnum;
+use opentelemetry::{global, trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Sampler, TracerProvider},
+    Resource,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{subscriber, Level, Subscriber};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{self, RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    filter::{self, EnvFilter, ParseError},
+    fmt::{self, MakeWriter},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    reload, Layer, Registry,
+};
+
+/// Target of events that should be kept in the audit log.
+pub const AUDIT_TARGET: &str = "audit";
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers.
+    Json,
+}
+
+/// How often a new log file is started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// What to log and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Filter directives of the logs written to stdout, in the syntax of `RUST_LOG`.
+    pub filter: String,
+    /// Directory to write log files to. Only stdout is logged to if not set.
+    pub dir: Option<PathBuf>,
+    /// Filter directives of the log files. The audit log is not affected.
+    pub file_filter: String,
+    pub rotation: LogRotation,
+    /// Number of log files to keep before the oldest are deleted. All are kept if not set.
+    pub max_files: Option<usize>,
+}
+
+/// Where and how to export traces over OTLP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtlpConfig {
+    /// gRPC endpoint of the collector, e.g. `http://localhost:4317`.
+    pub endpoint: String,
+    pub service_name: String,
+    /// Fraction of traces to export. Traces continued from an incoming `traceparent` header follow
+    /// the sampling decision of the caller instead.
+    pub sample_ratio: f64,
+}
+
+#[derive(Debug, Error)]
+pub enum LogFilterError {
+    #[error("Invalid log filter '{directives}': {source}")]
+    Invalid {
+        directives: String,
+        #[source]
+        source: ParseError,
+    },
+    #[error("No log files are written, since no log directory is configured.")]
+    NoLogFiles,
+    #[error("Failed to replace log filter.")]
+    Reload(#[from] reload::Error),
+}
+
+/// Filter directives of the logs. When updating, logs without directives keep their filter.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogFilterDirectives {
+    pub stdout: Option<String>,
+    /// Always `None` when reading the directives if no log files are written.
+    pub file: Option<String>,
+}
+
+/// Changes which events are logged while the server is running.
+#[derive(Debug, Clone)]
+pub struct LogFilters {
+    stdout: FilterHandle,
+    file: Option<FilterHandle>,
+}
+
+impl LogFilters {
+    pub fn current(&self) -> Result<LogFilterDirectives, LogFilterError> {
+        let current = |handle: &FilterHandle| handle.with_current(ToString::to_string);
+        Ok(LogFilterDirectives {
+            stdout: Some(current(&self.stdout)?),
+            file: self.file.as_ref().map(current).transpose()?,
+        })
+    }
+
+    /// Replaces the filters of the logs with directives. Nothing is replaced if any of the
+    /// directives are invalid.
+    pub fn update(&self, directives: &LogFilterDirectives) -> Result<(), LogFilterError> {
+        let stdout = directives.stdout.as_deref().map(parse_filter).transpose()?;
+        let file = match (&directives.file, &self.file) {
+            (Some(directives), Some(handle)) => Some((handle, parse_filter(directives)?)),
+            (Some(_), None) => return Err(LogFilterError::NoLogFiles),
+            (None, _) => None,
+        };
+        if let Some(stdout) = stdout {
+            self.stdout.reload(stdout)?;
+        }
+        if let Some((handle, file)) = file {
+            handle.reload(file)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl LogFilters {
+    /// Filters of layers that are not part of a subscriber, to test changing the filters. The
+    /// filters can only be changed while the returned layers are alive.
+    pub fn detached(stdout: &str, file: Option<&str>) -> (Self, Vec<BoxedLayer>) {
+        let layer = || file_layer(LogFormat::Text, std::io::sink);
+        let (stdout_layer, stdout) = reloadable(layer(), stdout).unwrap();
+        let (log_layer, file) = file.map(|file| reloadable(layer(), file).unwrap()).unzip();
+        let layers = [stdout_layer].into_iter().chain(log_layer).collect();
+        (Self { stdout, file }, layers)
+    }
+}
+
+/// Parses filter directives in the syntax of `RUST_LOG`, e.g. `warn,habi2ca=debug`.
+pub fn parse_filter(directives: &str) -> Result<EnvFilter, LogFilterError> {
+    EnvFilter::builder()
+        .parse(directives)
+        .map_err(|source| LogFilterError::Invalid {
+            directives: directives.to_owned(),
+            source,
+        })
+}
+
+/// Filters `layer` with `directives`, and returns a handle to replace the filter later.
+fn reloadable(
+    layer: BoxedLayer,
+    directives: &str,
+) -> Result<(BoxedLayer, FilterHandle), LogFilterError> {
+    let (filter, handle) = reload::Layer::new(parse_filter(directives)?);
+    Ok((layer.with_filter(filter).boxed(), handle))
+}
+
+fn file_layer<W>(format: LogFormat, writer: W) -> BoxedLayer
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = fmt::Layer::new().with_writer(writer).with_ansi(false);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+fn stdout_layer<W>(format: LogFormat, writer: W) -> BoxedLayer
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = fmt::Layer::new().with_writer(writer);
+    match format {
+        LogFormat::Text => layer.with_target(false).without_time().boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+fn audit_filter() -> filter::Targets {
+    filter::Targets::new().with_target(AUDIT_TARGET, Level::INFO)
+}
+
+/// Layers logging to `writer` with a filter that can be replaced later. Without an audit log, audit
+/// events are logged regardless of the filter, so that they cannot be filtered out.
+fn stdout_layers<W>(
+    format: LogFormat,
+    writer: W,
+    directives: &str,
+    audit_log: bool,
+) -> Result<(Vec<BoxedLayer>, FilterHandle), LogFilterError>
+where
+    W: for<'writer> MakeWriter<'writer> + Clone + Send + Sync + 'static,
+{
+    if audit_log {
+        let (layer, handle) = reloadable(stdout_layer(format, writer), directives)?;
+        return Ok((vec![layer], handle));
+    }
+    let layer = stdout_layer(format, writer.clone())
+        .with_filter(filter::filter_fn(|metadata| {
+            metadata.target() != AUDIT_TARGET
+        }))
+        .boxed();
+    let (layer, handle) = reloadable(layer, directives)?;
+    let audit_layer = stdout_layer(format, writer).with_filter(audit_filter());
+    Ok((vec![layer, audit_layer.boxed()], handle))
+}
+
+/// Keeps logs and traces flowing until dropped, at which point buffered ones are flushed.
+pub struct TracingGuard {
+    _log_guard: Option<WorkerGuard>,
+    tracer_provider: Option<TracerProvider>,
+    log_filters: LogFilters,
+}
+
+impl TracingGuard {
+    pub fn log_filters(&self) -> LogFilters {
+        self.log_filters.clone()
+    }
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(tracer_provider) = &self.tracer_provider {
+            if let Err(error) = tracer_provider.shutdown() {
+                eprintln!("Failed to flush traces: {error}");
+            }
+        }
+    }
+}
+
+fn tracer_provider(
+    builder: opentelemetry_sdk::trace::Builder,
+    service_name: &str,
+    sample_ratio: f64,
+) -> TracerProvider {
+    builder
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            sample_ratio,
+        ))))
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service_name.to_owned(),
+        )]))
+        .build()
+}
+
+/// Layer exporting spans to the tracer provider, and making the trace context of incoming
+/// requests available to `tracing-actix-web`.
+fn otel_layer<S>(tracer_provider: &TracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    tracing_opentelemetry::layer()
+        .with_tracer(tracer_provider.tracer("habi2ca-server"))
         .with_filter(
             filter::Targets::new()
                 .with_default(Level::INFO)
                 .with_targets([("habi2ca", Level::DEBUG)]),
-        );
+        )
+}
 
-    let stdout_layer = fmt::Layer::new()
-        .with_writer(io::stdout)
-        .with_target(false)
-        .without_time()
-        .with_filter(
-            filter::Targets::new()
-                .with_default(Level::WARN)
-                .with_targets([("habi2ca", Level::INFO)]),
-        );
+/// Logs to stdout, and to files in the log directory and traces to an OTLP collector if
+/// configured. Audit events go to stdout if there is no log directory.
+pub fn setup_tracing(log: &LogConfig, otlp: Option<&OtlpConfig>) -> Result<TracingGuard> {
+    let (mut layers, stdout_filter) =
+        stdout_layers(log.format, std::io::stdout, &log.filter, log.dir.is_some())?;
+
+    let (file_filter, log_guard) = match &log.dir {
+        Some(log_dir) => {
+            let appender = RollingFileAppender::builder()
+                .rotation(log.rotation.into())
+                .filename_prefix("debug");
+            let appender = match log.max_files {
+                Some(max_files) => appender.max_log_files(max_files),
+                None => appender,
+            };
+            let appender = appender
+                .build(log_dir)
+                .context("Failed to create log file")?;
+            let (log_file, guard) = tracing_appender::non_blocking(appender);
+            let (log_layer, file_filter) =
+                reloadable(file_layer(log.format, log_file), &log.file_filter)?;
+            layers.push(log_layer);
 
-    let subscriber = tracing_subscriber::registry()
-        .with(log_layer)
-        .with(stdout_layer);
+            // Audit events are rare, so they are written synchronously to never be lost.
+            let audit_layer = file_layer(log.format, rolling::never(log_dir, "audit.log"))
+                .with_filter(audit_filter());
+            layers.push(audit_layer.boxed());
+            (Some(file_filter), Some(guard))
+        }
+        None => (None, None),
+    };
 
-    subscriber::set_global_default(subscriber)
+    let tracer_provider = otlp
+        .map(|otlp| {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(&otlp.endpoint)
+                .build()
+                .context("Failed to create OTLP exporter")?;
+            let builder = TracerProvider::builder().with_batch_exporter(exporter, runtime::Tokio);
+            anyhow::Ok(tracer_provider(
+                builder,
+                &otlp.service_name,
+                otlp.sample_ratio,
+            ))
+        })
+        .transpose()?;
+    if let Some(tracer_provider) = &tracer_provider {
+        layers.push(otel_layer(tracer_provider).boxed());
+    }
+
+    subscriber::set_global_default(tracing_subscriber::registry().with(layers))
         .context("Failed to set global default tracing subscriber")?;
 
-    Ok(guard)
+    Ok(TracingGuard {
+        _log_guard: log_guard,
+        tracer_provider,
+        log_filters: LogFilters {
+            stdout: stdout_filter,
+            file: file_filter,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use actix_web::test::{self as actix_test, TestRequest};
+    use opentelemetry::trace::{SpanId, TraceId};
+    use opentelemetry_sdk::{testing::trace::InMemorySpanExporter, trace::TracerProvider};
+    use tokio::time;
+    use tracing::info;
+    use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt};
+
+    use super::{
+        otel_layer, parse_filter, stdout_layers, tracer_provider, LogFormat, AUDIT_TARGET,
+    };
+    use crate::{
+        logic::{
+            player::Player,
+            task::{Task, TaskData},
+        },
+        start::create_app,
+        test_utils,
+    };
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const PARENT_ID: &str = "00f067aa0ba902b7";
+
+    /// Collects the logs written to it.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        fn lines(&self) -> Vec<String> {
+            let bytes = self.0.lock().unwrap();
+            String::from_utf8_lossy(&bytes)
+                .lines()
+                .map(str::to_owned)
+                .collect()
+        }
+    }
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl MakeWriter<'_> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn audit_events_reach_stdout_without_audit_log() {
+        let buffer = Buffer::default();
+        let (layers, filter) =
+            stdout_layers(LogFormat::Text, buffer.clone(), "warn,habi2ca=info", false).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layers);
+        tracing::subscriber::with_default(subscriber, || {
+            info!(target: AUDIT_TARGET, "First audit event.");
+            info!(target: "other", "Filtered out.");
+            filter.reload(parse_filter("off").unwrap()).unwrap();
+            info!(target: AUDIT_TARGET, "Second audit event.");
+        });
+
+        let lines = buffer.lines();
+        assert_eq!(lines.len(), 2, "{lines:?}");
+        assert!(lines[0].ends_with("First audit event."));
+        assert!(lines[1].ends_with("Second audit event."));
+    }
+
+    #[test]
+    fn audit_events_follow_stdout_filter_with_audit_log() {
+        let buffer = Buffer::default();
+        let (layers, _filter) =
+            stdout_layers(LogFormat::Text, buffer.clone(), "warn,habi2ca=info", true).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layers);
+        tracing::subscriber::with_default(subscriber, || {
+            info!(target: AUDIT_TARGET, "Audit event.");
+        });
+        assert!(buffer.lines().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn exports_request_spans() {
+        let database = test_utils::setup_database().await;
+        let player = Player::create(&database, test_utils::TEST_ACCOUNT, "Alice")
+            .await
+            .unwrap();
+        let task = Task::create(
+            &database,
+            TaskData {
+                player_id: player.id(),
+                name: "Task".to_string(),
+                description: "Description".to_string(),
+                completed: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let exporter = InMemorySpanExporter::default();
+        let tracer_provider = tracer_provider(
+            TracerProvider::builder().with_simple_exporter(exporter.clone()),
+            "habi2ca-test",
+            // Only the sampled trace below is exported, and not spans of other tests.
+            0.0,
+        );
+        // The subscriber must be global, since the database driver enters spans on its own
+        // threads, which do not see a thread local subscriber.
+        let subscriber = tracing_subscriber::registry().with(otel_layer(&tracer_provider));
+        tracing::subscriber::set_global_default(subscriber).unwrap();
+
+        let app = actix_test::init_service(create_app(database)).await;
+        test_utils::assert_ok_response::<_, _, _, serde_json::Value>(
+            &app,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}/complete", task.id()))
+                .insert_header(("traceparent", format!("00-{TRACE_ID}-{PARENT_ID}-01")))
+                .to_request(),
+        )
+        .await;
+
+        // The database driver may close spans on its own thread shortly after the response is
+        // sent. The request span is closed last, since it contains the others.
+        let parent_id = SpanId::from_hex(PARENT_ID).unwrap();
+        let spans = time::timeout(Duration::from_secs(5), async {
+            loop {
+                let spans = exporter.get_finished_spans().unwrap();
+                if spans.iter().any(|span| span.parent_span_id == parent_id) {
+                    return spans;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("No span continues the incoming trace.");
+        let trace_id = TraceId::from_hex(TRACE_ID).unwrap();
+        let request_span = spans
+            .iter()
+            .find(|span| span.parent_span_id == parent_id)
+            .unwrap();
+        assert_eq!(request_span.span_context.trace_id(), trace_id);
+
+        let add_xp_span = spans
+            .iter()
+            .find(|span| span.name == "add_xp")
+            .expect("No span for adding xp.");
+        assert_eq!(add_xp_span.span_context.trace_id(), trace_id);
+        let transaction_span = spans
+            .iter()
+            .find(|span| span.span_context.span_id() == add_xp_span.parent_span_id)
+            .expect("The add_xp span has no parent.");
+        assert_eq!(transaction_span.name, "transaction");
+    }
 }